@@ -1,42 +1,615 @@
+use crate::chunk::CrcProfile;
+use crate::i18n::{self, Lang};
+use crate::limits::{self, Limits};
+use crate::platform::Newline;
+use crate::text::Unmappable;
+use crate::transform::Compression;
+use clap::{Command, CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::env;
+use std::ffi::OsString;
 use std::path::PathBuf;
-use clap::{Parser, Subcommand};
+use std::str::FromStr;
+use std::time::Duration;
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 pub struct Cli {
+    #[clap(flatten)]
+    pub global: GlobalArgs,
     #[clap(subcommand)]
     pub command: PngMeArgs,
 }
 
+impl Cli {
+    /// Like [`Parser::parse`], but with the help text in the language
+    /// `--lang` or the locale asks for.
+    pub fn parse_localized() -> Cli {
+        let args: Vec<OsString> = env::args_os().collect();
+        let lang = Lang::detect(lang_arg(&args));
+        let matches = localize(Cli::command(), lang).get_matches_from(args);
+        Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+    }
+}
+
+/// The value of `--lang` in `args`, read before clap parses them so that
+/// the help clap prints is already translated. Invalid values are left for
+/// clap to report.
+fn lang_arg(args: &[OsString]) -> Option<Lang> {
+    let mut args = args
+        .iter()
+        .map(|arg| arg.to_str())
+        .take_while(|&arg| arg != Some("--"));
+    while let Some(arg) = args.next() {
+        let value = match arg {
+            Some("--lang") => args.next().flatten(),
+            Some(arg) => arg.strip_prefix("--lang="),
+            None => None,
+        };
+        if let Some(lang) = value.and_then(|value| value.parse().ok()) {
+            return Some(lang);
+        }
+    }
+    None
+}
+
+/// `command` with the about and help text of itself, its arguments and its
+/// subcommands translated into `lang` from [`i18n::HELP`].
+pub fn localize(mut command: Command<'static>, lang: Lang) -> Command<'static> {
+    if lang == Lang::En {
+        return command;
+    }
+    if let Some(about) = command.get_about() {
+        command = command.about(i18n::help(about, lang));
+    }
+    if let Some(about) = command.get_long_about() {
+        command = command.long_about(i18n::help(about, lang));
+    }
+    // mut_arg and mut_subcommand move what they change to the end, so
+    // changing every one in turn keeps the order. The --version flag clap
+    // generates is global and subcommands have no version: a changed copy
+    // would be pushed down into them, where clap rejects it.
+    let ids: Vec<&str> = command
+        .get_arguments()
+        .map(|arg| arg.get_id())
+        .filter(|&id| id != "version" || command.get_version().is_some())
+        .collect();
+    for id in ids {
+        command = command.mut_arg(id, |arg| {
+            let help = arg.get_help().map(|text| i18n::help(text, lang));
+            let long_help = arg.get_long_help().map(|text| i18n::help(text, lang));
+            let global = arg.is_global_set() && id != "version";
+            arg.help(help).long_help(long_help).global(global)
+        });
+    }
+    let names: Vec<String> = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect();
+    for name in &names {
+        command = command.mut_subcommand(name.as_str(), |subcommand| localize(subcommand, lang));
+    }
+    command
+}
+
+/// Options shared by every subcommand.
+#[derive(Parser)]
+pub struct GlobalArgs {
+    /// Size in bytes of the read and write buffers used for file I/O
+    #[clap(long, global = true, default_value_t = 64 * 1024)]
+    pub io_buffer: usize,
+    /// Fail instead of continuing when a warning is reported
+    #[clap(long, global = true)]
+    pub warnings_as_errors: bool,
+    /// Print a diff of the chunk list a command would write instead of writing
+    /// it
+    #[clap(long, global = true)]
+    pub show_result: bool,
+    /// How to check chunk CRCs when reading: standard, none, or a 0x-prefixed
+    /// CRC-32 polynomial used by a non-standard tool. Files are always written
+    /// with standard CRCs
+    #[clap(
+        long,
+        global = true,
+        value_name = "PROFILE",
+        default_value = "standard"
+    )]
+    pub crc_profile: CrcProfile,
+    /// Print how long parsing, CRCs, compression and writing took to stderr;
+    /// needs a build with the perf-metrics feature
+    #[clap(long, global = true)]
+    pub timings: bool,
+    /// Language for error messages and help text: en or zh [default: from
+    /// LC_ALL, LC_MESSAGES or LANG]. Error codes are the same in every
+    /// language
+    #[clap(long, global = true, value_name = "LANG")]
+    pub lang: Option<Lang>,
+    /// Report errors as text, or as one JSON object per error on stderr with
+    /// code, message, file, offset and chunk_type fields
+    #[clap(long, global = true, value_name = "FORMAT", default_value = "text")]
+    pub format: Format,
+    /// Refuse to run commands that would write or replace files, for
+    /// inspection-only use [default: set when $PNGME_READ_ONLY is non-empty
+    /// and not 0]
+    #[clap(long, global = true)]
+    pub read_only: bool,
+    /// Refuse input files larger than this many bytes
+    #[clap(long, global = true, value_name = "BYTES")]
+    pub max_file_size: Option<u64>,
+    /// Refuse input files with more than this many chunks
+    #[clap(long, global = true, value_name = "N")]
+    pub max_chunks: Option<usize>,
+    /// Give up parsing an input file after this many seconds
+    #[clap(long, global = true, value_name = "SECS", value_parser = limits::parse_seconds)]
+    pub timeout: Option<Duration>,
+    /// Keep chunk indexes in this directory so that scan and extract on an
+    /// unchanged file skip parsing it; with --read-only, indexes already
+    /// there are used but none are added [default: $PNGME_INDEX_CACHE]
+    #[clap(long, global = true, value_name = "DIR")]
+    pub index_cache: Option<PathBuf>,
+}
+
+/// Setting this to anything but an empty string or `0` turns on
+/// `--read-only`.
+pub const READ_ONLY_ENV: &str = "PNGME_READ_ONLY";
+
+/// Directory for `--index-cache` when the flag is not given.
+pub const INDEX_CACHE_ENV: &str = "PNGME_INDEX_CACHE";
+
+impl GlobalArgs {
+    pub fn limits(&self) -> Limits {
+        Limits {
+            max_file_size: self.max_file_size,
+            max_chunks: self.max_chunks,
+            timeout: self.timeout,
+        }
+    }
+
+    /// Whether `--read-only` is on, by flag or by `PNGME_READ_ONLY`.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+            || std::env::var_os(READ_ONLY_ENV)
+                .is_some_and(|value| !value.is_empty() && value != "0")
+    }
+
+    /// The `--index-cache` directory, by flag or by `PNGME_INDEX_CACHE`.
+    pub fn index_cache(&self) -> Option<PathBuf> {
+        self.index_cache.clone().or_else(|| {
+            std::env::var_os(INDEX_CACHE_ENV)
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("expected text or json, not {:?}", s)),
+        }
+    }
+}
+
 #[derive(Subcommand)]
 pub enum PngMeArgs {
     Encode(EncodeArgs),
     Decode(DecodeArgs),
     Remove(RemoveArgs),
+    Extract(ExtractArgs),
+    History(HistoryArgs),
+    Gc(GcArgs),
     Print(PrintArgs),
+    Scan(ScanArgs),
+    Verify(VerifyArgs),
+    Count(CountArgs),
+    Tag(TagArgs),
+    Lint(LintArgs),
+    Recover(RecoverArgs),
+    Repair(RepairArgs),
+    Doctor(DoctorArgs),
+    Replay(ReplayArgs),
+    Identify(IdentifyArgs),
+    #[clap(subcommand)]
+    Baseline(BaselineArgs),
+    #[clap(subcommand)]
+    Hook(HookArgs),
+}
+
+impl PngMeArgs {
+    /// The name of the command when, run with these arguments, it would
+    /// write or replace a file. With `--show-result`, PNGs are diffed
+    /// instead of written, so only commands writing other files count.
+    /// Every command is listed, so a new one has to say whether it writes;
+    /// the writes themselves check `--read-only` as well.
+    pub fn writes_files(&self, global: &GlobalArgs) -> Option<&'static str> {
+        let writes_png = !global.show_result;
+        match self {
+            PngMeArgs::Encode(_) => writes_png.then_some("encode"),
+            PngMeArgs::Remove(args) => (writes_png && !args.dry_run).then_some("remove"),
+            PngMeArgs::Extract(args) => args.output_file.is_some().then_some("extract"),
+            PngMeArgs::Gc(args) => (writes_png && !args.dry_run).then_some("gc"),
+            PngMeArgs::Tag(_) => writes_png.then_some("tag"),
+            PngMeArgs::Lint(args) => (writes_png && args.fix).then_some("lint --fix"),
+            PngMeArgs::Recover(args) => {
+                (writes_png && args.output_file.is_some()).then_some("recover")
+            }
+            PngMeArgs::Repair(args) => (writes_png && args.structure).then_some("repair"),
+            PngMeArgs::Baseline(BaselineArgs::Create(_)) => Some("baseline create"),
+            PngMeArgs::Hook(HookArgs::PreCommit(_)) => writes_png.then_some("hook pre-commit"),
+            PngMeArgs::Decode(_)
+            | PngMeArgs::History(_)
+            | PngMeArgs::Print(_)
+            | PngMeArgs::Scan(_)
+            | PngMeArgs::Verify(_)
+            | PngMeArgs::Count(_)
+            | PngMeArgs::Doctor(_)
+            | PngMeArgs::Replay(_)
+            | PngMeArgs::Identify(_)
+            | PngMeArgs::Baseline(BaselineArgs::Check(_)) => None,
+        }
+    }
 }
 
 #[derive(Parser)]
 pub struct EncodeArgs {
     pub file_path: PathBuf,
-    pub chunk_type: String,
-    pub message: String,
+    #[clap(required_unless_present = "chunks", conflicts_with = "chunks")]
+    pub chunk_type: Option<String>,
+    #[clap(required_unless_present = "chunks")]
+    pub message: Option<String>,
     pub output_file: Option<PathBuf>,
+    /// Add a chunk from TYPE=MESSAGE, or TYPE@FILE to read the message from a
+    /// file; repeat to add several chunks in one write
+    #[clap(long = "chunk", value_name = "SPEC")]
+    pub chunks: Vec<String>,
+    /// File to write instead of modifying FILE_PATH, for use with --chunk
+    #[clap(short, long, value_name = "FILE", conflicts_with = "output-file")]
+    pub output: Option<PathBuf>,
+    /// Spread the payload over copies of FILE_PATH named by this template, with
+    /// %d replaced by the stripe number, e.g. out%d.png
+    #[clap(
+        long,
+        value_name = "TEMPLATE",
+        conflicts_with_all = &["chunks", "output", "output-file"]
+    )]
+    pub stripe: Option<String>,
+    /// Largest number of payload bytes per stripe
+    #[clap(long, value_name = "BYTES", default_value_t = 64 * 1024, requires = "stripe")]
+    pub stripe_size: usize,
+    /// Expand {variables} in the message: {date}, {timestamp}, {user}, {git_sha},
+    /// {git_branch}, {env:NAME}, {sha256:PATH}
+    #[clap(long)]
+    pub template: bool,
+    /// Transform the message before storing it (compress, base64); repeat to chain
+    #[clap(short, long = "transform")]
+    pub transforms: Vec<String>,
+    /// Compress the message before any --transform stages: none, deflate, or
+    /// auto to keep whichever of the two gives the smaller chunk
+    #[clap(long, value_name = "MODE", default_value = "none")]
+    pub compress: Compression,
+    /// Preset dictionary file for the compress transform
+    #[clap(long)]
+    pub dict: Option<PathBuf>,
+    /// Drop unknown unsafe-to-copy chunks when critical data is modified
+    #[clap(long)]
+    pub drop_unsafe: bool,
+    /// Refuse to store the message unless it is JSON matching this JSON Schema
+    #[clap(long, value_name = "FILE")]
+    pub schema: Option<PathBuf>,
+    /// Timestamp the payload so earlier payloads of the same type stay readable
+    /// as history; decode then reads the newest one
+    #[clap(long)]
+    pub versioned: bool,
+    /// Line endings to store text messages with: lf, crlf or preserve
+    #[clap(long, value_name = "STYLE", default_value = "preserve")]
+    pub newline: Newline,
+    /// Warn when ancillary chunks would make up more than this percentage of
+    /// the written file
+    #[clap(long, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub max_metadata_percent: Option<u8>,
 }
 
 #[derive(Parser)]
 pub struct DecodeArgs {
     pub file_path: PathBuf,
     pub chunk_type: String,
+    /// Treat FILE_PATH as a file name pattern such as 'out*.png' and reassemble
+    /// a payload striped across the matching files
+    #[clap(long, conflicts_with = "exists")]
+    pub gather: bool,
+    /// Read the newest versioned payload stored no later than this date
+    /// (YYYY-MM-DD, meaning the end of that day, or YYYY-MM-DDTHH:MM:SSZ)
+    #[clap(long, value_name = "DATE", conflicts_with = "gather")]
+    pub at: Option<String>,
+    /// Preset dictionary file the payload was compressed with
+    #[clap(long)]
+    pub dict: Option<PathBuf>,
+    /// Print nothing; exit with 0 if the chunk exists and 1 otherwise, or with
+    /// 2 if the file cannot be read
+    #[clap(long)]
+    pub exists: bool,
+    /// Read a truncated file up to the last complete chunk
+    #[clap(long)]
+    pub permissive: bool,
+    /// Refuse to print the payload unless it is JSON matching this JSON Schema
+    #[clap(long, value_name = "FILE")]
+    pub schema: Option<PathBuf>,
+    /// Print only the values a jq-style path selects from a JSON payload, e.g.
+    /// '.version' or '.targets[] | select(.size > 100) | .name'; strings are
+    /// printed without quotes
+    #[clap(long, value_name = "EXPR")]
+    pub query: Option<String>,
+    /// Print the payload as stored even on a terminal, instead of indenting and
+    /// highlighting JSON and XML and summarizing binary data
+    #[clap(long)]
+    pub no_pretty: bool,
+    /// Line endings to print text payloads with: lf, crlf or preserve
+    #[clap(long, value_name = "STYLE", default_value = "preserve")]
+    pub newline: Newline,
 }
 
 #[derive(Parser)]
 pub struct RemoveArgs {
     pub file_path: PathBuf,
-    pub chunk_type: String,
+    /// Remove the first chunk of this type; with --matching, only consider
+    /// chunks of this type
+    #[clap(required_unless_present_any = &["offset", "matching"])]
+    pub chunk_type: Option<String>,
+    /// Remove the chunk starting at this file offset, as listed by scan (decimal
+    /// or 0x-prefixed hex)
+    #[clap(long, value_parser = parse_offset, conflicts_with_all = &["chunk-type", "matching"])]
+    pub offset: Option<usize>,
+    /// Remove every ancillary chunk whose decoded text matches this regular
+    /// expression; for text chunks the keyword or the text must match
+    #[clap(long, value_name = "REGEX")]
+    pub matching: Option<String>,
+    /// List the chunks --matching would remove without removing them
+    #[clap(long, requires = "matching")]
+    pub dry_run: bool,
+    /// Drop unknown unsafe-to-copy chunks when critical data is modified
+    #[clap(long)]
+    pub drop_unsafe: bool,
+}
+
+#[derive(Parser)]
+pub struct ExtractArgs {
+    pub file_path: PathBuf,
+    /// File to write the chunk data to [default: stdout]
+    pub output_file: Option<PathBuf>,
+    /// Extract the chunk starting at this file offset, as listed by scan
+    /// (decimal or 0x-prefixed hex)
+    #[clap(long, value_parser = parse_offset, required = true)]
+    pub offset: usize,
+}
+
+fn parse_offset(s: &str) -> Result<usize, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| format!("{:?} is not a decimal or 0x-prefixed hex offset", s))
 }
 
 #[derive(Parser)]
 pub struct PrintArgs {
     pub file_path: PathBuf,
+    #[clap(flatten)]
+    pub output: PorcelainArgs,
+}
+
+#[derive(Parser)]
+pub struct ScanArgs {
+    pub file_path: PathBuf,
+    /// Read a truncated file up to the last complete chunk
+    #[clap(long)]
+    pub permissive: bool,
+    /// Only list private chunks in this org's namespace (a registered org name or
+    /// a two-letter prefix)
+    #[clap(long, value_name = "ORG")]
+    pub owned_by: Option<String>,
+    /// Org registry file mapping org names to prefixes [default: $PNGME_ORGS]
+    #[clap(long, value_name = "FILE")]
+    pub orgs: Option<PathBuf>,
+    /// Print the chunk layout as a Graphviz graph, with runs of chunks of one
+    /// type drawn as a single box
+    #[clap(long, conflicts_with_all = &["porcelain", "mermaid"])]
+    pub dot: bool,
+    /// Print the chunk layout as a Mermaid flowchart, like --dot
+    #[clap(long, conflicts_with = "porcelain")]
+    pub mermaid: bool,
+    #[clap(flatten)]
+    pub output: PorcelainArgs,
+}
+
+#[derive(Parser)]
+pub struct GcArgs {
+    /// PNG files or file name patterns such as 'out*.png'. With the cloud
+    /// feature, s3:// and gs:// objects, patterns and prefixes ending in '/'
+    /// work the same way
+    #[clap(required = true)]
+    pub paths: Vec<PathBuf>,
+    /// Chunk type holding a segmented payload; repeat for several types
+    #[clap(long = "segmented", value_name = "TYPE")]
+    pub segmented: Vec<String>,
+    /// Remove all but the newest N versions of each versioned payload
+    #[clap(long, value_name = "N")]
+    pub keep_versions: Option<usize>,
+    /// Also remove stripes whose payload none of the files complete. Only
+    /// pass it when every file holding stripes of those payloads is listed:
+    /// stripes whose siblings are merely not named are otherwise lost
+    #[clap(long)]
+    pub incomplete_stripes: bool,
+    /// Report what would be removed without changing any file
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Parser)]
+pub struct HistoryArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: String,
+}
+
+#[derive(Parser)]
+pub struct VerifyArgs {
+    /// A PNG file or a directory of PNG files; with the cloud feature, also
+    /// an s3:// or gs:// object, prefix ending in '/' or name pattern
+    pub path: PathBuf,
+    /// Descend into subdirectories
+    #[clap(short, long)]
+    pub recursive: bool,
+    /// Number of worker threads (defaults to the available parallelism)
+    #[clap(short, long)]
+    pub jobs: Option<usize>,
+    /// Fail unless the file's SHA-256 is this hex digest
+    #[clap(long, value_name = "HASH")]
+    pub expect_sha256: Option<String>,
+    /// Fail unless the decoded payload's SHA-256 is this hex digest
+    #[clap(long, value_name = "HASH", requires = "payload-type")]
+    pub expect_payload_sha256: Option<String>,
+    /// Chunk type holding the payload checked by --expect-payload-sha256
+    #[clap(long, value_name = "TYPE")]
+    pub payload_type: Option<String>,
+    /// Preset dictionary file the payload was compressed with
+    #[clap(long, requires = "expect-payload-sha256")]
+    pub dict: Option<PathBuf>,
+    /// For each issue, print the spec rule, why it matters and how to fix it
+    #[clap(long)]
+    pub explain: bool,
+    /// Warn about files whose ancillary chunks make up more than this
+    /// percentage of the file
+    #[clap(long, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub max_metadata_percent: Option<u8>,
+}
+
+#[derive(Parser)]
+pub struct CountArgs {
+    pub file_path: PathBuf,
+    /// Only count chunks of this type
+    pub chunk_type: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct TagArgs {
+    pub file_path: PathBuf,
+    pub output_file: Option<PathBuf>,
+    /// Record git commit, branch, build timestamp and CI job URL as tEXt chunks
+    #[clap(long, required = true)]
+    pub from_git: bool,
+    /// Write UTF-8 iTXt chunks instead of Latin-1 tEXt chunks
+    #[clap(long)]
+    pub international: bool,
+    /// What to do with characters Latin-1 cannot hold: error, transliterate or replace
+    #[clap(long, default_value_t, conflicts_with = "international")]
+    pub unmappable: Unmappable,
+}
+
+#[derive(Parser)]
+pub struct LintArgs {
+    pub file_path: PathBuf,
+    pub output_file: Option<PathBuf>,
+    /// Reorder misplaced ancillary chunks and drop conflicting ones
+    #[clap(long)]
+    pub fix: bool,
+    /// For each issue, print the spec rule, why it matters and how to fix it
+    #[clap(long)]
+    pub explain: bool,
+    /// Also report the file when its ancillary chunks make up more than this
+    /// percentage of it; --fix leaves such chunks alone
+    #[clap(long, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub max_metadata_percent: Option<u8>,
+}
+
+#[derive(Parser)]
+pub struct RecoverArgs {
+    /// A damaged PNG, e.g. one with a corrupted chunk length
+    pub file_path: PathBuf,
+    /// Write the surviving chunks to this file as a well-formed PNG
+    pub output_file: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+pub struct RepairArgs {
+    pub file_path: PathBuf,
+    pub output_file: Option<PathBuf>,
+    /// Add a missing signature or IEND and drop incomplete chunks and data after IEND
+    #[clap(long, required = true)]
+    pub structure: bool,
+}
+
+/// Run every check on a file and list the problems found, most severe first,
+/// with a command to fix each where pngme has one
+#[derive(Parser)]
+pub struct DoctorArgs {
+    pub file_path: PathBuf,
+}
+
+/// Run the parser over any input, such as a fuzzer's crash file, printing
+/// each chunk it reads and where and why it stops
+#[derive(Parser)]
+pub struct ReplayArgs {
+    pub file_path: PathBuf,
+}
+
+/// Tell which image container a file is in, how sure that is, and which
+/// pngme commands apply to it
+#[derive(Parser)]
+pub struct IdentifyArgs {
+    pub file_path: PathBuf,
+}
+
+/// Run as a git hook
+#[derive(Subcommand)]
+pub enum HookArgs {
+    /// Apply the repository's chunk policy to staged PNG files: strip chunks
+    /// it lists and restage the files, or block the commit when a file holds
+    /// chunks it does not allow. Install by putting `pngme hook pre-commit`
+    /// in .git/hooks/pre-commit
+    PreCommit(PreCommitArgs),
+}
+
+#[derive(Parser)]
+pub struct PreCommitArgs {
+    /// Policy file [default: .pngme-policy.json at the repository root]
+    #[clap(long, value_name = "FILE")]
+    pub policy: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+pub enum BaselineArgs {
+    /// Record the chunk hashes of every PNG under a directory
+    Create(BaselineFileArgs),
+    /// Report PNGs whose ancillary chunks changed since the baseline
+    Check(BaselineFileArgs),
+}
+
+#[derive(Parser)]
+pub struct BaselineFileArgs {
+    pub dir: PathBuf,
+    pub baseline_file: PathBuf,
+}
+
+#[derive(Parser)]
+pub struct PorcelainArgs {
+    /// Print stable, script-friendly output instead of the pretty layout
+    #[clap(
+        long,
+        long_help = "Print one tab-separated record per chunk instead of the pretty layout:\n\n    \
+                     chunk <index> <offset> <type> <length> <crc> <category>\n\n\
+                     <index> counts from 0, <offset> is the byte offset of the chunk's length field, \
+                     <crc> is 8 lowercase hex digits and <category> is critical, ancillary or private. \
+                     This format is frozen: new information will only ever be added as new record \
+                     kinds or trailing fields."
+    )]
+    pub porcelain: bool,
 }
@@ -0,0 +1,257 @@
+//! Per-file chunk fingerprints, used as a tripwire for metadata changes.
+
+use crate::chunk_type::ChunkType;
+use crate::json::Value;
+use crate::png::Png;
+use crate::{hash, Result};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Baseline {
+    /// Chunk type and SHA-256 of type + data for every chunk, by file path.
+    files: BTreeMap<String, Vec<(String, String)>>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change {
+    Added(String),
+    Removed(String),
+    /// The ancillary chunks of a file differ; lists the chunk types that
+    /// appeared and disappeared.
+    Modified {
+        path: String,
+        added: Vec<String>,
+        removed: Vec<String>,
+    },
+}
+
+impl Baseline {
+    const VERSION: f64 = 1.0;
+
+    pub fn add(&mut self, path: &str, png: &Png) {
+        let chunks = png
+            .chunks()
+            .iter()
+            .map(|chunk| {
+                let bytes = chunk.as_bytes();
+                let type_and_data = &bytes[4..bytes.len() - 4];
+                (
+                    chunk.chunk_type().to_string(),
+                    hash::to_hex(&hash::sha256(type_and_data)),
+                )
+            })
+            .collect();
+        self.files.insert(path.to_string(), chunks);
+    }
+
+    /// Compares `current` against this baseline. Only ancillary chunks are
+    /// considered: pixel data and structure are out of scope for the tripwire.
+    pub fn check(&self, current: &Baseline) -> Vec<Change> {
+        let mut changes = vec![];
+
+        for (path, recorded) in &self.files {
+            let Some(chunks) = current.files.get(path) else {
+                changes.push(Change::Removed(path.clone()));
+                continue;
+            };
+
+            let recorded = ancillary(recorded);
+            let chunks = ancillary(chunks);
+            if recorded != chunks {
+                changes.push(Change::Modified {
+                    path: path.clone(),
+                    added: difference(&chunks, &recorded),
+                    removed: difference(&recorded, &chunks),
+                });
+            }
+        }
+
+        for path in current.files.keys() {
+            if !self.files.contains_key(path) {
+                changes.push(Change::Added(path.clone()));
+            }
+        }
+
+        changes
+    }
+
+    pub fn to_json(&self) -> Value {
+        let files = self
+            .files
+            .iter()
+            .map(|(path, chunks)| {
+                let chunks = chunks
+                    .iter()
+                    .map(|(chunk_type, sha256)| {
+                        Value::Object(vec![
+                            ("type".to_string(), chunk_type.as_str().into()),
+                            ("sha256".to_string(), sha256.as_str().into()),
+                        ])
+                    })
+                    .collect();
+                (path.clone(), Value::Array(chunks))
+            })
+            .collect();
+
+        Value::Object(vec![
+            ("version".to_string(), Baseline::VERSION.into()),
+            ("files".to_string(), Value::Object(files)),
+        ])
+    }
+
+    pub fn from_json(value: &Value) -> Result<Baseline> {
+        if value.get("version").and_then(Value::as_f64) != Some(Baseline::VERSION) {
+            return Err("unsupported baseline version")?;
+        }
+        let invalid = || "invalid baseline file";
+
+        let mut files = BTreeMap::new();
+        for (path, chunks) in value
+            .get("files")
+            .and_then(Value::as_object)
+            .ok_or_else(invalid)?
+        {
+            let chunks = chunks
+                .as_array()
+                .ok_or_else(invalid)?
+                .iter()
+                .map(|chunk| {
+                    let field = |name| chunk.get(name).and_then(Value::as_str).map(str::to_string);
+                    field("type").zip(field("sha256")).ok_or_else(invalid)
+                })
+                .collect::<std::result::Result<_, _>>()?;
+            files.insert(path.clone(), chunks);
+        }
+
+        Ok(Baseline { files })
+    }
+}
+
+fn ancillary(chunks: &[(String, String)]) -> Vec<&(String, String)> {
+    chunks
+        .iter()
+        .filter(|(chunk_type, _)| {
+            ChunkType::from_str(chunk_type).map_or(true, |chunk_type| !chunk_type.is_critical())
+        })
+        .collect()
+}
+
+/// Chunk types in `a` that `b` lacks, counting duplicates.
+fn difference(a: &[&(String, String)], b: &[&(String, String)]) -> Vec<String> {
+    let mut b = b.to_vec();
+    a.iter()
+        .filter(|chunk| match b.iter().position(|other| other == *chunk) {
+            Some(i) => {
+                b.remove(i);
+                false
+            }
+            None => true,
+        })
+        .map(|(chunk_type, _)| chunk_type.clone())
+        .collect()
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Added(path) => write!(f, "added\t{}", path),
+            Change::Removed(path) => write!(f, "removed\t{}", path),
+            Change::Modified {
+                path,
+                added,
+                removed,
+            } => write!(
+                f,
+                "changed\t{}\t+[{}] -[{}]",
+                path,
+                added.join(" "),
+                removed.join(" ")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.into())
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", "header"),
+            chunk("tEXt", "Title\0dice"),
+            chunk("IDAT", "pixels"),
+            chunk("IEND", ""),
+        ])
+    }
+
+    #[test]
+    fn test_unchanged() {
+        let mut baseline = Baseline::default();
+        baseline.add("a.png", &testing_png());
+        let mut current = Baseline::default();
+        current.add("a.png", &testing_png());
+
+        assert!(baseline.check(&current).is_empty());
+    }
+
+    #[test]
+    fn test_changes() {
+        let mut baseline = Baseline::default();
+        baseline.add("a.png", &testing_png());
+        baseline.add("gone.png", &testing_png());
+
+        let mut modified = testing_png();
+        modified.remove_chunk("tEXt").unwrap();
+        modified.insert_before_iend(chunk("tEXt", "Title\0changed"));
+        modified.insert_before_iend(chunk("tIME", "now"));
+        let mut pixels_only = testing_png();
+        pixels_only.remove_chunk("IDAT").unwrap();
+
+        let mut current = Baseline::default();
+        current.add("a.png", &modified);
+        current.add("new.png", &pixels_only);
+
+        assert_eq!(
+            baseline.check(&current),
+            vec![
+                Change::Modified {
+                    path: "a.png".to_string(),
+                    added: vec!["tEXt".to_string(), "tIME".to_string()],
+                    removed: vec!["tEXt".to_string()],
+                },
+                Change::Removed("gone.png".to_string()),
+                Change::Added("new.png".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_critical_changes_are_ignored() {
+        let mut baseline = Baseline::default();
+        baseline.add("a.png", &testing_png());
+        let mut png = testing_png();
+        png.remove_chunk("IDAT").unwrap();
+        let mut current = Baseline::default();
+        current.add("a.png", &png);
+
+        assert!(baseline.check(&current).is_empty());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut baseline = Baseline::default();
+        baseline.add("a.png", &testing_png());
+        baseline.add("sub/b.png", &testing_png());
+
+        let json = Value::parse(&baseline.to_json().to_pretty_string()).unwrap();
+        assert_eq!(Baseline::from_json(&json).unwrap(), baseline);
+        assert!(Baseline::from_json(&Value::parse("{}").unwrap()).is_err());
+    }
+}
@@ -1,14 +1,19 @@
-use crate::chunk_type::{self, ChunkType};
+use crate::chunk_type::ChunkType;
+use crate::i18n::{self, CodedError};
+use crate::metrics::{self, Phase};
+use crate::view::ChunkRef;
 use crate::Error;
 use crc::Crc;
 use std::fmt::Display;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
 use std::string::FromUtf8Error;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Chunk(Vec<u8>);
 
 impl Chunk {
-    const calculator: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+    const CALCULATOR: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
 
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
         let data_length = data.len() as u32;
@@ -26,7 +31,11 @@ impl Chunk {
                 .iter()
                 .chain(chunk_type.to_string().as_bytes())
                 .chain(data.iter())
-                .chain(Self::calculator.checksum(&crc).to_be_bytes().iter())
+                .chain(
+                    metrics::time(Phase::Crc, || Self::CALCULATOR.checksum(&crc))
+                        .to_be_bytes()
+                        .iter(),
+                )
                 .copied()
                 .collect(),
         )
@@ -50,9 +59,19 @@ impl Chunk {
         &self.0[8..len]
     }
 
+    /// Streams the chunk data without copying it, e.g. into a decompressor.
+    pub fn data_reader(&self) -> impl Read + '_ {
+        self.data()
+    }
+
     pub fn crc(&self) -> u32 {
         let len = self.length() as usize + 8;
-        Self::calculator.checksum(&self.0[4..len])
+        Chunk::crc_of(&self.0[4..len])
+    }
+
+    /// The standard CRC of `bytes`, a chunk type followed by its data.
+    pub fn crc_of(bytes: &[u8]) -> u32 {
+        Self::CALCULATOR.checksum(bytes)
     }
 
     pub fn data_as_string(&self) -> Result<String, FromUtf8Error> {
@@ -62,6 +81,110 @@ impl Chunk {
     pub fn as_bytes(&self) -> Vec<u8> {
         self.0.to_vec()
     }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.0)
+    }
+
+    /// The chunk in `bytes`, which a `ChunkWalker` accepted with `profile`.
+    /// A non-standard CRC is replaced by the standard one.
+    pub(crate) fn from_accepted(bytes: Vec<u8>, profile: &CrcProfile) -> Chunk {
+        match profile {
+            CrcProfile::Standard => Chunk(bytes),
+            _ => ChunkRef::from_raw(&bytes).to_chunk(),
+        }
+    }
+
+    /// A borrowed view, for code written against `ChunkRef`.
+    pub fn view(&self) -> ChunkRef<'_> {
+        ChunkRef::from_raw(&self.0)
+    }
+}
+
+/// How the CRC field of chunks being read is checked. Some proprietary tools
+/// write non-standard checksums; reading with their profile lets pngme parse
+/// those files, and every chunk read gets a standard CRC when written back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CrcProfile {
+    /// CRC-32 as the PNG spec defines it.
+    #[default]
+    Standard,
+    /// Accept any CRC.
+    None,
+    /// CRC-32 with reflected input and output, initial value and final XOR
+    /// 0xffffffff, like the standard one, but another polynomial, given in
+    /// normal (MSB-first) form; the standard polynomial is 0x04c11db7.
+    Custom { polynomial: u32 },
+}
+
+impl CrcProfile {
+    /// Whether `crc` is the checksum of `bytes`, the chunk type and data.
+    pub fn accepts(&self, bytes: &[u8], crc: u32) -> bool {
+        metrics::time(Phase::Crc, || match self {
+            CrcProfile::Standard => Chunk::CALCULATOR.checksum(bytes) == crc,
+            CrcProfile::None => true,
+            CrcProfile::Custom { polynomial } => reflected_crc32(*polynomial, bytes) == crc,
+        })
+    }
+}
+
+/// Bitwise CRC-32 with a reflected polynomial; slow, but only used for
+/// files written by non-standard tools.
+fn reflected_crc32(polynomial: u32, bytes: &[u8]) -> u32 {
+    let reflected = polynomial.reverse_bits();
+    let mut crc = u32::MAX;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ reflected
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+impl FromStr for CrcProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "standard" => Ok(CrcProfile::Standard),
+            "none" => Ok(CrcProfile::None),
+            _ => s
+                .strip_prefix("0x")
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .map(|polynomial| CrcProfile::Custom { polynomial })
+                .ok_or_else(|| {
+                    format!(
+                        "expected standard, none or a 0x-prefixed CRC-32 polynomial, not {:?}",
+                        s
+                    )
+                }),
+        }
+    }
+}
+
+impl Chunk {
+    /// Parses a whole chunk, checking its CRC with `profile`. Chunks accepted
+    /// under another profile are rebuilt with a standard CRC.
+    pub fn from_bytes_with_crc(value: &[u8], profile: &CrcProfile) -> Result<Chunk, Error> {
+        if *profile == CrcProfile::Standard {
+            return Chunk::try_from(value);
+        }
+        let (remainder, crc) = value.split_at(value.len() - 4);
+        if !profile.accepts(&remainder[4..], u32::from_be_bytes(crc.try_into().unwrap())) {
+            Err(CodedError::new(&i18n::CHUNK_CRC, vec![]))?
+        }
+        let mut chunk_type = [0; 4];
+        chunk_type.copy_from_slice(&remainder[4..8]);
+        Ok(Chunk::new(
+            ChunkType::try_from(chunk_type)?,
+            remainder[8..].to_vec(),
+        ))
+    }
 }
 
 impl TryFrom<&[u8]> for Chunk {
@@ -72,10 +195,10 @@ impl TryFrom<&[u8]> for Chunk {
         let mut buffer = [0; 4];
         buffer.copy_from_slice(crc);
         let crc = u32::from_be_bytes(buffer);
-        if Self::calculator.checksum(&remainder[4..]) == crc {
-            Ok(Chunk(value.iter().copied().collect()))
+        if CrcProfile::Standard.accepts(&remainder[4..], crc) {
+            Ok(Chunk(value.to_vec()))
         } else {
-            Err("Invalid crc checks")?
+            Err(CodedError::new(&i18n::CHUNK_CRC, vec![]))?
         }
     }
 }
@@ -116,6 +239,28 @@ mod tests {
         Chunk::try_from(chunk_data.as_ref()).unwrap()
     }
 
+    #[test]
+    fn test_crc_profiles() {
+        let standard = testing_chunk().as_bytes();
+        let mut foreign = standard.clone();
+        let n = foreign.len();
+        foreign[n - 4..].copy_from_slice(&[0, 0, 0, 0]);
+
+        assert!(Chunk::from_bytes_with_crc(&foreign, &CrcProfile::Standard).is_err());
+        let rebuilt = Chunk::from_bytes_with_crc(&foreign, &CrcProfile::None).unwrap();
+        assert_eq!(rebuilt.as_bytes(), standard);
+
+        let same_as_standard: CrcProfile = "0x04c11db7".parse().unwrap();
+        assert!(Chunk::from_bytes_with_crc(&standard, &same_as_standard).is_ok());
+
+        // CRC-32C, as used by some storage pipelines.
+        let castagnoli: CrcProfile = "0x1edc6f41".parse().unwrap();
+        assert!(castagnoli.accepts(b"123456789", 0xe3069283));
+        assert!(Chunk::from_bytes_with_crc(&standard, &castagnoli).is_err());
+
+        assert!("crc16".parse::<CrcProfile>().is_err());
+    }
+
     #[test]
     fn test_new_chunk() {
         let chunk_type = ChunkType::from_str("RuSt").unwrap();
@@ -147,6 +292,14 @@ mod tests {
         assert_eq!(chunk_string, expected_chunk_string);
     }
 
+    #[test]
+    fn test_chunk_data_reader() {
+        let chunk = testing_chunk();
+        let mut data = String::new();
+        chunk.data_reader().read_to_string(&mut data).unwrap();
+        assert_eq!(data, "This is where your secret message will be!");
+    }
+
     #[test]
     fn test_chunk_crc() {
         let chunk = testing_chunk();
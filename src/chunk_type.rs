@@ -1,12 +1,20 @@
+use crate::i18n::{self, CodedError};
 use crate::Error;
 use std::{fmt::Display, str::FromStr};
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub struct ChunkType([u8; 4]);
 
 impl ChunkType {
     const BIT_5: u8 = 0x20;
 
+    /// Chunk types defined by the PNG specification and its registered extensions.
+    pub const KNOWN: &'static [&'static [u8; 4]] = &[
+        b"IHDR", b"PLTE", b"IDAT", b"IEND", b"cHRM", b"cICP", b"gAMA", b"iCCP", b"sBIT", b"sRGB",
+        b"bKGD", b"hIST", b"tRNS", b"eXIf", b"pHYs", b"sPLT", b"tIME", b"iTXt", b"tEXt", b"zTXt",
+        b"acTL", b"fcTL", b"fdAT",
+    ];
+
     pub fn bytes(&self) -> [u8; 4] {
         self.0
     }
@@ -30,6 +38,21 @@ impl ChunkType {
     pub fn is_valid(&self) -> bool {
         self.is_reserved_bit_valid()
     }
+
+    /// "critical", "private" (ancillary and not public) or "ancillary".
+    pub fn category(&self) -> &'static str {
+        if self.is_critical() {
+            "critical"
+        } else if !self.is_public() {
+            "private"
+        } else {
+            "ancillary"
+        }
+    }
+
+    pub fn is_known(&self) -> bool {
+        ChunkType::KNOWN.contains(&&self.0)
+    }
 }
 
 impl TryFrom<[u8; 4]> for ChunkType {
@@ -39,7 +62,7 @@ impl TryFrom<[u8; 4]> for ChunkType {
         if value.iter().all(|c| c.is_ascii_alphabetic()) {
             Ok(ChunkType(value))
         } else {
-            Err("type codes are restricted to consist of uppercase and lowercase ASCII letters(A-Z and a-z)")?
+            Err(CodedError::new(&i18n::TYPE_CODE_LETTERS, vec![]))?
         }
     }
 }
@@ -53,7 +76,7 @@ impl FromStr for ChunkType {
             arr.copy_from_slice(s.as_bytes());
             Self::try_from(arr)
         } else {
-            Err("type codes are restricted to 4 characters")?
+            Err(CodedError::new(&i18n::TYPE_CODE_LENGTH, vec![]))?
         }
     }
 }
@@ -148,6 +171,20 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_chunk_type_is_known() {
+        assert!(ChunkType::from_str("IDAT").unwrap().is_known());
+        assert!(ChunkType::from_str("tEXt").unwrap().is_known());
+        assert!(!ChunkType::from_str("RuSt").unwrap().is_known());
+    }
+
+    #[test]
+    pub fn test_chunk_type_category() {
+        assert_eq!(ChunkType::from_str("IDAT").unwrap().category(), "critical");
+        assert_eq!(ChunkType::from_str("tEXt").unwrap().category(), "ancillary");
+        assert_eq!(ChunkType::from_str("ruSt").unwrap().category(), "private");
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
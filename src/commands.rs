@@ -1,50 +1,1470 @@
-use crate::png::Png;
-use crate::Result;
+use crate::baseline::Baseline;
+use crate::hook::Policy;
+use crate::i18n::{self, CodedError, FileError, Lang};
+use crate::index::{ChunkEntry, ChunkIndex};
+use crate::index_cache::{self, IndexCache};
+use crate::json::Value;
+use crate::namespace::Registry;
+use crate::payload::{self, decode_payload, encode_smallest_payload};
+use crate::platform::{self, long_path};
+use crate::png::{ParseError, Png};
+use crate::query::Query;
+use crate::regex::Regex;
+use crate::schema::Schema;
+use crate::storage::{self, ObjectUrl};
+use crate::summary::Summary;
+use crate::text::{Encoding, TextChunk};
+use crate::validation::{self, Rule};
+use crate::view::PngRef;
+use crate::warning::Warning;
 use crate::{args::*, chunk::Chunk, chunk_type::ChunkType};
-use std::fs;
+use crate::{
+    container, diff, doctor, gc, glob, graph, hash, hook, lint, metrics, provenance, recovery,
+    replay, sniff, stripe, template, text, time, transform, Result,
+};
+use std::borrow::Cow;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
-pub fn encode(args: &EncodeArgs) -> Result<()> {
-    let mut png: Png = fs::read(&args.file_path)?.as_slice().try_into()?;
+pub fn encode(args: &EncodeArgs, global: &GlobalArgs) -> Result<()> {
+    let mut png: Png = read_png(&args.file_path, global)?;
 
-    png.append_chunk(Chunk::new(
-        ChunkType::from_str(&args.chunk_type)?,
-        args.message.as_bytes().into(),
-    ));
+    let messages = match (&args.chunk_type, &args.message) {
+        (Some(chunk_type), Some(message)) => {
+            vec![(ChunkType::from_str(chunk_type)?, message.clone())]
+        }
+        _ => args
+            .chunks
+            .iter()
+            .map(|spec| parse_chunk_spec(spec))
+            .collect::<Result<_>>()?,
+    };
 
-    fs::write(
-        args.output_file.as_ref().unwrap_or(&args.file_path),
-        png.as_bytes(),
+    if messages
+        .iter()
+        .any(|(chunk_type, _)| chunk_type.is_critical())
+    {
+        let warnings = handle_unsafe_to_copy(&mut png, args.drop_unsafe);
+        report_warnings(&args.file_path, &warnings, global)?;
+    }
+
+    let pipelines = args
+        .compress
+        .candidates()
+        .iter()
+        .map(|compression| {
+            compression
+                .iter()
+                .copied()
+                .chain(args.transforms.iter().map(String::as_str))
+                .map(transform::by_name)
+                .collect::<Result<Vec<_>>>()
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let options = transform_options(args.dict.as_deref())?;
+
+    // Build every chunk before touching the file, so one bad message leaves
+    // it unchanged.
+    let mut chunks = vec![];
+    for (chunk_type, message) in messages {
+        let message = if args.template {
+            template::expand(&message)?
+        } else {
+            message
+        };
+        let message = args.newline.apply(message.as_bytes());
+        if let Some(schema) = &args.schema {
+            check_schema(schema, &message)?;
+        }
+        let timestamp = args.versioned.then(time::now);
+        let data = encode_smallest_payload(&message, &pipelines, &options, timestamp)?;
+        chunks.push(Chunk::new(chunk_type, data));
+    }
+    if let Some(template) = &args.stripe {
+        return write_stripes(args, template, png, chunks.remove(0), global);
+    }
+    for chunk in chunks {
+        png.insert_before_iend(chunk);
+    }
+    if let Some(max_percent) = args.max_metadata_percent {
+        let warnings: Vec<Warning> = validation::metadata_ratio_warning(&png, max_percent)
+            .into_iter()
+            .collect();
+        report_warnings(&args.file_path, &warnings, global)?;
+    }
+
+    let output = args.output_file.as_deref().or(args.output.as_deref());
+    write_png(&args.file_path, output, &png, global)?;
+
+    Ok(())
+}
+
+/// Writes one copy of the carrier per stripe of the encoded payload.
+fn write_stripes(
+    args: &EncodeArgs,
+    template: &str,
+    carrier: Png,
+    chunk: Chunk,
+    global: &GlobalArgs,
+) -> Result<()> {
+    if !template.contains("%d") {
+        return Err("--stripe template must contain %d".into());
+    }
+    let stripes = stripe::split(chunk.data(), args.stripe_size);
+    for stripe in &stripes {
+        let mut png = carrier.clone();
+        png.insert_before_iend(Chunk::new(chunk.chunk_type(), stripe.to_bytes()));
+        let output = PathBuf::from(template.replace("%d", &stripe.index.to_string()));
+        write_png(&args.file_path, Some(&output), &png, global)?;
+    }
+    eprintln!("wrote {} stripes", stripes.len());
+    Ok(())
+}
+
+/// Parses `TYPE=MESSAGE`, or `TYPE@FILE` with the message read from FILE.
+fn parse_chunk_spec(spec: &str) -> Result<(ChunkType, String)> {
+    let invalid = || format!("--chunk {:?} is not TYPE=MESSAGE or TYPE@FILE", spec);
+    let (chunk_type, rest) = match spec.char_indices().nth(4) {
+        Some((4, '=' | '@')) => spec.split_at(4),
+        _ => return Err(invalid().into()),
+    };
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    let message = match rest.split_at(1) {
+        ("=", message) => message.to_string(),
+        (_, path) => fs::read_to_string(path).map_err(|e| format!("reading {}: {}", path, e))?,
+    };
+    Ok((chunk_type, message))
+}
+
+/// The error `decode --exists` reports for a missing chunk; the binary
+/// exits with 1 for it, without a message, and with 2 for real failures.
+#[derive(Debug)]
+pub struct ChunkAbsent {
+    pub chunk_type: String,
+}
+
+impl std::fmt::Display for ChunkAbsent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no {} chunk", self.chunk_type)
+    }
+}
+
+impl std::error::Error for ChunkAbsent {}
+
+pub fn decode(args: &DecodeArgs, global: &GlobalArgs) -> Result<()> {
+    let bytes;
+    let data = if args.gather {
+        Cow::Owned(gather_stripes(args, global)?)
+    } else {
+        bytes = read_bytes(&args.file_path, global)?;
+        let png = parse_png_ref(&args.file_path, &bytes, args.permissive, global)?;
+
+        if args.exists {
+            if png.chunk_by_type(&args.chunk_type).is_none() {
+                Err(ChunkAbsent {
+                    chunk_type: args.chunk_type.clone(),
+                })?;
+            }
+            return Ok(());
+        }
+
+        let at = args.at.as_deref().map(time::parse_as_of).transpose()?;
+        let versions = match ChunkType::from_str(&args.chunk_type) {
+            Ok(chunk_type) => payload::versions(&png, chunk_type),
+            Err(_) => vec![],
+        };
+        match payload::version_at(&versions, at) {
+            Some(version) => Cow::Borrowed(version.chunk.data()),
+            None if versions.is_empty() => {
+                eprintln!("non-existent chunk type");
+                return Ok(());
+            }
+            None => {
+                return Err(CodedError::new(
+                    &i18n::NO_VERSIONED_PAYLOAD,
+                    vec![args.chunk_type.clone(), args.at.clone().unwrap_or_default()],
+                )
+                .into())
+            }
+        }
+    };
+
+    let payload = decode_payload(&data, &transform_options(args.dict.as_deref())?)?;
+    let payload = args.newline.apply(&payload);
+    if let Some(schema) = &args.schema {
+        check_schema(schema, &payload)?;
+    }
+    let terminal = io::stdout().is_terminal();
+    match &args.query {
+        Some(query) => print_query(&Query::parse(query)?, &payload)?,
+        None if terminal && !args.no_pretty => {
+            let color = env::var_os("NO_COLOR").is_none() && platform::enable_colors();
+            println!("{}", sniff::pretty(&payload, color));
+        }
+        None => println!("{}", String::from_utf8_lossy(&payload)),
+    }
+
+    Ok(())
+}
+
+/// Collects the stripes of `args.chunk_type` from every file matching the
+/// pattern and joins them.
+fn gather_stripes(args: &DecodeArgs, global: &GlobalArgs) -> Result<Vec<u8>> {
+    let chunk_type = ChunkType::from_str(&args.chunk_type)?;
+    let mut stripes = vec![];
+    for path in glob::expand(&args.file_path)? {
+        let bytes = read_bytes(&path, global)?;
+        let png = parse_png_ref(&path, &bytes, args.permissive, global)?;
+        for chunk in png.chunks_of_type(chunk_type) {
+            if let Some(stripe) = stripe::Stripe::parse(chunk.data())
+                .map_err(|e| format!("{}: {}", path.display(), e))?
+            {
+                stripes.push(stripe);
+            }
+        }
+    }
+    stripe::gather(stripes)
+}
+
+pub fn remove(args: &RemoveArgs, global: &GlobalArgs) -> Result<()> {
+    let mut png: Png = read_png(&args.file_path, global)?;
+
+    if let Some(pattern) = &args.matching {
+        return remove_matching(args, &Regex::new(pattern)?, png, global);
+    }
+
+    let removed = match (&args.chunk_type, args.offset) {
+        (_, Some(offset)) => {
+            let index = png.chunk_index_at(offset)?;
+            png.remove_chunk_at(index)
+        }
+        (Some(chunk_type), None) => png.remove_chunk(chunk_type)?,
+        (None, None) => unreachable!("clap requires a chunk type, --offset or --matching"),
+    };
+
+    if removed.chunk_type().is_critical() {
+        let warnings = handle_unsafe_to_copy(&mut png, args.drop_unsafe);
+        report_warnings(&args.file_path, &warnings, global)?;
+    }
+
+    write_png(&args.file_path, None, &png, global)?;
+
+    Ok(())
+}
+
+/// Removes ancillary chunks whose decoded contents match `regex`. Critical
+/// chunks are never candidates, so the image itself is left intact.
+fn remove_matching(
+    args: &RemoveArgs,
+    regex: &Regex,
+    mut png: Png,
+    global: &GlobalArgs,
+) -> Result<()> {
+    let only = args
+        .chunk_type
+        .as_deref()
+        .map(ChunkType::from_str)
+        .transpose()?;
+    let options = transform::Options::default();
+    let matched: Vec<(usize, usize)> = png
+        .chunks_with_offsets()
+        .enumerate()
+        .filter(|(_, (_, chunk))| {
+            let chunk_type = chunk.chunk_type();
+            if chunk_type.is_critical() || only.is_some_and(|only| only != chunk_type) {
+                return false;
+            }
+            match TextChunk::from_chunk(chunk) {
+                Ok(entry) => regex.is_match(&entry.keyword) || regex.is_match(&entry.text),
+                Err(_) => {
+                    let data = decode_payload(chunk.data(), &options)
+                        .unwrap_or_else(|_| chunk.data().to_vec());
+                    regex.is_match(&String::from_utf8_lossy(&data))
+                }
+            }
+        })
+        .map(|(index, (offset, _))| (index, offset))
+        .collect();
+
+    let verb = if args.dry_run {
+        "would remove"
+    } else {
+        "removed"
+    };
+    for &(index, offset) in &matched {
+        eprintln!(
+            "{} chunk {} ({}) at offset {}",
+            verb,
+            index,
+            png.chunks()[index].chunk_type(),
+            offset
+        );
+    }
+    if args.dry_run || matched.is_empty() {
+        return Ok(());
+    }
+    for &(index, _) in matched.iter().rev() {
+        png.remove_chunk_at(index);
+    }
+    write_png(&args.file_path, None, &png, global)
+}
+
+pub fn history(args: &HistoryArgs, global: &GlobalArgs) -> Result<()> {
+    let bytes = read_bytes(&args.file_path, global)?;
+    let png = parse_png_ref(&args.file_path, &bytes, false, global)?;
+    let versions = payload::versions(&png, ChunkType::from_str(&args.chunk_type)?);
+    let current = payload::version_at(&versions, None).map(|version| version.index);
+    let offsets: Vec<usize> = png
+        .chunks_with_offsets()
+        .map(|(offset, _)| offset)
+        .collect();
+
+    println!("{:>10}  {:20}  {:>10}", "OFFSET", "STORED", "LENGTH");
+    for version in &versions {
+        println!(
+            "{:>10}  {:20}  {:>10}{}",
+            offsets[version.index],
+            version
+                .timestamp
+                .map_or("-".to_string(), time::format_rfc3339),
+            version.chunk.length(),
+            if current == Some(version.index) {
+                "  current"
+            } else {
+                ""
+            }
+        );
+    }
+
+    Ok(())
+}
+
+pub fn gc(args: &GcArgs, global: &GlobalArgs) -> Result<()> {
+    let policy = gc::Policy {
+        segmented: args
+            .segmented
+            .iter()
+            .map(|chunk_type| ChunkType::from_str(chunk_type))
+            .collect::<Result<_>>()?,
+        keep_versions: args.keep_versions,
+        incomplete_stripes: args.incomplete_stripes,
+    };
+    let mut files = vec![];
+    for pattern in &args.paths {
+        let paths = match ObjectUrl::from_path(pattern)? {
+            Some(url) => storage::expand(&url, false)?
+                .iter()
+                .map(ObjectUrl::to_path)
+                .collect(),
+            None => glob::expand(pattern)?,
+        };
+        for path in paths {
+            let png = read_png(&path, global)?;
+            files.push((path, png));
+        }
+    }
+    let mut stripes = gc::StripeInventory::default();
+    for (_, png) in &files {
+        stripes.add(png);
+    }
+
+    let (mut chunks, mut bytes) = (0, 0);
+    for (path, mut png) in files {
+        let garbage = gc::find(&png, &policy, &stripes);
+        for found in &garbage {
+            println!("{}: {}", path.display(), found);
+            bytes += found.bytes;
+        }
+        chunks += garbage.len();
+        if args.dry_run || garbage.is_empty() {
+            continue;
+        }
+        for found in garbage.iter().rev() {
+            png.remove_chunk_at(found.index);
+        }
+        write_png(&path, None, &png, global)?;
+    }
+    eprintln!(
+        "{} {} bytes in {} chunks",
+        if args.dry_run {
+            "would reclaim"
+        } else {
+            "reclaimed"
+        },
+        bytes,
+        chunks
+    );
+
+    Ok(())
+}
+
+pub fn extract(args: &ExtractArgs, global: &GlobalArgs) -> Result<()> {
+    let data = match ObjectUrl::from_path(&args.file_path)? {
+        // An object cannot seek, so it is downloaded once and read in memory.
+        Some(_) => {
+            let bytes = read_bytes(&args.file_path, global)?;
+            let index = in_file(&args.file_path, || {
+                ChunkIndex::read(&bytes[..], &global.crc_profile, &global.limits())
+            })?;
+            report_warnings(&args.file_path, &index.warnings(), global)?;
+            let entry = index.entry_at(args.offset)?;
+            in_file(&args.file_path, || entry.read_data(io::Cursor::new(&bytes)))?
+        }
+        None => {
+            let index = read_index(&args.file_path, false, global)?;
+            let entry = index.entry_at(args.offset)?;
+            in_file(&args.file_path, || {
+                entry.read_data(File::open(long_path(&args.file_path))?)
+            })?
+        }
+    };
+
+    match &args.output_file {
+        Some(path) => write_file(path, &data, global)?,
+        None => io::stdout().write_all(&data)?,
+    }
+
+    Ok(())
+}
+
+pub fn print(args: &PrintArgs, global: &GlobalArgs) -> Result<()> {
+    let png: Png = read_png(&args.file_path, global)?;
+
+    if args.output.porcelain {
+        print_porcelain(&ChunkIndex::of(&png), |_| true);
+    } else {
+        println!("{}", Summary::of(&png));
+        println!("{}", png);
+    }
+
+    Ok(())
+}
+
+pub fn scan(args: &ScanArgs, global: &GlobalArgs) -> Result<()> {
+    let index = read_index(&args.file_path, args.permissive, global)?;
+    let owner = match &args.owned_by {
+        Some(org) => Some(Registry::load(args.orgs.as_deref())?.resolve(org)?),
+        None => None,
+    };
+    let listed = |entry: &ChunkEntry| owner.is_none_or(|prefix| prefix.owns(&entry.chunk_type));
+
+    if args.output.porcelain {
+        print_porcelain(&index, listed);
+        return Ok(());
+    }
+    if args.dot || args.mermaid {
+        let entries = index.entries().iter().filter(|entry| listed(entry));
+        if args.dot {
+            print!("{}", graph::dot(entries));
+        } else {
+            print!("{}", graph::mermaid(entries));
+        }
+        return Ok(());
+    }
+
+    println!(
+        "{:>10}  {:4}  {:>10}  {:8}  CATEGORY",
+        "OFFSET", "TYPE", "LENGTH", "CRC"
+    );
+    for entry in index.entries().iter().filter(|entry| listed(entry)) {
+        println!(
+            "{:>10}  {:4}  {:>10}  {:08x}  {}",
+            entry.offset,
+            entry.chunk_type,
+            entry.length,
+            entry.crc,
+            entry.chunk_type.category()
+        );
+    }
+
+    Ok(())
+}
+
+pub fn count(args: &CountArgs, global: &GlobalArgs) -> Result<()> {
+    let png: Png = read_png(&args.file_path, global)?;
+
+    let count = match &args.chunk_type {
+        Some(chunk_type) => png.chunks_of_type(ChunkType::from_str(chunk_type)?).count(),
+        None => png.chunks().len(),
+    };
+    println!("{}", count);
+
+    Ok(())
+}
+
+pub fn tag(args: &TagArgs, global: &GlobalArgs) -> Result<()> {
+    let mut png: Png = read_png(&args.file_path, global)?;
+
+    let mut warnings = vec![];
+    if args.from_git {
+        for (key, value) in provenance::collect() {
+            println!("{}={}", key, value);
+            let encoding = if args.international {
+                Encoding::International
+            } else {
+                Encoding::Latin1
+            };
+            let entry = TextChunk::with_encoding(key, &value, encoding);
+            text::set_entry(&mut png, &entry, args.unmappable)?;
+            warnings.extend(entry.warnings());
+        }
+    }
+    report_warnings(&args.file_path, &warnings, global)?;
+
+    write_png(&args.file_path, args.output_file.as_deref(), &png, global)
+}
+
+pub fn lint(args: &LintArgs, global: &GlobalArgs) -> Result<()> {
+    let mut png: Png = read_png(&args.file_path, global)?;
+
+    let mut issues = lint::check(&png);
+    if let Some(max_percent) = args.max_metadata_percent {
+        issues.extend(validation::metadata_ratio_warning(&png, max_percent));
+    }
+    for issue in &issues {
+        println!("{}: {}", args.file_path.display(), issue);
+        if args.explain {
+            for line in explain(
+                issue.rule(),
+                &args.file_path,
+                Some(issue.chunk_type()),
+                issue.offset(),
+            ) {
+                println!("{}", line);
+            }
+        }
+    }
+
+    if !args.fix {
+        if !issues.is_empty() {
+            Err(CodedError::new(
+                &i18n::CHUNK_PROBLEMS,
+                vec![issues.len().to_string()],
+            ))?
+        }
+        return Ok(());
+    }
+
+    let fixes = lint::fix(&mut png)?;
+    for fix in &fixes {
+        eprintln!("{}", fix);
+    }
+    if fixes.is_empty() && args.output_file.is_none() {
+        return Ok(());
+    }
+    write_png(&args.file_path, args.output_file.as_deref(), &png, global)
+}
+
+pub fn recover(args: &RecoverArgs, global: &GlobalArgs) -> Result<()> {
+    let recovered = recovery::recover(&read_bytes(&args.file_path, global)?, &global.limits())?;
+
+    for found in &recovered.chunks {
+        println!(
+            "{:>10}  {:<4}  {:>10}  {}",
+            found.offset,
+            found.chunk.chunk_type(),
+            found.chunk.length(),
+            if found.repaired_length {
+                "recovered (length repaired)"
+            } else {
+                "recovered"
+            }
+        );
+    }
+    for (start, end) in &recovered.skipped {
+        println!("{:>10}  skipped {} damaged bytes", start, end - start);
+    }
+    eprintln!(
+        "{} chunks recovered, {} damaged ranges skipped",
+        recovered.chunks.len(),
+        recovered.skipped.len()
+    );
+
+    match &args.output_file {
+        Some(output_file) => write_png(
+            &args.file_path,
+            Some(output_file),
+            &recovered.into_png(),
+            global,
+        ),
+        None => Ok(()),
+    }
+}
+
+pub fn repair(args: &RepairArgs, global: &GlobalArgs) -> Result<()> {
+    if !args.structure {
+        return Ok(());
+    }
+
+    let (png, repairs) =
+        recovery::repair_structure(&read_bytes(&args.file_path, global)?, &global.limits())?;
+    for repair in &repairs {
+        eprintln!("{}", repair);
+    }
+    if repairs.is_empty() {
+        eprintln!("nothing to repair");
+        if args.output_file.is_none() {
+            return Ok(());
+        }
+    }
+    report_warnings(&args.file_path, &png.warnings(), global)?;
+
+    write_png(&args.file_path, args.output_file.as_deref(), &png, global)
+}
+
+pub fn doctor(args: &DoctorArgs, global: &GlobalArgs) -> Result<()> {
+    let file = args.file_path.display().to_string();
+    let bytes = read_bytes(&args.file_path, global)?;
+    let findings = doctor::examine(&bytes, &file, &global.crc_profile, &global.limits())?;
+
+    for (i, finding) in findings.iter().enumerate() {
+        println!("{:>3}. {}", i + 1, finding);
+        if let Some(fix) = &finding.fix {
+            println!("     fix: {}", fix);
+        }
+    }
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == doctor::Severity::Error)
+        .count();
+    eprintln!(
+        "{}: {} problems found, {} of them errors",
+        file,
+        findings.len(),
+        errors
+    );
+
+    if errors > 0 {
+        Err(CodedError::new(
+            &i18n::DOCTOR_ERRORS,
+            vec![file.to_string(), errors.to_string()],
+        ))?
+    }
+    Ok(())
+}
+
+pub fn replay(args: &ReplayArgs, global: &GlobalArgs) -> Result<()> {
+    let bytes = read_bytes(&args.file_path, global)?;
+    let trace = replay::replay(&bytes, &global.crc_profile, &global.limits());
+
+    if bytes.starts_with(&Png::STANDARD_HEADER) {
+        println!("{:>10}  signature", 0);
+    }
+    for step in &trace.steps {
+        println!("{}", step);
+    }
+    match &trace.stop {
+        Some((offset, error)) => println!(
+            "stopped at offset {}: {}; {} bytes left unparsed",
+            offset,
+            i18n::render(error, Lang::detect(global.lang)),
+            trace.unread
+        ),
+        None => println!(
+            "reached the end after {} chunks, {} bytes",
+            trace.steps.len(),
+            bytes.len()
+        ),
+    }
+    Ok(())
+}
+
+pub fn identify(args: &IdentifyArgs, global: &GlobalArgs) -> Result<()> {
+    let bytes = read_bytes(&args.file_path, global)?;
+    let identity = container::identify(&bytes, &global.limits());
+    let capabilities = identity.container.capabilities();
+
+    println!("container: {}", identity.container.name());
+    println!(
+        "confidence: {} ({})",
+        identity.confidence.name(),
+        identity.evidence
+    );
+    if capabilities.is_empty() {
+        println!("capabilities: none; pngme has no backend for this container yet");
+    } else {
+        println!("capabilities: {}", capabilities.join(", "));
+    }
+    Ok(())
+}
+
+pub fn baseline(args: &BaselineArgs, global: &GlobalArgs) -> Result<()> {
+    match args {
+        BaselineArgs::Create(args) => {
+            let baseline = record_baseline(&args.dir, global)?;
+            let json = baseline.to_json().to_pretty_string() + "\n";
+            write_file(&args.baseline_file, json.as_bytes(), global)?;
+            Ok(())
+        }
+        BaselineArgs::Check(args) => {
+            let recorded =
+                Baseline::from_json(&Value::parse(&fs::read_to_string(&args.baseline_file)?)?)?;
+            let changes = recorded.check(&record_baseline(&args.dir, global)?);
+            for change in &changes {
+                println!("{}", change);
+            }
+
+            if changes.is_empty() {
+                Ok(())
+            } else {
+                Err(CodedError::new(
+                    &i18n::BASELINE_CHANGED,
+                    vec![changes.len().to_string()],
+                ))?
+            }
+        }
+    }
+}
+
+pub fn hook(args: &HookArgs, global: &GlobalArgs) -> Result<()> {
+    match args {
+        HookArgs::PreCommit(args) => pre_commit(args, global),
+    }
+}
+
+/// Applies the chunk policy to the staged content of every PNG, restaging
+/// the files it changed. The working tree copy is rewritten too when it
+/// matches the index, and otherwise left with its unstaged edits. Files with
+/// chunks the policy does not allow are left alone and fail the hook, which
+/// blocks the commit.
+fn pre_commit(args: &PreCommitArgs, global: &GlobalArgs) -> Result<()> {
+    let root = hook::git(Path::new("."), &["rev-parse", "--show-toplevel"])?;
+    let root = PathBuf::from(String::from_utf8_lossy(&root).trim());
+    let policy = Policy::load(
+        &args
+            .policy
+            .clone()
+            .unwrap_or_else(|| root.join(hook::POLICY_FILE)),
     )?;
+    let staged = hook::git(
+        &root,
+        &[
+            "diff",
+            "--cached",
+            "--raw",
+            "--no-abbrev",
+            "--diff-filter=ACMR",
+            "-z",
+        ],
+    )?;
+
+    let mut blocked = 0;
+    for file in hook::staged_pngs(&staged) {
+        let name = file.path.display();
+        let path = root.join(&file.path);
+        let content = in_file(&path, || hook::read_staged(&root, &file))?;
+        let mut png = in_file(&path, || {
+            let png =
+                Png::read_with_crc_profile(&content[..], &global.crc_profile, &global.limits())?;
+            report_warnings(&path, &png.warnings(), global)?;
+            Ok(png)
+        })?;
+        let outcome = policy.apply(&mut png);
+        for chunk_type in &outcome.disallowed {
+            eprintln!(
+                "{}: {} chunks are not allowed by the policy",
+                name, chunk_type
+            );
+        }
+        if !outcome.disallowed.is_empty() {
+            blocked += 1;
+            continue;
+        }
+        if outcome.stripped.is_empty() {
+            continue;
+        }
+
+        eprintln!("{}: stripped {} chunks", name, outcome.stripped.len());
+        if global.show_result {
+            let before = Png::read_partial(&content[..], &global.crc_profile, &global.limits())
+                .map_or_else(|_| vec![], |(before, _)| diff::chunk_lines(&before));
+            print!("{}", diff::unified(&before, &diff::chunk_lines(&png)));
+            continue;
+        }
+        check_writable(&path, global)?;
+        hook::restage(&root, &file, &png.as_bytes())?;
+        if fs::read(&path).is_ok_and(|current| current == content) {
+            write_png(&path, None, &png, global)?;
+        } else {
+            eprintln!(
+                "{}: the working tree copy has unstaged changes and still holds the stripped chunks",
+                name
+            );
+        }
+    }
 
+    if blocked > 0 {
+        Err(CodedError::new(
+            &i18n::HOOK_BLOCKED,
+            vec![blocked.to_string()],
+        ))?
+    }
     Ok(())
 }
 
-pub fn decode(args: &DecodeArgs) -> Result<()> {
-    let png: Png = fs::read(&args.file_path)?.as_slice().try_into()?;
+/// Paths are stored relative to `dir` with `/` separators, so a baseline
+/// created on one machine can be checked on another.
+fn record_baseline(dir: &Path, global: &GlobalArgs) -> Result<Baseline> {
+    let mut files = vec![];
+    collect_png_files(dir, true, &mut files)?;
+
+    let mut baseline = Baseline::default();
+    for file in files {
+        let relative: Vec<_> = file
+            .strip_prefix(dir)?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect();
+        baseline.add(&relative.join("/"), &read_png(&file, global)?);
+    }
+    Ok(baseline)
+}
+
+/// The frozen `--porcelain` layout documented on the `print` and `scan` flags.
+/// Never change existing fields; only append new ones.
+/// Chunks rejected by `listed` are skipped without renumbering the rest.
+fn print_porcelain(index: &ChunkIndex, listed: impl Fn(&ChunkEntry) -> bool) {
+    for (i, entry) in index.entries().iter().enumerate() {
+        if !listed(entry) {
+            continue;
+        }
+        println!(
+            "chunk\t{}\t{}\t{}\t{}\t{:08x}\t{}",
+            i,
+            entry.offset,
+            entry.chunk_type,
+            entry.length,
+            entry.crc,
+            entry.chunk_type.category()
+        );
+    }
+}
 
-    match png.chunk_by_type(&args.chunk_type) {
-        Some(chunk) => println!("{}", String::from_utf8_lossy(chunk.data())),
-        None => eprintln!("non-existent chunk type"),
+/// Checks every PNG under `args.path`, spreading files over worker threads.
+/// Failures are printed to stdout as `path<TAB>reason` lines and the summary
+/// goes to stderr, so the output can be piped straight into other tools.
+pub fn verify(args: &VerifyArgs, global: &GlobalArgs) -> Result<()> {
+    let mut files = vec![];
+    if let Some(url) = ObjectUrl::from_path(&args.path)? {
+        files.extend(
+            storage::expand(&url, args.recursive)?
+                .iter()
+                .map(ObjectUrl::to_path),
+        );
+    } else if args.path.is_dir() {
+        collect_png_files(&args.path, args.recursive, &mut files)?;
+    } else {
+        files.push(args.path.clone());
     }
 
+    let jobs = args
+        .jobs
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+        .clamp(1, files.len().max(1));
+    let next = AtomicUsize::new(0);
+    let lang = Lang::detect(global.lang);
+    let options = transform_options(args.dict.as_deref())?;
+
+    let (mut failures, mut warnings): (Vec<Failure>, Vec<(PathBuf, Warning)>) =
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..jobs)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut failures = vec![];
+                        let mut warnings = vec![];
+                        loop {
+                            let i = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(file) = files.get(i) else {
+                                metrics::merge_thread();
+                                break (failures, warnings);
+                            };
+                            match verify_file(file, args, &options, global) {
+                                Ok(found) => {
+                                    if global.warnings_as_errors && !found.is_empty() {
+                                        let error = CodedError::new(
+                                            &i18n::WARNINGS_AS_ERRORS,
+                                            vec![
+                                                file.display().to_string(),
+                                                found.len().to_string(),
+                                            ],
+                                        );
+                                        failures.push(Failure {
+                                            path: file.clone(),
+                                            reason: format!(
+                                                "{} warnings treated as errors",
+                                                found.len()
+                                            ),
+                                            rule: None,
+                                            json: i18n::to_json(
+                                                &FileError::wrap(file, error.into()),
+                                                lang,
+                                            ),
+                                        });
+                                    }
+                                    warnings.extend(found.into_iter().map(|w| (file.clone(), w)));
+                                }
+                                Err(e) => failures.push(Failure {
+                                    path: file.clone(),
+                                    reason: e.to_string(),
+                                    rule: e.downcast_ref::<ParseError>().map(ParseError::rule),
+                                    json: i18n::to_json(&FileError::wrap(file, e), lang),
+                                }),
+                            }
+                        }
+                    })
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("verify worker panicked"))
+                .fold((vec![], vec![]), |(mut failures, mut warnings), (f, w)| {
+                    failures.extend(f);
+                    warnings.extend(w);
+                    (failures, warnings)
+                })
+        });
+    failures.sort_by(|a, b| a.path.cmp(&b.path));
+    warnings.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (file, warning) in &warnings {
+        print_warning(file, warning, global);
+        // JSON warnings carry the fix already.
+        if args.explain && global.format == Format::Text {
+            for line in explain(
+                warning.rule(),
+                file,
+                Some(warning.chunk_type()),
+                warning.offset(),
+            ) {
+                eprintln!("{}", line);
+            }
+        }
+    }
+    for failure in &failures {
+        println!("{}\t{}", failure.path.display(), failure.reason);
+        if global.format == Format::Json {
+            eprintln!("{}", failure.json);
+        }
+        if let (true, Some(rule)) = (args.explain, failure.rule) {
+            for line in explain(rule, &failure.path, None, None) {
+                eprintln!("{}", line);
+            }
+        }
+    }
+    eprintln!(
+        "verified {} files: {} ok, {} failed",
+        files.len(),
+        files.len() - failures.len(),
+        failures.len()
+    );
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(CodedError::new(
+            &i18n::VERIFY_FAILED,
+            vec![failures.len().to_string()],
+        ))?
+    }
+}
+
+/// Fails unless `payload` is a JSON document matching the schema file,
+/// listing every violation on stderr.
+fn check_schema(schema: &Path, payload: &[u8]) -> Result<()> {
+    let schema = Schema::parse(&fs::read_to_string(schema)?)
+        .map_err(|e| format!("{}: {}", schema.display(), e))?;
+    let document = std::str::from_utf8(payload)
+        .map_err(|e| e.into())
+        .and_then(Value::parse)
+        .map_err(|e| format!("payload is not JSON: {}", e))?;
+
+    let violations = schema.validate(&document);
+    for violation in &violations {
+        eprintln!("schema: {}", violation);
+    }
+    if !violations.is_empty() {
+        Err(CodedError::new(
+            &i18n::SCHEMA_MISMATCH,
+            vec![violations.len().to_string()],
+        ))?
+    }
     Ok(())
 }
 
-pub fn remove(args: &RemoveArgs) -> Result<()> {
-    let mut png: Png = fs::read(&args.file_path)?.as_slice().try_into()?;
+fn print_query(query: &Query, payload: &[u8]) -> Result<()> {
+    let document = Value::parse(std::str::from_utf8(payload)?)
+        .map_err(|e| format!("payload is not JSON: {}", e))?;
+    for value in query.evaluate(&document)? {
+        match value {
+            Value::String(string) => println!("{}", string),
+            value => println!("{}", value),
+        }
+    }
+    Ok(())
+}
+
+fn transform_options(dict: Option<&Path>) -> Result<transform::Options> {
+    Ok(transform::Options {
+        dictionary: dict.map(fs::read).transpose()?,
+    })
+}
+
+/// `path` opened for reading; with the cloud feature it may be an object URL.
+fn open_input(path: &Path) -> Result<Box<dyn Read>> {
+    Ok(match ObjectUrl::from_path(path)? {
+        Some(url) => Box::new(storage::open(&url)?),
+        None => Box::new(File::open(long_path(path))?),
+    })
+}
+
+fn read_png(path: &Path, global: &GlobalArgs) -> Result<Png> {
+    in_file(path, || {
+        let png = Png::read_with_crc_profile(
+            BufReader::with_capacity(global.io_buffer, open_input(path)?),
+            &global.crc_profile,
+            &global.limits(),
+        )?;
+        let warnings = png.warnings();
+        report_warnings(path, &warnings, global)?;
+        Ok(png)
+    })
+}
 
-    png.remove_chunk(&args.chunk_type)?;
+/// Runs `f`, attributing any error to `path` for `--format json`.
+fn in_file<T>(path: &Path, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    f().map_err(|e| FileError::wrap(path, e))
+}
 
-    fs::write(&args.file_path, png.as_bytes())?;
+/// The whole of `path`, for commands that work on damaged files the parser
+/// would reject. Only `--max-file-size` is checked here; the commands hold
+/// the chunks they walk to the other limits themselves.
+fn read_bytes(path: &Path, global: &GlobalArgs) -> Result<Vec<u8>> {
+    in_file(path, || {
+        let limits = global.limits();
+        let mut bytes = vec![];
+        if ObjectUrl::from_path(path)?.is_none() {
+            let file = File::open(long_path(path))?;
+            limits.check_file_size(file.metadata()?.len())?;
+            BufReader::with_capacity(global.io_buffer, file).read_to_end(&mut bytes)?;
+            return Ok(bytes);
+        }
+        // An object's size is only known once it is downloaded, so stop one
+        // byte past the limit.
+        let limit = limits
+            .max_file_size
+            .map_or(u64::MAX, |max| max.saturating_add(1));
+        open_input(path)?.take(limit).read_to_end(&mut bytes)?;
+        limits.check_file_size(bytes.len() as u64)?;
+        Ok(bytes)
+    })
+}
+
+/// `bytes`, read from `path` with `read_bytes`, as a view borrowing from
+/// them. With `permissive` a truncated file is read up to the last complete
+/// chunk and the cut is reported instead of failing.
+fn parse_png_ref<'a>(
+    path: &Path,
+    bytes: &'a [u8],
+    permissive: bool,
+    global: &GlobalArgs,
+) -> Result<PngRef<'a>> {
+    in_file(path, || {
+        let png = if permissive {
+            let (png, truncated_at) =
+                PngRef::parse_partial(bytes, &global.crc_profile, &global.limits())?;
+            if let Some(offset) = truncated_at {
+                warn_truncated(path, offset, png.chunks().len(), bytes.len() as u64, global);
+            }
+            png
+        } else {
+            PngRef::parse(bytes, &global.crc_profile, &global.limits())?
+        };
+        report_warnings(path, &png.warnings(), global)?;
+        Ok(png)
+    })
+}
+
+fn warn_truncated(path: &Path, offset: usize, chunks: usize, size: u64, global: &GlobalArgs) {
+    let message = format!(
+        "truncated at offset {}: {} complete chunks read, last {} bytes discarded",
+        offset,
+        chunks,
+        size - offset as u64
+    );
+    warn(
+        path,
+        &message,
+        Some(&validation::TRUNCATED),
+        None,
+        Some(offset),
+        global,
+    );
+}
+
+fn print_warning(path: &Path, warning: &Warning, global: &GlobalArgs) {
+    let message = warning.to_string();
+    let (chunk_type, offset) = (Some(warning.chunk_type()), warning.offset());
+    warn(
+        path,
+        &message,
+        Some(warning.rule()),
+        chunk_type,
+        offset,
+        global,
+    );
+}
 
+/// Prints a warning about `path` on stderr: a `warning:` line, or with
+/// `--format json` an object shaped like the ones errors are printed as,
+/// naming the rule broken and the command that fixes it.
+fn warn(
+    path: &Path,
+    message: &str,
+    rule: Option<&Rule>,
+    chunk_type: Option<&str>,
+    offset: Option<usize>,
+    global: &GlobalArgs,
+) {
+    if global.format == Format::Text {
+        eprintln!("warning: {}: {}", path.display(), message);
+        return;
+    }
+    let file = path.display().to_string();
+    let fix = rule.and_then(|rule| rule.fix_at(&file, chunk_type, offset));
+    let optional = |value: Option<Value>| value.unwrap_or(Value::Null);
+    let json = Value::Object(vec![
+        ("level".to_string(), "warning".into()),
+        (
+            "rule".to_string(),
+            optional(rule.map(|rule| rule.id.into())),
+        ),
+        ("message".to_string(), message.into()),
+        ("file".to_string(), file.into()),
+        (
+            "offset".to_string(),
+            optional(offset.map(|o| (o as f64).into())),
+        ),
+        (
+            "chunk_type".to_string(),
+            optional(chunk_type.map(Value::from)),
+        ),
+        ("fix".to_string(), optional(fix.map(Value::from))),
+    ]);
+    eprintln!("{}", json);
+}
+
+/// The chunk index of `path`, read without keeping chunk data in memory,
+/// or loaded from `--index-cache`; `permissive` works as for
+/// `parse_png_ref`.
+fn read_index(path: &Path, permissive: bool, global: &GlobalArgs) -> Result<ChunkIndex> {
+    in_file(path, || read_index_from(path, permissive, global))
+}
+
+fn read_index_from(path: &Path, permissive: bool, global: &GlobalArgs) -> Result<ChunkIndex> {
+    // Objects have no file metadata to key the cache on; they are parsed
+    // from memory every time.
+    if ObjectUrl::from_path(path)?.is_some() {
+        let bytes = read_bytes(path, global)?;
+        let index = if permissive {
+            let (index, truncated_at) =
+                ChunkIndex::read_partial(&bytes[..], &global.crc_profile, &global.limits())?;
+            if let Some(offset) = truncated_at {
+                warn_truncated(
+                    path,
+                    offset,
+                    index.entries().len(),
+                    bytes.len() as u64,
+                    global,
+                );
+            }
+            index
+        } else {
+            ChunkIndex::read(&bytes[..], &global.crc_profile, &global.limits())?
+        };
+        report_warnings(path, &index.warnings(), global)?;
+        return Ok(index);
+    }
+
+    let file = File::open(long_path(path))?;
+    let metadata = file.metadata()?;
+    let size = metadata.len();
+    let cache = global.index_cache().map(IndexCache::new);
+    let key = index_cache::Key::of(&metadata, &global.crc_profile);
+
+    let index = match cache.as_ref().and_then(|cache| cache.load(path, &key)) {
+        Some(index) => {
+            // The limits may be tighter than when the index was stored.
+            let mut budget = global.limits().start();
+            for entry in index.entries() {
+                budget.admit(entry.offset, entry.length)?;
+            }
+            index
+        }
+        None => {
+            let reader = BufReader::with_capacity(global.io_buffer, file);
+            let (index, truncated_at) = if permissive {
+                ChunkIndex::read_partial(reader, &global.crc_profile, &global.limits())?
+            } else {
+                (
+                    ChunkIndex::read(reader, &global.crc_profile, &global.limits())?,
+                    None,
+                )
+            };
+            match (truncated_at, &cache) {
+                (Some(offset), _) => {
+                    warn_truncated(path, offset, index.entries().len(), size, global)
+                }
+                // Only whole files are cached, so a permissive read of a
+                // truncated file keeps reporting the cut. Read-only runs use
+                // the cache but never add to it.
+                (None, Some(cache)) if !global.is_read_only() => {
+                    // A file changed while it was parsed may not match the
+                    // index, so it is not stored.
+                    let unchanged = fs::metadata(long_path(path)).is_ok_and(|after| {
+                        index_cache::Key::of(&after, &global.crc_profile) == key
+                    });
+                    if unchanged {
+                        if let Err(e) = cache.store(path, &key, &index) {
+                            let message = format!("index cache: {}", e);
+                            warn(path, &message, None, None, None, global);
+                        }
+                    }
+                }
+                (None, _) => {}
+            }
+            index
+        }
+    };
+    report_warnings(path, &index.warnings(), global)?;
+    Ok(index)
+}
+
+/// Writes `png` to `output`, or back over `input` when there is no output
+/// file. With `--show-result` nothing is written; the chunk lists of `input`
+/// and `png` are diffed instead.
+fn write_png(input: &Path, output: Option<&Path>, png: &Png, global: &GlobalArgs) -> Result<()> {
+    if global.show_result {
+        // The input may be damaged, as for recover; diff whatever parses.
+        let before = open_input(input)
+            .ok()
+            .and_then(|mut reader| {
+                let mut bytes = vec![];
+                reader.read_to_end(&mut bytes).ok()?;
+                Png::read_partial(&bytes[..], &global.crc_profile, &global.limits()).ok()
+            })
+            .map_or_else(Vec::new, |(before, _)| diff::chunk_lines(&before));
+        print!("{}", diff::unified(&before, &diff::chunk_lines(png)));
+        return Ok(());
+    }
+
+    // Write next to the target and rename over it, so an interrupted write
+    // never leaves a partial file behind.
+    let target = output.unwrap_or(input);
+    check_writable(target, global)?;
+    if let Some(url) = ObjectUrl::from_path(target)? {
+        let written = storage::create(&url).and_then(|mut object| {
+            png.write_to(BufWriter::with_capacity(global.io_buffer, &mut object))?;
+            object.finish()
+        });
+        return written.map_err(|e| FileError::wrap(target, e));
+    }
+    // Replace what a symlink points to rather than the link itself.
+    let resolved = match fs::symlink_metadata(target) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            Cow::Owned(fs::canonicalize(target).map_err(|e| FileError::wrap(target, e.into()))?)
+        }
+        _ => Cow::Borrowed(target),
+    };
+    let path = long_path(&resolved);
+    let (temp, file) = create_temp(&path).map_err(|e| FileError::wrap(target, e.into()))?;
+    let written = (|| {
+        if let Ok(metadata) = fs::metadata(&path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        let mut writer = BufWriter::with_capacity(global.io_buffer, file);
+        png.write_to(&mut writer)?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        fs::rename(&temp, &path)
+    })();
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(FileError::wrap(target, e.into()));
+    }
     Ok(())
 }
 
-pub fn print(args: &PrintArgs) -> Result<()> {
-    let png: Png = fs::read(&args.file_path)?.as_slice().try_into()?;
+/// Writes a file other than a PNG, such as an extracted payload.
+fn write_file(path: &Path, contents: &[u8], global: &GlobalArgs) -> Result<()> {
+    check_writable(path, global)?;
+    in_file(path, || Ok(fs::write(long_path(path), contents)?))
+}
+
+/// Fails under `--read-only`. `main` already refuses commands that write,
+/// going by `PngMeArgs::writes_files`; every write checks again so that a
+/// path that check misses cannot write either.
+fn check_writable(path: &Path, global: &GlobalArgs) -> Result<()> {
+    if global.is_read_only() {
+        Err(CodedError::new(
+            &i18n::READ_ONLY_WRITE,
+            vec![path.display().to_string()],
+        ))?
+    }
+    Ok(())
+}
+
+/// A new file next to `path` for writing its replacement, named so that
+/// concurrent writers never share one.
+fn create_temp(path: &Path) -> io::Result<(PathBuf, File)> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(format!(
+            ".pngme-tmp-{}-{}",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        match File::options().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((PathBuf::from(temp), file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// A file that failed `verify`, with the spec rule it broke when known.
+struct Failure {
+    path: PathBuf,
+    reason: String,
+    rule: Option<&'static Rule>,
+    /// The error as `--format json` reports it.
+    json: Value,
+}
+
+/// The indented lines `verify --explain` prints under an issue.
+fn explain(
+    rule: &Rule,
+    path: &Path,
+    chunk_type: Option<&str>,
+    offset: Option<usize>,
+) -> Vec<String> {
+    let mut lines = vec![
+        format!("  rule: {} ({})", rule.summary, rule.id),
+        format!("  spec: {}", rule.spec),
+        format!("  why:  {}", rule.rationale),
+    ];
+    if let Some(fix) = rule.fix_at(&path.display().to_string(), chunk_type, offset) {
+        lines.push(format!("  fix:  {}", fix));
+    }
+    lines
+}
+
+/// Checks one file, returning its warnings for the caller to report.
+/// `options` holds the `--dict` the payload checked by
+/// `--expect-payload-sha256` was compressed with.
+fn verify_file(
+    path: &Path,
+    args: &VerifyArgs,
+    options: &transform::Options,
+    global: &GlobalArgs,
+) -> Result<Vec<Warning>> {
+    // Hashing the file needs all of it, so it is read once into memory
+    // rather than downloaded again.
+    let png = match &args.expect_sha256 {
+        Some(expected) => {
+            let bytes = read_bytes(path, global)?;
+            let actual = hash::to_hex(&hash::sha256(&bytes));
+            if !actual.eq_ignore_ascii_case(expected) {
+                Err(CodedError::new(
+                    &i18n::FILE_SHA256,
+                    vec![actual, expected.clone()],
+                ))?
+            }
+            Png::read_with_crc_profile(&bytes[..], &global.crc_profile, &global.limits())?
+        }
+        None => Png::read_with_crc_profile(
+            BufReader::with_capacity(global.io_buffer, open_input(path)?),
+            &global.crc_profile,
+            &global.limits(),
+        )?,
+    };
+    let mut warnings = png.warnings();
+    if let Some(max_percent) = args.max_metadata_percent {
+        warnings.extend(validation::metadata_ratio_warning(&png, max_percent));
+    }
+
+    if let (Some(expected), Some(chunk_type)) = (&args.expect_payload_sha256, &args.payload_type) {
+        let chunk = png
+            .chunk_by_type(chunk_type)
+            .ok_or_else(|| format!("no {} payload chunk", chunk_type))?;
+        let payload = decode_payload(chunk.data(), options)?;
+        let actual = hash::to_hex(&hash::sha256(&payload));
+        if !actual.eq_ignore_ascii_case(expected) {
+            Err(CodedError::new(
+                &i18n::PAYLOAD_SHA256,
+                vec![actual, expected.clone()],
+            ))?
+        }
+    }
+
+    Ok(warnings)
+}
+
+fn collect_png_files(dir: &Path, recursive: bool, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(long_path(dir))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if recursive {
+                collect_png_files(&path, recursive, files)?;
+            }
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png"))
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
 
-    println!("{}", png);
+/// Warnings go to stderr so they never mix with command output. With
+/// `--warnings-as-errors` any warning aborts the command before it writes.
+fn report_warnings(path: &Path, warnings: &[Warning], global: &GlobalArgs) -> Result<()> {
+    for warning in warnings {
+        print_warning(path, warning, global);
+    }
 
+    if global.warnings_as_errors && !warnings.is_empty() {
+        Err(CodedError::new(
+            &i18n::WARNINGS_AS_ERRORS,
+            vec![path.display().to_string(), warnings.len().to_string()],
+        ))?
+    }
     Ok(())
 }
+
+/// The spec forbids copying unknown unsafe-to-copy chunks unchanged once
+/// critical chunks have been modified, so either drop them or warn.
+fn handle_unsafe_to_copy(png: &mut Png, drop_unsafe: bool) -> Vec<Warning> {
+    if drop_unsafe {
+        for chunk in png.remove_unsafe_to_copy_chunks() {
+            eprintln!("dropped unsafe-to-copy chunk {}", chunk.chunk_type());
+        }
+        vec![]
+    } else {
+        png.unsafe_to_copy_chunks()
+            .map(|chunk| Warning::UnsafeToCopy {
+                chunk_type: chunk.chunk_type().to_string(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,246 @@
+//! Which image container a file is in, as `pngme identify` reports it.
+//!
+//! pngme only works on PNG chunks so far. `identify` is where other formats
+//! are dispatched once they have backends: it names the container from the
+//! file's magic bytes and structure, says how sure it is, and lists the
+//! commands that apply, which for other containers is none yet.
+
+use crate::args::Cli;
+use crate::chunk::CrcProfile;
+use crate::limits::Limits;
+use crate::png::Png;
+use crate::replay;
+use clap::CommandFactory;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    Png,
+    Apng,
+    Jpeg,
+    WebP,
+    Gif,
+    Unknown,
+}
+
+impl Container {
+    pub fn name(self) -> &'static str {
+        match self {
+            Container::Png => "PNG",
+            Container::Apng => "APNG",
+            Container::Jpeg => "JPEG",
+            Container::WebP => "WebP",
+            Container::Gif => "GIF",
+            Container::Unknown => "unknown",
+        }
+    }
+
+    /// The pngme commands that work on files in this container. Every
+    /// command works on PNGs, so their list comes from the command line
+    /// definition and cannot miss a new command.
+    pub fn capabilities(self) -> Vec<String> {
+        match self {
+            // Animation chunks are kept and listed like any other chunk.
+            Container::Png | Container::Apng => Cli::command()
+                .get_subcommands()
+                .map(|command| command.get_name().to_string())
+                .collect(),
+            Container::Jpeg | Container::WebP | Container::Gif | Container::Unknown => vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl Confidence {
+    pub fn name(self) -> &'static str {
+        match self {
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Identity {
+    pub container: Container,
+    pub confidence: Confidence,
+    /// What the guess rests on, for the user to judge it by.
+    pub evidence: String,
+}
+
+/// High confidence when both the magic bytes and the end of the file are
+/// what the container requires, medium when only the magic bytes are.
+fn by_magic(container: Container, ends_well: bool, evidence: &str) -> Identity {
+    Identity {
+        container,
+        confidence: if ends_well {
+            Confidence::High
+        } else {
+            Confidence::Medium
+        },
+        evidence: if ends_well {
+            evidence.to_string()
+        } else {
+            format!("{}, but the file does not end as it should", evidence)
+        },
+    }
+}
+
+/// Identifies the container of `bytes`, walking PNG chunks within `limits`.
+pub fn identify(bytes: &[u8], limits: &Limits) -> Identity {
+    if bytes.starts_with(&Png::STANDARD_HEADER) {
+        return identify_png(bytes, limits);
+    }
+    if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        return by_magic(
+            Container::Jpeg,
+            bytes.ends_with(&[0xff, 0xd9]),
+            "SOI marker",
+        );
+    }
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        return by_magic(Container::Gif, bytes.ends_with(&[0x3b]), "GIF signature");
+    }
+    if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        let size = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        return by_magic(
+            Container::WebP,
+            size + 8 == bytes.len(),
+            "RIFF header of form WEBP",
+        );
+    }
+    // A text-mode transfer rewrites the line endings in the signature.
+    if bytes.get(1..4) == Some(b"PNG") {
+        return Identity {
+            container: Container::Png,
+            confidence: Confidence::Low,
+            evidence: "damaged PNG signature; pngme doctor can tell more".to_string(),
+        };
+    }
+    Identity {
+        container: Container::Unknown,
+        confidence: Confidence::Low,
+        evidence: "no known signature".to_string(),
+    }
+}
+
+/// Walks the chunks without checking CRCs, which is `verify`'s business,
+/// to tell APNG from PNG and a whole file from a fragment.
+fn identify_png(bytes: &[u8], limits: &Limits) -> Identity {
+    let trace = replay::replay(bytes, &CrcProfile::None, limits);
+    let types: Vec<&[u8; 4]> = trace.steps.iter().map(|step| &step.chunk_type).collect();
+    let animation = types.iter().position(|&t| t == b"acTL");
+    let first_idat = types.iter().position(|&t| t == b"IDAT");
+
+    let container = match (animation, first_idat) {
+        (Some(actl), Some(idat)) if actl > idat => Container::Png,
+        (Some(_), _) => Container::Apng,
+        (None, _) => Container::Png,
+    };
+    let whole =
+        trace.stop.is_none() && types.first() == Some(&b"IHDR") && types.last() == Some(&b"IEND");
+    let evidence = match container {
+        Container::Apng => "PNG signature and an acTL chunk before the image data",
+        _ => "PNG signature",
+    };
+    if whole {
+        Identity {
+            container,
+            confidence: Confidence::High,
+            evidence: format!("{}, IHDR through IEND", evidence),
+        }
+    } else {
+        Identity {
+            container,
+            confidence: Confidence::Medium,
+            evidence: format!("{}, but the chunks are not a complete image", evidence),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn png(types: &[&str]) -> Vec<u8> {
+        Png::from_chunks(
+            types
+                .iter()
+                .map(|name| Chunk::new(ChunkType::from_str(name).unwrap(), vec![]))
+                .collect(),
+        )
+        .as_bytes()
+    }
+
+    #[test]
+    fn test_identify_png() {
+        let still = identify(&png(&["IHDR", "IDAT", "IEND"]), &Limits::default());
+        assert_eq!(still.container, Container::Png);
+        assert_eq!(still.confidence, Confidence::High);
+
+        let animated = identify(
+            &png(&["IHDR", "acTL", "IDAT", "fdAT", "IEND"]),
+            &Limits::default(),
+        );
+        assert_eq!(animated.container, Container::Apng);
+        assert_eq!(animated.confidence, Confidence::High);
+
+        let fragment = png(&["IHDR", "IDAT", "IEND"]);
+        let fragment = identify(&fragment[..fragment.len() - 12], &Limits::default());
+        assert_eq!(fragment.confidence, Confidence::Medium);
+
+        let mut mangled = png(&["IHDR", "IEND"]);
+        mangled.remove(4);
+        assert_eq!(
+            identify(&mangled, &Limits::default()).confidence,
+            Confidence::Low
+        );
+
+        let limits = Limits {
+            max_chunks: Some(2),
+            ..Limits::default()
+        };
+        let capped = identify(&png(&["IHDR", "IDAT", "IEND"]), &limits);
+        assert_eq!(capped.confidence, Confidence::Medium);
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let capabilities = Container::Apng.capabilities();
+        for command in ["print", "encode", "baseline", "hook", "identify"] {
+            assert!(capabilities.iter().any(|c| c == command), "{}", command);
+        }
+        assert!(Container::Jpeg.capabilities().is_empty());
+    }
+
+    #[test]
+    fn test_identify_other_containers() {
+        let identity = |bytes: &[u8]| {
+            let identity = identify(bytes, &Limits::default());
+            (identity.container, identity.confidence)
+        };
+        assert_eq!(
+            identity(&[0xff, 0xd8, 0xff, 0xe0, 0, 0, 0xff, 0xd9]),
+            (Container::Jpeg, Confidence::High)
+        );
+        assert_eq!(
+            identity(b"GIF89a\x01\x00"),
+            (Container::Gif, Confidence::Medium)
+        );
+        assert_eq!(
+            identity(b"RIFF\x08\x00\x00\x00WEBPVP8 "),
+            (Container::WebP, Confidence::High)
+        );
+        assert_eq!(identity(b"hello"), (Container::Unknown, Confidence::Low));
+        assert!(Container::Gif.capabilities().is_empty());
+    }
+}
@@ -0,0 +1,155 @@
+//! Line diffs of chunk lists, for `--show-result`.
+
+use crate::png::Png;
+
+/// One line per chunk. Offsets are left out so that inserting a chunk only
+/// changes the line for that chunk.
+pub fn chunk_lines(png: &Png) -> Vec<String> {
+    png.chunks()
+        .iter()
+        .map(|chunk| {
+            format!(
+                "{}  {:>10}  {:08x}  {}",
+                chunk.chunk_type(),
+                chunk.length(),
+                chunk.crc(),
+                chunk.chunk_type().category()
+            )
+        })
+        .collect()
+}
+
+/// A unified diff with full context: unchanged lines start with a space,
+/// removed lines with `-` and added lines with `+`.
+///
+/// Lines shared at both ends are matched first; the rest is aligned with
+/// Hirschberg's algorithm, so memory stays linear in the number of lines.
+pub fn unified(before: &[String], after: &[String]) -> String {
+    let prefix = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut out = String::from("--- before\n+++ after\n");
+    for line in &before[..prefix] {
+        out += &format!(" {}\n", line);
+    }
+    align(
+        &before[prefix..before.len() - suffix],
+        &after[prefix..after.len() - suffix],
+        &mut out,
+    );
+    for line in &before[before.len() - suffix..] {
+        out += &format!(" {}\n", line);
+    }
+    out
+}
+
+/// Appends a shortest edit of `before` into `after`, preferring to remove
+/// lines before adding them.
+fn align(before: &[String], after: &[String], out: &mut String) {
+    match before {
+        [] => after
+            .iter()
+            .for_each(|line| *out += &format!("+{}\n", line)),
+        _ if after.is_empty() => before
+            .iter()
+            .for_each(|line| *out += &format!("-{}\n", line)),
+        [line] => match after.iter().position(|other| other == line) {
+            Some(at) => {
+                align(&[], &after[..at], out);
+                *out += &format!(" {}\n", line);
+                align(&[], &after[at + 1..], out);
+            }
+            None => {
+                align(before, &[], out);
+                align(&[], after, out);
+            }
+        },
+        _ => {
+            let mid = before.len() / 2;
+            let head = lcs_lengths(&before[..mid], after);
+            let tail_before: Vec<&String> = before[mid..].iter().rev().collect();
+            let tail_after: Vec<&String> = after.iter().rev().collect();
+            let tail = lcs_lengths(&tail_before, &tail_after);
+            // The first split with the longest common subsequence keeps
+            // removals ahead of additions.
+            let split = (0..=after.len())
+                .rev()
+                .max_by_key(|&j| head[j] + tail[after.len() - j])
+                .unwrap();
+            align(&before[..mid], &after[..split], out);
+            align(&before[mid..], &after[split..], out);
+        }
+    }
+}
+
+/// `lengths[j]` is the longest common subsequence of `a` and `b[..j]`.
+fn lcs_lengths<T: PartialEq>(a: &[T], b: &[T]) -> Vec<usize> {
+    let mut lengths = vec![0; b.len() + 1];
+    for x in a {
+        let mut diagonal = 0;
+        for (j, y) in b.iter().enumerate() {
+            let above = lengths[j + 1];
+            lengths[j + 1] = if x == y {
+                diagonal + 1
+            } else {
+                above.max(lengths[j])
+            };
+            diagonal = above;
+        }
+    }
+    lengths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_unified() {
+        assert_eq!(
+            unified(&lines("IHDR tEXt IDAT IEND"), &lines("IHDR IDAT ruSt IEND")),
+            "--- before\n+++ after\n IHDR\n-tEXt\n IDAT\n+ruSt\n IEND\n"
+        );
+        assert_eq!(
+            unified(&[], &lines("IHDR IEND")),
+            "--- before\n+++ after\n+IHDR\n+IEND\n"
+        );
+        assert_eq!(
+            unified(&lines("a b"), &lines("a b")),
+            "--- before\n+++ after\n a\n b\n"
+        );
+    }
+
+    #[test]
+    fn test_moved_chunk() {
+        assert_eq!(
+            unified(&lines("IHDR IDAT gAMA IEND"), &lines("IHDR gAMA IDAT IEND")),
+            "--- before\n+++ after\n IHDR\n-IDAT\n gAMA\n+IDAT\n IEND\n"
+        );
+    }
+
+    #[test]
+    fn test_long_lists() {
+        let before: Vec<String> = (0..4_000).map(|i| format!("c{}", i % 7)).collect();
+        let mut after = before.clone();
+        after.insert(3_000, "ruSt".to_string());
+        after.remove(1_000);
+        let diff = unified(&before, &after);
+        let changed: Vec<&str> = diff
+            .lines()
+            .skip(2)
+            .filter(|line| !line.starts_with(' '))
+            .collect();
+        assert_eq!(changed, ["-c6", "+ruSt"]);
+        assert_eq!(diff.lines().count(), 2 + 4_001);
+    }
+}
@@ -0,0 +1,481 @@
+//! `doctor`: every check pngme knows, run over one file, with the problems
+//! found sorted by how badly they hurt and a command to fix each where
+//! pngme has one.
+
+use crate::chunk::CrcProfile;
+use crate::ihdr::Header;
+use crate::limits::{LimitError, Limits};
+use crate::payload::PayloadHeader;
+use crate::png::{ParseError, Png};
+use crate::text::TextChunk;
+use crate::validation::Rule;
+use crate::warning::Warning;
+use crate::{lint, recovery, stripe, transform, zlib, Result};
+use std::fmt::Display;
+
+/// How urgent a finding is; findings are listed most severe first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The file or the image in it cannot be read correctly.
+    Error,
+    /// The file breaks a spec rule that some decoders will trip over.
+    Warning,
+    /// Legal, but worth knowing.
+    Info,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    /// Which check found the problem: a validation rule id or a doctor check.
+    pub check: &'static str,
+    pub message: String,
+    /// A command that fixes the problem.
+    pub fix: Option<String>,
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}: {}", self.severity, self.check, self.message)
+    }
+}
+
+/// Data lengths fixed by the spec for known chunks.
+const FIXED_LENGTHS: &[(&[u8; 4], usize)] = &[
+    (b"IHDR", 13),
+    (b"IEND", 0),
+    (b"cHRM", 32),
+    (b"cICP", 4),
+    (b"gAMA", 4),
+    (b"sRGB", 1),
+    (b"pHYs", 9),
+    (b"tIME", 7),
+    (b"acTL", 8),
+];
+
+/// Runs the whole battery over the bytes of `file`: signature and chunk
+/// structure, CRCs, the image data, chunk ordering, the contents of known
+/// chunks and pngme payload headers. Damaged files are checked as far as
+/// [`recovery`] can salvage them. CRCs are checked with `profile`. A file
+/// that exceeds `limits` is not examined at all; the `LimitError` is
+/// returned instead.
+pub fn examine(
+    bytes: &[u8],
+    file: &str,
+    profile: &CrcProfile,
+    limits: &Limits,
+) -> Result<Vec<Finding>> {
+    let mut findings = vec![];
+    let png = match Png::read_with_crc_profile(bytes, profile, limits) {
+        Ok(png) => {
+            check_structure(bytes, file, limits, &mut findings)?;
+            png
+        }
+        Err(e) if e.is::<LimitError>() => return Err(e),
+        Err(e) => {
+            let parse = e.downcast_ref::<ParseError>();
+            let fix = match parse {
+                Some(ParseError::InvalidCrc { .. } | ParseError::InvalidChunkType { .. }) => {
+                    Some(format!("pngme recover {} fixed.png", file))
+                }
+                Some(parse) => parse.rule().fix_for(file, None),
+                None => None,
+            };
+            findings.push(Finding {
+                severity: Severity::Error,
+                check: parse.map_or("parse", |parse| parse.rule().id),
+                message: e.to_string(),
+                fix,
+            });
+            match recovery::repair_structure(bytes, limits) {
+                Ok((png, _)) => png,
+                Err(e) if e.is::<LimitError>() => return Err(e),
+                Err(_) => recovery::recover(bytes, limits)?.into_png(),
+            }
+        }
+    };
+
+    check_image_data(&png, &mut findings);
+    for warning in png.warnings().into_iter().chain(lint::check(&png)) {
+        findings.push(from_warning(&warning, Severity::Warning, file));
+    }
+    check_known_chunks(&png, file, &mut findings);
+    check_payloads(&png, file, &mut findings);
+
+    findings.sort_by_key(|finding| finding.severity);
+    Ok(findings)
+}
+
+fn from_warning(warning: &Warning, severity: Severity, file: &str) -> Finding {
+    let rule: &Rule = warning.rule();
+    Finding {
+        severity,
+        check: rule.id,
+        message: warning.to_string(),
+        fix: rule.fix_at(file, Some(warning.chunk_type()), warning.offset()),
+    }
+}
+
+/// File-level damage the parser tolerates: a missing IEND or bytes after it.
+fn check_structure(
+    bytes: &[u8],
+    file: &str,
+    limits: &Limits,
+    findings: &mut Vec<Finding>,
+) -> Result<()> {
+    let repairs = match recovery::repair_structure(bytes, limits) {
+        Ok((_, repairs)) => repairs,
+        Err(e) if e.is::<LimitError>() => return Err(e),
+        Err(_) => return Ok(()),
+    };
+    for repair in repairs {
+        findings.push(Finding {
+            severity: Severity::Warning,
+            check: "structure",
+            message: format!("repair would have {}", repair),
+            fix: Some(format!("pngme repair --structure {}", file)),
+        });
+    }
+    Ok(())
+}
+
+/// Bytes of filtered image data IHDR calls for: each row of each Adam7 pass,
+/// or of the whole image, plus its filter type byte.
+pub fn expected_image_bytes(header: &Header) -> u64 {
+    const ADAM7: [(u64, u64, u64, u64); 7] = [
+        (0, 0, 8, 8),
+        (4, 0, 8, 8),
+        (0, 4, 4, 8),
+        (2, 0, 4, 4),
+        (0, 2, 2, 4),
+        (1, 0, 2, 2),
+        (0, 1, 1, 2),
+    ];
+    let (width, height) = (header.width as u64, header.height as u64);
+    let bits = header.bits_per_pixel() as u64;
+    let size = |width: u64, height: u64| {
+        if width == 0 {
+            0
+        } else {
+            height * (1 + (width * bits).div_ceil(8))
+        }
+    };
+    if !header.interlaced {
+        return size(width, height);
+    }
+    ADAM7
+        .iter()
+        .map(|&(x, y, dx, dy)| {
+            size(
+                width.saturating_sub(x).div_ceil(dx),
+                height.saturating_sub(y).div_ceil(dy),
+            )
+        })
+        .sum()
+}
+
+/// Decompresses IDAT and checks it holds exactly the rows IHDR describes,
+/// each starting with a valid filter type.
+fn check_image_data(png: &Png, findings: &mut Vec<Finding>) {
+    let mut error = |check, message: String| {
+        findings.push(Finding {
+            severity: Severity::Error,
+            check,
+            message,
+            fix: None,
+        })
+    };
+    let header = match Header::from_png(png) {
+        Ok(header) => header,
+        Err(e) => return error("ihdr", e.to_string()),
+    };
+    let compressed: Vec<u8> = png
+        .chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().bytes() == *b"IDAT")
+        .flat_map(|chunk| chunk.data().iter().copied())
+        .collect();
+    if compressed.is_empty() {
+        return error("idat", "no image data: the file has no IDAT chunks".into());
+    }
+    // Inflating stops one byte past what IHDR needs, so a small file cannot
+    // claim gigabytes of image data.
+    let expected = expected_image_bytes(&header);
+    let limit = usize::try_from(expected.saturating_add(1)).unwrap_or(usize::MAX);
+    let data = match zlib::decompress_limited(&compressed, limit) {
+        Ok(data) => data,
+        Err(e) if e.is::<zlib::OutputLimit>() => {
+            findings.push(Finding {
+                severity: Severity::Warning,
+                check: "idat",
+                message: format!("image data is more than the {} bytes IHDR needs", expected),
+                fix: None,
+            });
+            return;
+        }
+        Err(e) => return error("idat", format!("image data does not decompress: {}", e)),
+    };
+
+    if (data.len() as u64) < expected {
+        return error(
+            "idat",
+            format!(
+                "image data is {} bytes, IHDR needs {}; the image is cut off",
+                data.len(),
+                expected
+            ),
+        );
+    }
+    if !header.interlaced {
+        let stride = expected / header.height.max(1) as u64;
+        let filter = |row: u64| data.get((row * stride) as usize).copied();
+        if stride > 0 {
+            if let Some((row, filter)) = (0..header.height as u64)
+                .filter_map(|row| Some((row, filter(row)?)))
+                .find(|&(_, filter)| filter > 4)
+            {
+                findings.push(Finding {
+                    severity: Severity::Error,
+                    check: "idat",
+                    message: format!("row {} has invalid filter type {}", row, filter),
+                    fix: None,
+                });
+            }
+        }
+    }
+}
+
+/// Known chunks whose data cannot be right: wrong fixed lengths, malformed
+/// palettes and text chunks that do not decode.
+fn check_known_chunks(png: &Png, file: &str, findings: &mut Vec<Finding>) {
+    for (offset, chunk) in png.chunks_with_offsets() {
+        let chunk_type = chunk.chunk_type().bytes();
+        let length = chunk.data().len();
+        let mut problem = |message: String| {
+            findings.push(Finding {
+                severity: Severity::Warning,
+                check: "known-chunk",
+                message: format!("{} at offset {}: {}", chunk.chunk_type(), offset, message),
+                fix: Some(format!("pngme remove {} --offset {}", file, offset)),
+            })
+        };
+
+        if let Some(&(_, expected)) = FIXED_LENGTHS.iter().find(|(t, _)| **t == chunk_type) {
+            if length != expected {
+                problem(format!("must be {} bytes, not {}", expected, length));
+            }
+        }
+        match &chunk_type {
+            b"PLTE" if length == 0 || length > 768 || length % 3 != 0 => {
+                problem(format!("{} bytes is not 1 to 256 RGB entries", length))
+            }
+            b"tEXt" | b"zTXt" | b"iTXt" => match TextChunk::from_chunk(chunk) {
+                Ok(entry) => findings.extend(
+                    entry
+                        .warnings()
+                        .iter()
+                        .map(|warning| from_warning(warning, Severity::Info, file)),
+                ),
+                Err(e) => problem(e.to_string()),
+            },
+            _ => {}
+        }
+    }
+}
+
+/// pngme payload headers and stripes in chunks that are not part of the
+/// spec, which `decode` would fail on.
+fn check_payloads(png: &Png, file: &str, findings: &mut Vec<Finding>) {
+    for (offset, chunk) in png.chunks_with_offsets() {
+        if chunk.chunk_type().is_known() {
+            continue;
+        }
+        let problem = match PayloadHeader::parse(chunk.data()) {
+            Err(e) => Some(e.to_string()),
+            Ok(Some((header, _))) => header
+                .transforms
+                .iter()
+                .find_map(|&id| transform::by_id(id).err())
+                .map(|e| e.to_string()),
+            Ok(None) => stripe::Stripe::parse(chunk.data())
+                .err()
+                .map(|e| e.to_string()),
+        };
+        if let Some(problem) = problem {
+            findings.push(Finding {
+                severity: Severity::Error,
+                check: "payload",
+                message: format!(
+                    "{} payload at offset {}: {}",
+                    chunk.chunk_type(),
+                    offset,
+                    problem
+                ),
+                fix: Some(format!("pngme remove {} --offset {}", file, offset)),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    /// A 2x2 8-bit grayscale image.
+    fn healthy() -> Png {
+        let mut ihdr = 2u32.to_be_bytes().to_vec();
+        ihdr.extend_from_slice(&2u32.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 0, 0, 0, 0]);
+        Png::from_chunks(vec![
+            chunk("IHDR", &ihdr),
+            chunk("IDAT", &zlib::compress(&[0, 1, 2, 0, 3, 4])),
+            chunk("IEND", b""),
+        ])
+    }
+
+    fn checks(png: &Png) -> Vec<(Severity, &'static str)> {
+        examine(
+            &png.as_bytes(),
+            "a.png",
+            &CrcProfile::Standard,
+            &Limits::default(),
+        )
+        .unwrap()
+        .iter()
+        .map(|finding| (finding.severity, finding.check))
+        .collect()
+    }
+
+    #[test]
+    fn test_healthy_file() {
+        assert_eq!(checks(&healthy()), vec![]);
+    }
+
+    #[test]
+    fn test_findings_are_prioritized() {
+        let mut png = healthy();
+        png.insert_chunk(1, chunk("gAMA", b"\0\0"));
+        png.insert_before_iend(chunk("ruSt", b"\0PMH\x07"));
+        png.append_chunk(chunk("pHYs", &[0; 9]));
+        assert_eq!(
+            checks(&png),
+            vec![
+                (Severity::Error, "payload"),
+                (Severity::Warning, "structure"),
+                (Severity::Warning, "iend-last"),
+                (Severity::Warning, "before-idat"),
+                (Severity::Warning, "known-chunk"),
+            ]
+        );
+
+        let findings = examine(
+            &png.as_bytes(),
+            "a.png",
+            &CrcProfile::Standard,
+            &Limits::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            findings[0].fix.as_deref(),
+            Some("pngme remove a.png --offset 73")
+        );
+    }
+
+    #[test]
+    fn test_damaged_file() {
+        let mut bytes = healthy().as_bytes();
+        let n = bytes.len();
+        bytes[n - 13] ^= 1;
+        let findings = examine(&bytes, "a.png", &CrcProfile::Standard, &Limits::default()).unwrap();
+        assert_eq!(findings[0].check, "crc");
+        assert_eq!(
+            findings[0].fix.as_deref(),
+            Some("pngme recover a.png fixed.png")
+        );
+
+        let findings = examine(&bytes, "a.png", &CrcProfile::None, &Limits::default()).unwrap();
+        assert_eq!(findings, []);
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = Limits {
+            max_chunks: Some(2),
+            ..Limits::default()
+        };
+        let error = examine(
+            &healthy().as_bytes(),
+            "a.png",
+            &CrcProfile::Standard,
+            &limits,
+        )
+        .unwrap_err();
+        assert!(error.is::<LimitError>());
+
+        // Damaged files are held to the limits while they are salvaged too:
+        // with IHDR's CRC broken, parsing stops before the limit is reached,
+        // but recovering the other chunks reaches it.
+        let limits = Limits {
+            max_chunks: Some(1),
+            ..Limits::default()
+        };
+        let mut bytes = healthy().as_bytes();
+        bytes[8 + 12 + 13 - 1] ^= 1;
+        let error = examine(&bytes, "a.png", &CrcProfile::Standard, &limits).unwrap_err();
+        assert!(error.is::<LimitError>());
+    }
+
+    #[test]
+    fn test_image_data() {
+        let mut png = healthy();
+        png.remove_chunk("IDAT").unwrap();
+        png.insert_chunk(1, chunk("IDAT", &zlib::compress(&[0, 1, 2, 0])));
+        assert_eq!(checks(&png), vec![(Severity::Error, "idat")]);
+
+        png.remove_chunk("IDAT").unwrap();
+        png.insert_chunk(1, chunk("IDAT", &zlib::compress(&[0, 1, 2, 9, 3, 4])));
+        assert_eq!(checks(&png), vec![(Severity::Error, "idat")]);
+
+        // Far more data than IHDR needs is not inflated in full.
+        png.remove_chunk("IDAT").unwrap();
+        png.insert_chunk(1, chunk("IDAT", &zlib::compress(&[0; 1 << 20])));
+        assert_eq!(checks(&png), vec![(Severity::Warning, "idat")]);
+
+        let mut ihdr = 0u32.to_be_bytes().to_vec();
+        ihdr.extend_from_slice(&2u32.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 0, 0, 0, 0]);
+        png.remove_chunk("IHDR").unwrap();
+        png.insert_chunk(0, chunk("IHDR", &ihdr));
+        assert!(checks(&png).contains(&(Severity::Error, "ihdr")));
+    }
+
+    #[test]
+    fn test_expected_image_bytes() {
+        let mut header = Header::parse(&[0, 0, 0, 3, 0, 0, 0, 3, 8, 2, 0, 0, 1]).unwrap();
+        // Passes 1 and 4 hold one pixel, 5 two, 6 two rows of one and 7 three;
+        // 2 and 3 are empty.
+        assert_eq!(
+            expected_image_bytes(&header),
+            (1 + 3) + (1 + 3) + (1 + 6) + (2 * (1 + 3)) + (1 + 9)
+        );
+        header.interlaced = false;
+        assert_eq!(expected_image_bytes(&header), 3 * (1 + 9));
+    }
+}
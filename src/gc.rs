@@ -0,0 +1,370 @@
+//! Finding payload chunks that can no longer be read back usefully:
+//! segment runs with gaps, stripes whose payload is incomplete, duplicate
+//! fragments and versions older than the caller wants to keep.
+
+use crate::chunk_type::ChunkType;
+use crate::payload;
+use crate::png::Png;
+use crate::stripe::{self, Stripe};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Display;
+
+/// What `gc` may treat as garbage. Segmented payloads carry no marker of
+/// their own, so their chunk types have to be named.
+#[derive(Debug, Default)]
+pub struct Policy {
+    pub segmented: Vec<ChunkType>,
+    /// Keep this many of the newest versions of each versioned payload;
+    /// `None` keeps them all.
+    pub keep_versions: Option<usize>,
+    /// Treat stripes as garbage when no file in the inventory holds the rest
+    /// of their payload. Off by default, since the inventory only knows the
+    /// files it was given and the missing stripes may be in others.
+    pub incomplete_stripes: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    /// The segment run has gaps, so the payload cannot be reassembled.
+    MissingSegments {
+        missing: Vec<u32>,
+    },
+    DuplicateSegment {
+        sequence: u32,
+    },
+    /// Too short to hold a sequence number.
+    DamagedSegment,
+    /// None of the files hold the other stripes of this payload, listed as
+    /// inclusive ranges.
+    MissingStripes {
+        missing: Vec<(u32, u32)>,
+    },
+    DuplicateStripe {
+        index: u32,
+    },
+    DamagedStripe,
+    StaleVersion {
+        timestamp: u64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Garbage {
+    pub index: usize,
+    pub chunk_type: String,
+    /// Bytes reclaimed by removing the chunk, framing included.
+    pub bytes: usize,
+    pub reason: Reason,
+}
+
+impl Display for Garbage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = |numbers: &[u32]| {
+            numbers
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        write!(
+            f,
+            "chunk {} ({}, {} bytes): ",
+            self.index, self.chunk_type, self.bytes
+        )?;
+        match &self.reason {
+            Reason::MissingSegments { missing } => {
+                write!(f, "segment run is missing segments {}", list(missing))
+            }
+            Reason::DuplicateSegment { sequence } => write!(f, "duplicate segment {}", sequence),
+            Reason::DamagedSegment => write!(f, "segment has no sequence number"),
+            Reason::MissingStripes { missing } => {
+                write!(
+                    f,
+                    "striped payload is missing stripes {}",
+                    stripe::format_ranges(missing)
+                )
+            }
+            Reason::DuplicateStripe { index } => write!(f, "duplicate stripe {}", index),
+            Reason::DamagedStripe => write!(f, "stripe header is damaged"),
+            Reason::StaleVersion { timestamp } => write!(
+                f,
+                "stale version stored {}",
+                crate::time::format_rfc3339(*timestamp)
+            ),
+        }
+    }
+}
+
+/// A striped payload: its chunk type and payload id.
+type PayloadKey = ([u8; 4], [u8; 8]);
+
+/// Which stripes exist across a set of files, so that a payload striped over
+/// several files is only incomplete if no file holds the missing stripes.
+#[derive(Debug, Default)]
+pub struct StripeInventory {
+    /// Stripe count and the stripe indices found.
+    payloads: HashMap<PayloadKey, (u32, BTreeSet<u32>)>,
+}
+
+impl StripeInventory {
+    pub fn add(&mut self, png: &Png) {
+        for chunk in png.chunks() {
+            if let Ok(Some(stripe)) = Stripe::parse(chunk.data()) {
+                self.payloads
+                    .entry((chunk.chunk_type().bytes(), stripe.id))
+                    .or_insert_with(|| (stripe.count, BTreeSet::new()))
+                    .1
+                    .insert(stripe.index);
+            }
+        }
+    }
+
+    fn missing(&self, chunk_type: [u8; 4], id: [u8; 8]) -> Vec<(u32, u32)> {
+        match self.payloads.get(&(chunk_type, id)) {
+            Some((count, present)) => stripe::missing_ranges(*count, present.iter().copied()),
+            None => vec![],
+        }
+    }
+}
+
+/// Garbage chunks in `png`, in chunk order.
+pub fn find(png: &Png, policy: &Policy, stripes: &StripeInventory) -> Vec<Garbage> {
+    let mut garbage = vec![];
+    let mut collect = |index: usize, reason| {
+        let chunk = &png.chunks()[index];
+        garbage.push(Garbage {
+            index,
+            chunk_type: chunk.chunk_type().to_string(),
+            bytes: chunk.data().len() + 12,
+            reason,
+        });
+    };
+
+    for &chunk_type in &policy.segmented {
+        let mut segments = vec![];
+        for (index, chunk) in png.chunks().iter().enumerate() {
+            if chunk.chunk_type() != chunk_type {
+                continue;
+            }
+            match chunk.data().get(..4) {
+                Some(sequence) => {
+                    segments.push((u32::from_be_bytes(sequence.try_into().unwrap()), index))
+                }
+                None => collect(index, Reason::DamagedSegment),
+            }
+        }
+        let mut seen = BTreeSet::new();
+        let mut unique = vec![];
+        for (sequence, index) in segments {
+            if seen.insert(sequence) {
+                unique.push(index);
+            } else {
+                collect(index, Reason::DuplicateSegment { sequence });
+            }
+        }
+        let end = seen.last().map_or(0, |last| last + 1);
+        let missing: Vec<u32> = (0..end).filter(|s| !seen.contains(s)).collect();
+        if !missing.is_empty() {
+            for index in unique {
+                collect(
+                    index,
+                    Reason::MissingSegments {
+                        missing: missing.clone(),
+                    },
+                );
+            }
+        }
+    }
+
+    let mut seen_stripes = BTreeSet::new();
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        if policy.segmented.contains(&chunk.chunk_type()) {
+            continue;
+        }
+        match Stripe::parse(chunk.data()) {
+            Ok(None) => {}
+            Err(_) => collect(index, Reason::DamagedStripe),
+            Ok(Some(stripe)) => {
+                let chunk_type = chunk.chunk_type().bytes();
+                let missing = if policy.incomplete_stripes {
+                    stripes.missing(chunk_type, stripe.id)
+                } else {
+                    vec![]
+                };
+                if !missing.is_empty() {
+                    collect(index, Reason::MissingStripes { missing });
+                } else if !seen_stripes.insert((chunk_type, stripe.id, stripe.index)) {
+                    collect(
+                        index,
+                        Reason::DuplicateStripe {
+                            index: stripe.index,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    if let Some(keep) = policy.keep_versions {
+        let mut done = BTreeSet::new();
+        for chunk_type in png.chunks().iter().map(|chunk| chunk.chunk_type()) {
+            if !done.insert(chunk_type.bytes()) || policy.segmented.contains(&chunk_type) {
+                continue;
+            }
+            let mut versioned: Vec<(u64, usize)> = payload::versions(&png.view(), chunk_type)
+                .iter()
+                .filter_map(|version| Some((version.timestamp?, version.index)))
+                .collect();
+            versioned.sort_by(|a, b| b.cmp(a));
+            for &(timestamp, index) in versioned.iter().skip(keep) {
+                collect(index, Reason::StaleVersion { timestamp });
+            }
+        }
+    }
+
+    garbage.sort_by_key(|garbage| garbage.index);
+    garbage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::payload::encode_versioned_payload;
+    use crate::transform::Options;
+    use std::str::FromStr;
+
+    fn png(chunks: Vec<(&str, Vec<u8>)>) -> Png {
+        let mut all = vec![Chunk::new(
+            ChunkType::from_str("IHDR").unwrap(),
+            vec![0; 13],
+        )];
+        for (chunk_type, data) in chunks {
+            all.push(Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data));
+        }
+        all.push(Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]));
+        Png::from_chunks(all)
+    }
+
+    fn segment(sequence: u32) -> Vec<u8> {
+        [&sequence.to_be_bytes()[..], b"data"].concat()
+    }
+
+    fn reasons(garbage: &[Garbage]) -> Vec<(usize, Reason)> {
+        garbage
+            .iter()
+            .map(|g| (g.index, g.reason.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_segments() {
+        let policy = Policy {
+            segmented: vec![ChunkType::from_str("loGs").unwrap()],
+            ..Policy::default()
+        };
+        let complete = png(vec![("loGs", segment(0)), ("loGs", segment(1))]);
+        assert!(find(&complete, &policy, &StripeInventory::default()).is_empty());
+
+        let broken = png(vec![
+            ("loGs", segment(0)),
+            ("loGs", segment(2)),
+            ("loGs", segment(2)),
+            ("loGs", vec![1]),
+        ]);
+        let garbage = find(&broken, &policy, &StripeInventory::default());
+        let missing = Reason::MissingSegments { missing: vec![1] };
+        assert_eq!(
+            reasons(&garbage),
+            [
+                (1, missing.clone()),
+                (2, missing),
+                (3, Reason::DuplicateSegment { sequence: 2 }),
+                (4, Reason::DamagedSegment),
+            ]
+        );
+        assert_eq!(garbage[0].bytes, 20);
+        assert_eq!(
+            garbage[0].to_string(),
+            "chunk 1 (loGs, 20 bytes): segment run is missing segments 1"
+        );
+    }
+
+    #[test]
+    fn test_stripes() {
+        let stripes = stripe::split(b"abcdefghij", 4);
+        let first = png(vec![
+            ("ruSt", stripes[0].to_bytes()),
+            ("ruSt", stripes[0].to_bytes()),
+            ("ruSt", b"\0PMS\x01".to_vec()),
+        ]);
+        let second = png(vec![("ruSt", stripes[1].to_bytes())]);
+        let mut policy = Policy::default();
+
+        let mut inventory = StripeInventory::default();
+        inventory.add(&first);
+        // The other stripes may be in files that were not listed.
+        assert_eq!(
+            reasons(&find(&first, &policy, &inventory)),
+            [
+                (2, Reason::DuplicateStripe { index: 0 }),
+                (3, Reason::DamagedStripe)
+            ]
+        );
+
+        policy.incomplete_stripes = true;
+        assert_eq!(
+            reasons(&find(&first, &policy, &inventory)),
+            [
+                (
+                    1,
+                    Reason::MissingStripes {
+                        missing: vec![(1, 2)]
+                    }
+                ),
+                (
+                    2,
+                    Reason::MissingStripes {
+                        missing: vec![(1, 2)]
+                    }
+                ),
+                (3, Reason::DamagedStripe),
+            ]
+        );
+
+        let third = png(vec![("ruSt", stripes[2].to_bytes())]);
+        inventory.add(&second);
+        inventory.add(&third);
+        assert_eq!(
+            reasons(&find(&first, &policy, &inventory)),
+            [
+                (2, Reason::DuplicateStripe { index: 0 }),
+                (3, Reason::DamagedStripe)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stale_versions() {
+        let version = |timestamp| {
+            encode_versioned_payload(b"v", &[], &Options::default(), Some(timestamp)).unwrap()
+        };
+        let png = png(vec![
+            ("ruSt", b"legacy".to_vec()),
+            ("ruSt", version(300)),
+            ("ruSt", version(100)),
+            ("ruSt", version(200)),
+        ]);
+        let mut policy = Policy::default();
+        assert!(find(&png, &policy, &StripeInventory::default()).is_empty());
+
+        policy.keep_versions = Some(1);
+        assert_eq!(
+            reasons(&find(&png, &policy, &StripeInventory::default())),
+            [
+                (3, Reason::StaleVersion { timestamp: 100 }),
+                (4, Reason::StaleVersion { timestamp: 200 }),
+            ]
+        );
+    }
+}
@@ -0,0 +1,88 @@
+//! File name patterns for commands that take a set of files, such as
+//! `decode --gather 'out*.png'`, so they work even when the shell does not
+//! expand the pattern.
+
+use crate::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Whether `name` matches `pattern`, where `*` matches any run of characters
+/// and `?` matches exactly one.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was and how much of the name it has swallowed.
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The files matching `pattern`, sorted. Wildcards are only allowed in the
+/// file name, not in the directories leading to it.
+pub fn expand(pattern: &Path) -> Result<Vec<PathBuf>> {
+    let name = pattern
+        .file_name()
+        .ok_or_else(|| format!("invalid file pattern {}", pattern.display()))?;
+    let dir = match pattern.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    // A name that is not valid Unicode, such as a UTF-16 file name with an
+    // unpaired surrogate, names a single file.
+    let name = match name.to_str() {
+        Some(name) if name.contains(['*', '?']) => name,
+        _ => return Ok(vec![pattern.to_path_buf()]),
+    };
+
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir).map_err(|e| format!("reading {}: {}", dir.display(), e))? {
+        let entry = entry?;
+        if matches(name, &entry.file_name().to_string_lossy()) && entry.file_type()?.is_file() {
+            paths.push(pattern.with_file_name(entry.file_name()));
+        }
+    }
+    if paths.is_empty() {
+        return Err(format!("no files match {}", pattern.display()).into());
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("out*.png", "out0.png"));
+        assert!(matches("out*.png", "out.png"));
+        assert!(matches("out?.png", "out7.png"));
+        assert!(!matches("out?.png", "out12.png"));
+        assert!(matches("*.png", "a.b.png"));
+        assert!(!matches("*.png", "a.png.bak"));
+        assert!(matches("*a*b*", "xxaxxbxx"));
+        assert!(!matches("*a*b", "xxbxxa"));
+        assert!(matches("*", ""));
+        assert!(!matches("a", ""));
+    }
+}
@@ -0,0 +1,153 @@
+//! Diagram sources for the chunk layout of a file, as `scan --dot` and
+//! `scan --mermaid` print them.
+//!
+//! Each chunk is a box labelled with its type, data length and category and
+//! colored by category, chained in file order. Consecutive chunks of the
+//! same type, typically a long run of IDAT, are drawn as one box with their
+//! count and total length so the diagram stays readable.
+
+use crate::chunk_type::ChunkType;
+use crate::index::ChunkEntry;
+
+/// A run of consecutive chunks of one type.
+#[derive(Debug, PartialEq, Eq)]
+struct Node {
+    chunk_type: ChunkType,
+    count: usize,
+    length: u64,
+}
+
+impl Node {
+    fn label(&self, newline: &str) -> String {
+        let count = match self.count {
+            1 => String::new(),
+            n => format!(" ×{}", n),
+        };
+        format!(
+            "{}{}{newline}{} bytes{newline}{}",
+            self.chunk_type,
+            count,
+            self.length,
+            self.chunk_type.category(),
+        )
+    }
+}
+
+/// Fill colors for "critical", "ancillary" and "private" chunks.
+const COLORS: [(&str, &str); 3] = [
+    ("critical", "#f4cccc"),
+    ("ancillary", "#d9ead3"),
+    ("private", "#cfe2f3"),
+];
+
+fn color(category: &str) -> &'static str {
+    COLORS
+        .iter()
+        .find(|(name, _)| *name == category)
+        .map_or("#ffffff", |(_, color)| color)
+}
+
+fn nodes<'a>(entries: impl IntoIterator<Item = &'a ChunkEntry>) -> Vec<Node> {
+    let mut nodes: Vec<Node> = vec![];
+    for entry in entries {
+        match nodes.last_mut() {
+            Some(node) if node.chunk_type == entry.chunk_type => {
+                node.count += 1;
+                node.length += entry.length as u64;
+            }
+            _ => nodes.push(Node {
+                chunk_type: entry.chunk_type,
+                count: 1,
+                length: entry.length as u64,
+            }),
+        }
+    }
+    nodes
+}
+
+/// The chunk layout as a Graphviz graph.
+pub fn dot<'a>(entries: impl IntoIterator<Item = &'a ChunkEntry>) -> String {
+    let nodes = nodes(entries);
+    let mut out =
+        String::from("digraph png {\n    rankdir=LR;\n    node [shape=box, style=filled];\n");
+    for (i, node) in nodes.iter().enumerate() {
+        out += &format!(
+            "    c{} [label=\"{}\", fillcolor=\"{}\"];\n",
+            i,
+            node.label("\\n"),
+            color(node.chunk_type.category())
+        );
+    }
+    for i in 1..nodes.len() {
+        out += &format!("    c{} -> c{};\n", i - 1, i);
+    }
+    out + "}\n"
+}
+
+/// The chunk layout as a Mermaid flowchart.
+pub fn mermaid<'a>(entries: impl IntoIterator<Item = &'a ChunkEntry>) -> String {
+    let nodes = nodes(entries);
+    let mut out = String::from("flowchart LR\n");
+    for (i, node) in nodes.iter().enumerate() {
+        out += &format!(
+            "    c{}[\"{}\"]:::{}\n",
+            i,
+            node.label("<br/>"),
+            node.chunk_type.category()
+        );
+    }
+    for i in 1..nodes.len() {
+        out += &format!("    c{} --> c{}\n", i - 1, i);
+    }
+    for (category, color) in COLORS {
+        out += &format!("    classDef {} fill:{}\n", category, color);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn entries() -> Vec<ChunkEntry> {
+        [
+            ("IHDR", 13),
+            ("IDAT", 100),
+            ("IDAT", 50),
+            ("ruSt", 2),
+            ("IEND", 0),
+        ]
+        .iter()
+        .map(|&(chunk_type, length)| ChunkEntry {
+            offset: 0,
+            chunk_type: ChunkType::from_str(chunk_type).unwrap(),
+            length,
+            crc: 0,
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_dot() {
+        let dot = dot(&entries());
+        assert!(dot.starts_with("digraph png {\n"));
+        assert!(dot.contains(
+            "    c1 [label=\"IDAT ×2\\n150 bytes\\ncritical\", fillcolor=\"#f4cccc\"];\n"
+        ));
+        assert!(
+            dot.contains("    c2 [label=\"ruSt\\n2 bytes\\nprivate\", fillcolor=\"#cfe2f3\"];\n")
+        );
+        assert!(dot.contains("    c2 -> c3;\n"));
+        assert!(!dot.contains("c4"));
+    }
+
+    #[test]
+    fn test_mermaid() {
+        let mermaid = mermaid(&entries());
+        assert!(mermaid.starts_with("flowchart LR\n"));
+        assert!(mermaid.contains("    c0[\"IHDR<br/>13 bytes<br/>critical\"]:::critical\n"));
+        assert!(mermaid.contains("    c0 --> c1\n"));
+        assert!(mermaid.ends_with("    classDef private fill:#cfe2f3\n"));
+    }
+}
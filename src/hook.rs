@@ -0,0 +1,289 @@
+//! Chunk policy enforced by `pngme hook pre-commit`.
+//!
+//! A repository describes its policy in `.pngme-policy.json` at its root:
+//!
+//! ```json
+//! {"strip": ["tIME", "tEXt"], "allow": ["sRGB", "pHYs"]}
+//! ```
+//!
+//! Chunks of a `strip` type are removed from the staged content of PNGs,
+//! which is then restaged, so values that change on every export never reach
+//! history. Unstaged edits stay out of the commit: the working tree copy is
+//! only rewritten when it matches what was staged.
+//! When `allow` is given, any other ancillary chunk blocks the commit.
+//! Critical chunks are always allowed, since the image cannot do without
+//! them.
+
+use crate::chunk_type::ChunkType;
+use crate::json::Value;
+use crate::png::Png;
+use crate::Result;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+/// Where the policy is read from, relative to the repository root.
+pub const POLICY_FILE: &str = ".pngme-policy.json";
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Policy {
+    pub strip: Vec<ChunkType>,
+    /// Ancillary chunk types a file may keep; `None` allows every type.
+    pub allow: Option<Vec<ChunkType>>,
+}
+
+/// What applying a policy did to one file.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Outcome {
+    /// Types of the chunks removed, one entry per chunk.
+    pub stripped: Vec<ChunkType>,
+    /// Ancillary types the policy does not allow, each listed once.
+    pub disallowed: Vec<ChunkType>,
+}
+
+impl Policy {
+    pub fn load(path: &Path) -> Result<Policy> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("reading policy {}: {}", path.display(), e))?;
+        Policy::from_json(&Value::parse(&json)?)
+            .map_err(|e| format!("policy {}: {}", path.display(), e).into())
+    }
+
+    pub fn from_json(value: &Value) -> Result<Policy> {
+        let types = |key: &str| -> Result<Option<Vec<ChunkType>>> {
+            let Some(list) = value.get(key) else {
+                return Ok(None);
+            };
+            list.as_array()
+                .ok_or_else(|| format!("{:?} must be a list of chunk types", key))?
+                .iter()
+                .map(|item| {
+                    let name = item
+                        .as_str()
+                        .ok_or_else(|| format!("{:?} must be a list of chunk types", key))?;
+                    ChunkType::from_str(name)
+                })
+                .collect::<Result<_>>()
+                .map(Some)
+        };
+        if value.as_object().is_none() {
+            return Err("policy must be an object".into());
+        }
+        Ok(Policy {
+            strip: types("strip")?.unwrap_or_default(),
+            allow: types("allow")?,
+        })
+    }
+
+    /// Removes the chunks to strip from `png` and reports the ones that are
+    /// not allowed.
+    pub fn apply(&self, png: &mut Png) -> Outcome {
+        let mut outcome = Outcome::default();
+        for index in (0..png.chunks().len()).rev() {
+            let chunk_type = png.chunks()[index].chunk_type();
+            if self.strip.contains(&chunk_type) {
+                png.remove_chunk_at(index);
+                outcome.stripped.insert(0, chunk_type);
+            }
+        }
+
+        if let Some(allow) = &self.allow {
+            for chunk in png.chunks() {
+                let chunk_type = chunk.chunk_type();
+                if !chunk_type.is_critical()
+                    && !allow.contains(&chunk_type)
+                    && !outcome.disallowed.contains(&chunk_type)
+                {
+                    outcome.disallowed.push(chunk_type);
+                }
+            }
+        }
+        outcome
+    }
+}
+
+/// Runs git in `dir`, returning its output or its error message. Paths are
+/// taken literally, never as patterns.
+pub fn git<S: AsRef<OsStr>>(dir: &Path, args: &[S]) -> Result<Vec<u8>> {
+    git_with_input(dir, args, None)
+}
+
+/// Runs git like [`git`], feeding it `input` on stdin.
+pub fn git_with_input<S: AsRef<OsStr>>(
+    dir: &Path,
+    args: &[S],
+    input: Option<&[u8]>,
+) -> Result<Vec<u8>> {
+    let mut child = Command::new("git")
+        .current_dir(dir)
+        .arg("--literal-pathspecs")
+        .args(args)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input)?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let args: Vec<_> = args
+            .iter()
+            .map(|arg| arg.as_ref().to_string_lossy())
+            .collect();
+        Err(format!(
+            "git {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))?
+    }
+    Ok(output.stdout)
+}
+
+/// A PNG as staged in the index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StagedFile {
+    /// The file mode, such as `100644`.
+    pub mode: String,
+    /// The object id of the staged content.
+    pub blob: String,
+    /// The path relative to the repository root.
+    pub path: PathBuf,
+}
+
+/// The PNG files in the NUL-separated output of
+/// `git diff --cached --raw --no-abbrev -z`, where each entry is a
+/// `:old-mode new-mode old-id new-id status` field followed by its path, or
+/// by the old and new path for renames and copies.
+pub fn staged_pngs(output: &[u8]) -> Vec<StagedFile> {
+    let mut fields = output.split(|&byte| byte == 0);
+    let mut files = vec![];
+    while let Some(entry) = fields.next() {
+        let entry = String::from_utf8_lossy(entry);
+        let parts: Vec<&str> = entry.trim_start_matches(':').split(' ').collect();
+        let [_, mode, _, blob, status] = parts[..] else {
+            continue;
+        };
+        let paths = if status.starts_with(['R', 'C']) { 2 } else { 1 };
+        let Some(path) = fields.by_ref().take(paths).last() else {
+            break;
+        };
+        let path = path_from_bytes(path);
+        if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png"))
+        {
+            files.push(StagedFile {
+                mode: mode.to_string(),
+                blob: blob.to_string(),
+                path,
+            });
+        }
+    }
+    files
+}
+
+/// Git paths are bytes; on Unix they map to paths exactly.
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// The content staged for `file`.
+pub fn read_staged(root: &Path, file: &StagedFile) -> Result<Vec<u8>> {
+    git(root, &["cat-file", "blob", &file.blob])
+}
+
+/// Replaces what is staged for `file` with `content`, leaving the working
+/// tree alone.
+pub fn restage(root: &Path, file: &StagedFile, content: &[u8]) -> Result<()> {
+    let blob = git_with_input(
+        root,
+        &["hash-object", "-w", "--no-filters", "--stdin"],
+        Some(content),
+    )?;
+    let blob = String::from_utf8_lossy(&blob).trim().to_string();
+    git(
+        root,
+        &[
+            OsStr::new("update-index"),
+            OsStr::new("--cacheinfo"),
+            OsStr::new(&file.mode),
+            OsStr::new(&blob),
+            file.path.as_os_str(),
+        ],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    fn chunk_type(name: &str) -> ChunkType {
+        ChunkType::from_str(name).unwrap()
+    }
+
+    #[test]
+    fn test_apply() {
+        let policy =
+            Policy::from_json(&Value::parse(r#"{"strip": ["tIME"], "allow": ["sRGB"]}"#).unwrap())
+                .unwrap();
+        let mut png = Png::from_chunks(
+            [
+                "IHDR", "tIME", "sRGB", "ruSt", "IDAT", "ruSt", "tIME", "IEND",
+            ]
+            .iter()
+            .map(|name| Chunk::new(chunk_type(name), vec![]))
+            .collect(),
+        );
+
+        let outcome = policy.apply(&mut png);
+        assert_eq!(outcome.stripped, [chunk_type("tIME"), chunk_type("tIME")]);
+        assert_eq!(outcome.disallowed, [chunk_type("ruSt")]);
+        assert_eq!(png.chunks().len(), 6);
+
+        assert_eq!(Policy::default().apply(&mut png), Outcome::default());
+    }
+
+    #[test]
+    fn test_from_json_rejects_bad_policies() {
+        for json in [r#"[]"#, r#"{"strip": "tIME"}"#, r#"{"allow": ["tIM"]}"#] {
+            assert!(Policy::from_json(&Value::parse(json).unwrap()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_staged_pngs() {
+        let output = b":000000 100644 0000 1111 A\0a.png\0\
+                       :100644 100755 2222 3333 R087\0old.png\0docs/b.PNG\0\
+                       :100644 100644 4444 5555 M\0c.txt\0";
+        let staged = |mode: &str, blob: &str, path: &str| StagedFile {
+            mode: mode.to_string(),
+            blob: blob.to_string(),
+            path: PathBuf::from(path),
+        };
+        assert_eq!(
+            staged_pngs(output),
+            [
+                staged("100644", "1111", "a.png"),
+                staged("100755", "3333", "docs/b.PNG"),
+            ]
+        );
+        assert!(staged_pngs(b"").is_empty());
+    }
+}
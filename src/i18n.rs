@@ -0,0 +1,891 @@
+//! The message catalog for errors and help text shown to users.
+//!
+//! Every error that reaches `main` is reported with a stable code such as
+//! `PNGME-0011`, which scripts can match on whatever language the message
+//! itself is printed in. Messages are templates with `{0}`, `{1}`, ...
+//! placeholders, filled in from the error's arguments. Errors that are not
+//! in the catalog yet are reported as [`OTHER`] with their English text.
+
+use crate::json::Value;
+use crate::limits::LimitError;
+use crate::png::ParseError;
+use crate::zlib::OutputLimit;
+use crate::Error;
+use std::fmt::Display;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Lang {
+    #[default]
+    En,
+    Zh,
+}
+
+impl Lang {
+    /// `--lang` when given, otherwise the first of `LC_ALL`, `LC_MESSAGES`
+    /// and `LANG` that is set, falling back to English.
+    pub fn detect(flag: Option<Lang>) -> Lang {
+        flag.or_else(|| {
+            ["LC_ALL", "LC_MESSAGES", "LANG"]
+                .iter()
+                .filter_map(|name| std::env::var(name).ok())
+                .find(|value| !value.is_empty())
+                .map(|locale| locale.parse().unwrap_or_default())
+        })
+        .unwrap_or_default()
+    }
+}
+
+impl FromStr for Lang {
+    type Err = String;
+
+    /// Accepts a language code or a POSIX locale such as `zh_CN.UTF-8`.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let language = s.split(['_', '-', '.', '@']).next().unwrap_or_default();
+        match language.to_ascii_lowercase().as_str() {
+            "en" | "c" | "posix" => Ok(Lang::En),
+            "zh" => Ok(Lang::Zh),
+            _ => Err(format!("expected en or zh, not {:?}", s)),
+        }
+    }
+}
+
+/// A catalog entry. Codes are never reused or renumbered.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Message {
+    pub code: u16,
+    pub en: &'static str,
+    pub zh: &'static str,
+}
+
+impl Message {
+    /// The code as printed, e.g. `PNGME-0042`.
+    pub fn code(&self) -> String {
+        format!("PNGME-{:04}", self.code)
+    }
+
+    pub fn template(&self, lang: Lang) -> &'static str {
+        match lang {
+            Lang::En => self.en,
+            Lang::Zh => self.zh,
+        }
+    }
+
+    /// The template with each placeholder replaced by its argument, in one
+    /// pass so that braces inside arguments are left alone. Placeholders
+    /// without an argument stay as they are.
+    pub fn format(&self, lang: Lang, args: &[String]) -> String {
+        let mut rest = self.template(lang);
+        let mut text = String::with_capacity(rest.len());
+        while let Some(open) = rest.find('{') {
+            text.push_str(&rest[..open]);
+            rest = &rest[open..];
+            let placeholder = rest.find('}').and_then(|close| {
+                let index: usize = rest[1..close].parse().ok()?;
+                Some((close, args.get(index)?))
+            });
+            match placeholder {
+                Some((close, arg)) => {
+                    text.push_str(arg);
+                    rest = &rest[close + 1..];
+                }
+                None => {
+                    text.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        text.push_str(rest);
+        text
+    }
+}
+
+pub const OTHER: Message = Message {
+    code: 1,
+    en: "{0}",
+    zh: "{0}",
+};
+pub const IO: Message = Message {
+    code: 2,
+    en: "I/O error: {0}",
+    zh: "输入/输出错误：{0}",
+};
+pub const INVALID_SIGNATURE: Message = Message {
+    code: 10,
+    en: "Invalid header",
+    zh: "文件头无效",
+};
+pub const TRUNCATED: Message = Message {
+    code: 11,
+    en: "Truncated chunk at offset {0}",
+    zh: "偏移 {0} 处的数据块不完整",
+};
+pub const INVALID_CHUNK_TYPE: Message = Message {
+    code: 12,
+    en: "Invalid chunk type at offset {0}",
+    zh: "偏移 {0} 处的数据块类型无效",
+};
+pub const INVALID_CRC: Message = Message {
+    code: 13,
+    en: "Invalid crc for {0} chunk at offset {1}",
+    zh: "偏移 {1} 处的 {0} 数据块 CRC 校验失败",
+};
+pub const CHUNK_NOT_FOUND: Message = Message {
+    code: 20,
+    en: "Not Found",
+    zh: "未找到数据块",
+};
+pub const TYPE_CODE_LETTERS: Message = Message {
+    code: 21,
+    en:
+        "type codes are restricted to consist of uppercase and lowercase ASCII letters(A-Z and a-z)",
+    zh: "类型代码只能由大小写 ASCII 字母（A-Z 和 a-z）组成",
+};
+pub const TYPE_CODE_LENGTH: Message = Message {
+    code: 22,
+    en: "type codes are restricted to 4 characters",
+    zh: "类型代码必须是 4 个字符",
+};
+pub const CHUNK_CRC: Message = Message {
+    code: 23,
+    en: "Invalid crc checks",
+    zh: "CRC 校验失败",
+};
+pub const NO_CHUNK_AT_OFFSET: Message = Message {
+    code: 24,
+    en: "no chunk starts at offset {0}",
+    zh: "偏移 {0} 处没有数据块",
+};
+pub const WARNINGS_AS_ERRORS: Message = Message {
+    code: 30,
+    en: "{0}: {1} warnings treated as errors",
+    zh: "{0}：{1} 个警告被视为错误",
+};
+pub const READ_ONLY: Message = Message {
+    code: 31,
+    en: "{0} writes files, which --read-only forbids",
+    zh: "{0} 会写入文件，--read-only 模式下不允许",
+};
+pub const READ_ONLY_WRITE: Message = Message {
+    code: 38,
+    en: "--read-only forbids writing {0}",
+    zh: "--read-only 模式下不允许写入 {0}",
+};
+pub const CHUNK_PROBLEMS: Message = Message {
+    code: 32,
+    en: "{0} chunk problems found",
+    zh: "发现 {0} 个数据块问题",
+};
+pub const DOCTOR_ERRORS: Message = Message {
+    code: 33,
+    en: "{0}: {1} errors found",
+    zh: "{0}：发现 {1} 个错误",
+};
+pub const BASELINE_CHANGED: Message = Message {
+    code: 34,
+    en: "{0} files differ from the baseline",
+    zh: "{0} 个文件与基线不同",
+};
+pub const HOOK_BLOCKED: Message = Message {
+    code: 35,
+    en: "{0} staged PNG files hold chunks the policy does not allow",
+    zh: "{0} 个已暂存的 PNG 文件包含策略不允许的数据块",
+};
+pub const VERIFY_FAILED: Message = Message {
+    code: 36,
+    en: "{0} files failed verification",
+    zh: "{0} 个文件校验失败",
+};
+pub const FILE_SHA256: Message = Message {
+    code: 37,
+    en: "file sha256 is {0}, expected {1}",
+    zh: "文件的 sha256 为 {0}，应为 {1}",
+};
+pub const FILE_TOO_LARGE: Message = Message {
+    code: 40,
+    en: "file is larger than the --max-file-size limit of {0} bytes",
+    zh: "文件超过 --max-file-size 限制的 {0} 字节",
+};
+pub const TOO_MANY_CHUNKS: Message = Message {
+    code: 41,
+    en: "more than the --max-chunks limit of {0} chunks; stopped at offset {1}",
+    zh: "数据块超过 --max-chunks 限制的 {0} 个；在偏移 {1} 处停止",
+};
+pub const TIMED_OUT: Message = Message {
+    code: 42,
+    en: "parsing took longer than the --timeout of {0} seconds; stopped at offset {1}",
+    zh: "解析时间超过 --timeout 限制的 {0} 秒；在偏移 {1} 处停止",
+};
+pub const OUTPUT_LIMIT: Message = Message {
+    code: 43,
+    en: "decompressed data exceeds {0} bytes",
+    zh: "解压后的数据超过 {0} 字节",
+};
+pub const PAYLOAD_CORRUPT: Message = Message {
+    code: 50,
+    en: "payload is corrupt: its checksum is {1}, but {0} was stored with it",
+    zh: "载荷已损坏：校验和为 {1}，但存储的校验和为 {0}",
+};
+pub const PAYLOAD_HEADER_TRUNCATED: Message = Message {
+    code: 51,
+    en: "truncated payload header",
+    zh: "载荷头不完整",
+};
+pub const PAYLOAD_HEADER_VERSION: Message = Message {
+    code: 52,
+    en: "unsupported payload header version {0}",
+    zh: "不支持的载荷头版本 {0}",
+};
+pub const DICTIONARY_MISSING: Message = Message {
+    code: 53,
+    en: "payload was compressed with dictionary {0}; pass it with --dict",
+    zh: "载荷使用字典 {0} 压缩；请用 --dict 指定该字典",
+};
+pub const DICTIONARY_MISMATCH: Message = Message {
+    code: 54,
+    en: "payload was compressed with dictionary {0}, not {1}",
+    zh: "载荷使用字典 {0} 压缩，而不是 {1}",
+};
+pub const NO_VERSIONED_PAYLOAD: Message = Message {
+    code: 55,
+    en: "no versioned {0} payload stored by {1}",
+    zh: "{1} 之前没有存储带版本的 {0} 载荷",
+};
+pub const SCHEMA_MISMATCH: Message = Message {
+    code: 56,
+    en: "payload does not match the schema ({0} violations)",
+    zh: "载荷不符合模式（{0} 处违例）",
+};
+pub const PAYLOAD_SHA256: Message = Message {
+    code: 57,
+    en: "payload sha256 is {0}, expected {1}",
+    zh: "载荷的 sha256 为 {0}，应为 {1}",
+};
+pub const UNKNOWN_TRANSFORM: Message = Message {
+    code: 58,
+    en: "unknown transform {0}",
+    zh: "未知的转换 {0}",
+};
+pub const UNKNOWN_TRANSFORM_ID: Message = Message {
+    code: 59,
+    en: "unknown transform id {0}",
+    zh: "未知的转换编号 {0}",
+};
+
+/// Every entry, for checking that codes stay unique.
+pub const CATALOG: &[&Message] = &[
+    &OTHER,
+    &IO,
+    &INVALID_SIGNATURE,
+    &TRUNCATED,
+    &INVALID_CHUNK_TYPE,
+    &INVALID_CRC,
+    &CHUNK_NOT_FOUND,
+    &TYPE_CODE_LETTERS,
+    &TYPE_CODE_LENGTH,
+    &CHUNK_CRC,
+    &NO_CHUNK_AT_OFFSET,
+    &WARNINGS_AS_ERRORS,
+    &READ_ONLY,
+    &CHUNK_PROBLEMS,
+    &DOCTOR_ERRORS,
+    &BASELINE_CHANGED,
+    &HOOK_BLOCKED,
+    &VERIFY_FAILED,
+    &FILE_SHA256,
+    &READ_ONLY_WRITE,
+    &FILE_TOO_LARGE,
+    &TOO_MANY_CHUNKS,
+    &TIMED_OUT,
+    &OUTPUT_LIMIT,
+    &PAYLOAD_CORRUPT,
+    &PAYLOAD_HEADER_TRUNCATED,
+    &PAYLOAD_HEADER_VERSION,
+    &DICTIONARY_MISSING,
+    &DICTIONARY_MISMATCH,
+    &NO_VERSIONED_PAYLOAD,
+    &SCHEMA_MISMATCH,
+    &PAYLOAD_SHA256,
+    &UNKNOWN_TRANSFORM,
+    &UNKNOWN_TRANSFORM_ID,
+];
+
+/// Help text for the command line in languages other than English, keyed
+/// by its English text as written in the doc comments of [`crate::args`],
+/// the way gettext keys messages. A test checks that every help text has an
+/// entry, so new options cannot ship untranslated.
+pub const HELP: &[(&str, &str)] = &[
+    (
+        "Options shared by every subcommand",
+        "所有子命令共用的选项",
+    ),
+    (
+        "Print help information",
+        "打印帮助信息",
+    ),
+    (
+        "Print version information",
+        "打印版本信息",
+    ),
+    (
+        "Size in bytes of the read and write buffers used for file I/O",
+        "文件读写缓冲区的大小（字节）",
+    ),
+    (
+        "Fail instead of continuing when a warning is reported",
+        "报告警告时直接失败，而不是继续执行",
+    ),
+    (
+        "Print a diff of the chunk list a command would write instead of writing it",
+        "打印命令将要写入的数据块列表的差异，而不实际写入",
+    ),
+    (
+        "How to check chunk CRCs when reading: standard, none, or a 0x-prefixed CRC-32 polynomial used by a non-standard tool. Files are always written with standard CRCs",
+        "读取时如何校验数据块 CRC：standard、none，或非标准工具使用的以 0x 开头的 CRC-32 多项式。写入文件时始终使用标准 CRC",
+    ),
+    (
+        "Print how long parsing, CRCs, compression and writing took to stderr; needs a build with the perf-metrics feature",
+        "将解析、CRC、压缩和写入各自耗时打印到 stderr；需要启用 perf-metrics 特性构建",
+    ),
+    (
+        "Language for error messages and help text: en or zh [default: from LC_ALL, LC_MESSAGES or LANG]. Error codes are the same in every language",
+        "错误信息和帮助文本的语言：en 或 zh [默认：取自 LC_ALL、LC_MESSAGES 或 LANG]。错误码在各语言中相同",
+    ),
+    (
+        "Report errors as text, or as one JSON object per error on stderr with code, message, file, offset and chunk_type fields",
+        "以文本报告错误，或在 stderr 上为每个错误输出一个 JSON 对象，包含 code、message、file、offset 和 chunk_type 字段",
+    ),
+    (
+        "Refuse to run commands that would write or replace files, for inspection-only use [default: set when $PNGME_READ_ONLY is non-empty and not 0]",
+        "拒绝运行会写入或替换文件的命令，用于只读检查 [默认：$PNGME_READ_ONLY 非空且不为 0 时启用]",
+    ),
+    (
+        "Refuse input files larger than this many bytes",
+        "拒绝大于此字节数的输入文件",
+    ),
+    (
+        "Refuse input files with more than this many chunks",
+        "拒绝数据块多于此数量的输入文件",
+    ),
+    (
+        "Give up parsing an input file after this many seconds",
+        "解析输入文件超过此秒数后放弃",
+    ),
+    (
+        "Keep chunk indexes in this directory so that scan and extract on an unchanged file skip parsing it; with --read-only, indexes already there are used but none are added [default: $PNGME_INDEX_CACHE]",
+        "将数据块索引保存在此目录中，使 scan 和 extract 对未改动的文件跳过解析；使用 --read-only 时只使用已有索引，不再添加 [默认：$PNGME_INDEX_CACHE]",
+    ),
+    (
+        "Add a chunk from TYPE=MESSAGE, or TYPE@FILE to read the message from a file; repeat to add several chunks in one write",
+        "从 TYPE=MESSAGE 添加数据块，或用 TYPE@FILE 从文件读取消息；可重复以一次写入多个数据块",
+    ),
+    (
+        "File to write instead of modifying FILE_PATH, for use with --chunk",
+        "与 --chunk 一起使用，写入此文件而不修改 FILE_PATH",
+    ),
+    (
+        "Spread the payload over copies of FILE_PATH named by this template, with %d replaced by the stripe number, e.g. out%d.png",
+        "将载荷分散到按此模板命名的 FILE_PATH 副本中，%d 替换为分片编号，例如 out%d.png",
+    ),
+    (
+        "Largest number of payload bytes per stripe",
+        "每个分片最多容纳的载荷字节数",
+    ),
+    (
+        "Expand {variables} in the message: {date}, {timestamp}, {user}, {git_sha}, {git_branch}, {env:NAME}, {sha256:PATH}",
+        "展开消息中的 {变量}：{date}、{timestamp}、{user}、{git_sha}、{git_branch}、{env:NAME}、{sha256:PATH}",
+    ),
+    (
+        "Transform the message before storing it (compress, base64); repeat to chain",
+        "存储前变换消息（compress、base64）；可重复以串联",
+    ),
+    (
+        "Compress the message before any --transform stages: none, deflate, or auto to keep whichever of the two gives the smaller chunk",
+        "在所有 --transform 阶段之前压缩消息：none、deflate，或 auto 以保留两者中较小的数据块",
+    ),
+    (
+        "Preset dictionary file for the compress transform",
+        "compress 变换使用的预设字典文件",
+    ),
+    (
+        "Drop unknown unsafe-to-copy chunks when critical data is modified",
+        "修改关键数据时丢弃未知的不可安全复制数据块",
+    ),
+    (
+        "Refuse to store the message unless it is JSON matching this JSON Schema",
+        "除非消息是符合此 JSON Schema 的 JSON，否则拒绝存储",
+    ),
+    (
+        "Timestamp the payload so earlier payloads of the same type stay readable as history; decode then reads the newest one",
+        "为载荷加上时间戳，使同类型的早期载荷作为历史仍可读取；decode 读取最新的一个",
+    ),
+    (
+        "Line endings to store text messages with: lf, crlf or preserve",
+        "存储文本消息时使用的换行符：lf、crlf 或 preserve",
+    ),
+    (
+        "Warn when ancillary chunks would make up more than this percentage of the written file",
+        "当辅助数据块在写入文件中所占比例将超过此百分比时发出警告",
+    ),
+    (
+        "Treat FILE_PATH as a file name pattern such as 'out*.png' and reassemble a payload striped across the matching files",
+        "将 FILE_PATH 视为文件名模式（如 'out*.png'），并重新拼合分散在匹配文件中的载荷",
+    ),
+    (
+        "Read the newest versioned payload stored no later than this date (YYYY-MM-DD, meaning the end of that day, or YYYY-MM-DDTHH:MM:SSZ)",
+        "读取不晚于此日期存储的最新版本化载荷（YYYY-MM-DD 表示当天结束时，或 YYYY-MM-DDTHH:MM:SSZ）",
+    ),
+    (
+        "Preset dictionary file the payload was compressed with",
+        "压缩载荷时使用的预设字典文件",
+    ),
+    (
+        "Print nothing; exit with 0 if the chunk exists and 1 otherwise, or with 2 if the file cannot be read",
+        "不打印任何内容；数据块存在时以 0 退出，否则以 1 退出，无法读取文件时以 2 退出",
+    ),
+    (
+        "Read a truncated file up to the last complete chunk",
+        "读取截断的文件，直到最后一个完整的数据块",
+    ),
+    (
+        "Refuse to print the payload unless it is JSON matching this JSON Schema",
+        "除非载荷是符合此 JSON Schema 的 JSON，否则拒绝打印",
+    ),
+    (
+        "Print only the values a jq-style path selects from a JSON payload, e.g. '.version' or '.targets[] | select(.size > 100) | .name'; strings are printed without quotes",
+        "只打印 jq 风格路径从 JSON 载荷中选出的值，例如 '.version' 或 '.targets[] | select(.size > 100) | .name'；字符串不带引号打印",
+    ),
+    (
+        "Print the payload as stored even on a terminal, instead of indenting and highlighting JSON and XML and summarizing binary data",
+        "即使在终端上也按存储原样打印载荷，而不缩进和高亮 JSON 与 XML、不概括二进制数据",
+    ),
+    (
+        "Line endings to print text payloads with: lf, crlf or preserve",
+        "打印文本载荷时使用的换行符：lf、crlf 或 preserve",
+    ),
+    (
+        "Remove the first chunk of this type; with --matching, only consider chunks of this type",
+        "移除此类型的第一个数据块；与 --matching 一起使用时，只考虑此类型的数据块",
+    ),
+    (
+        "Remove the chunk starting at this file offset, as listed by scan (decimal or 0x-prefixed hex)",
+        "移除从此文件偏移开始的数据块，偏移即 scan 列出的值（十进制或以 0x 开头的十六进制）",
+    ),
+    (
+        "Remove every ancillary chunk whose decoded text matches this regular expression; for text chunks the keyword or the text must match",
+        "移除解码文本匹配此正则表达式的所有辅助数据块；对于文本数据块，关键字或文本须匹配",
+    ),
+    (
+        "List the chunks --matching would remove without removing them",
+        "列出 --matching 将移除的数据块，但不移除",
+    ),
+    (
+        "File to write the chunk data to [default: stdout]",
+        "写入数据块数据的文件 [默认：stdout]",
+    ),
+    (
+        "Extract the chunk starting at this file offset, as listed by scan (decimal or 0x-prefixed hex)",
+        "提取从此文件偏移开始的数据块，偏移即 scan 列出的值（十进制或以 0x 开头的十六进制）",
+    ),
+    (
+        "PNG files or file name patterns such as 'out*.png'. With the cloud feature, s3:// and gs:// objects, patterns and prefixes ending in '/' work the same way",
+        "PNG 文件或文件名模式（如 'out*.png'）。启用 cloud 特性时，s3:// 和 gs:// 对象、模式以及以 '/' 结尾的前缀同样适用",
+    ),
+    (
+        "Chunk type holding a segmented payload; repeat for several types",
+        "存放分段载荷的数据块类型；可重复以指定多个类型",
+    ),
+    (
+        "Remove all but the newest N versions of each versioned payload",
+        "每个版本化载荷只保留最新的 N 个版本",
+    ),
+    (
+        "Also remove stripes whose payload none of the files complete. Only pass it when every file holding stripes of those payloads is listed: stripes whose siblings are merely not named are otherwise lost",
+        "同时移除其载荷无法由所列文件补全的分片。仅当列出了存放这些载荷分片的所有文件时才使用：否则，只是未被列出兄弟分片的分片也会丢失",
+    ),
+    (
+        "Report what would be removed without changing any file",
+        "报告将要移除的内容，而不修改任何文件",
+    ),
+    (
+        "Print stable, script-friendly output instead of the pretty layout",
+        "打印稳定、便于脚本处理的输出，而不是美观的布局",
+    ),
+    (
+        "Print one tab-separated record per chunk instead of the pretty layout:\n\n    chunk <index> <offset> <type> <length> <crc> <category>\n\n<index> counts from 0, <offset> is the byte offset of the chunk's length field, <crc> is 8 lowercase hex digits and <category> is critical, ancillary or private. This format is frozen: new information will only ever be added as new record kinds or trailing fields.",
+        "每个数据块打印一条制表符分隔的记录，而不是美观的布局：\n\n    chunk <index> <offset> <type> <length> <crc> <category>\n\n<index> 从 0 开始计数，<offset> 是数据块长度字段的字节偏移，<crc> 是 8 位小写十六进制数，<category> 为 critical、ancillary 或 private。此格式已冻结：新信息只会以新的记录种类或末尾字段的形式添加。",
+    ),
+    (
+        "Only list private chunks in this org's namespace (a registered org name or a two-letter prefix)",
+        "只列出此组织命名空间中的私有数据块（已注册的组织名或两个字母的前缀）",
+    ),
+    (
+        "Org registry file mapping org names to prefixes [default: $PNGME_ORGS]",
+        "将组织名映射到前缀的组织注册文件 [默认：$PNGME_ORGS]",
+    ),
+    (
+        "Print the chunk layout as a Graphviz graph, with runs of chunks of one type drawn as a single box",
+        "以 Graphviz 图打印数据块布局，同一类型的连续数据块画成一个方框",
+    ),
+    (
+        "Print the chunk layout as a Mermaid flowchart, like --dot",
+        "以 Mermaid 流程图打印数据块布局，与 --dot 相同",
+    ),
+    (
+        "A PNG file or a directory of PNG files; with the cloud feature, also an s3:// or gs:// object, prefix ending in '/' or name pattern",
+        "一个 PNG 文件或一个包含 PNG 文件的目录；启用 cloud 特性时，也可以是 s3:// 或 gs:// 对象、以 '/' 结尾的前缀或名称模式",
+    ),
+    (
+        "Descend into subdirectories",
+        "递归进入子目录",
+    ),
+    (
+        "Number of worker threads (defaults to the available parallelism)",
+        "工作线程数（默认为可用的并行度）",
+    ),
+    (
+        "Fail unless the file's SHA-256 is this hex digest",
+        "除非文件的 SHA-256 是此十六进制摘要，否则失败",
+    ),
+    (
+        "Fail unless the decoded payload's SHA-256 is this hex digest",
+        "除非解码后载荷的 SHA-256 是此十六进制摘要，否则失败",
+    ),
+    (
+        "Chunk type holding the payload checked by --expect-payload-sha256",
+        "存放 --expect-payload-sha256 所校验载荷的数据块类型",
+    ),
+    (
+        "For each issue, print the spec rule, why it matters and how to fix it",
+        "对每个问题，打印规范条款、其重要性以及修复方法",
+    ),
+    (
+        "Warn about files whose ancillary chunks make up more than this percentage of the file",
+        "当文件中辅助数据块所占比例超过此百分比时发出警告",
+    ),
+    (
+        "Only count chunks of this type",
+        "只统计此类型的数据块",
+    ),
+    (
+        "Record git commit, branch, build timestamp and CI job URL as tEXt chunks",
+        "将 git 提交、分支、构建时间戳和 CI 作业 URL 记录为 tEXt 数据块",
+    ),
+    (
+        "Write UTF-8 iTXt chunks instead of Latin-1 tEXt chunks",
+        "写入 UTF-8 的 iTXt 数据块，而不是 Latin-1 的 tEXt 数据块",
+    ),
+    (
+        "What to do with characters Latin-1 cannot hold: error, transliterate or replace",
+        "如何处理 Latin-1 无法容纳的字符：error、transliterate 或 replace",
+    ),
+    (
+        "Reorder misplaced ancillary chunks and drop conflicting ones",
+        "重排位置错误的辅助数据块，并丢弃相互冲突的数据块",
+    ),
+    (
+        "Also report the file when its ancillary chunks make up more than this percentage of it; --fix leaves such chunks alone",
+        "当文件中辅助数据块所占比例超过此百分比时也报告该文件；--fix 不会改动这些数据块",
+    ),
+    (
+        "A damaged PNG, e.g. one with a corrupted chunk length",
+        "损坏的 PNG，例如数据块长度字段已损坏的文件",
+    ),
+    (
+        "Write the surviving chunks to this file as a well-formed PNG",
+        "将幸存的数据块作为格式正确的 PNG 写入此文件",
+    ),
+    (
+        "Add a missing signature or IEND and drop incomplete chunks and data after IEND",
+        "补上缺失的签名或 IEND，并丢弃不完整的数据块和 IEND 之后的数据",
+    ),
+    (
+        "Run every check on a file and list the problems found, most severe first, with a command to fix each where pngme has one",
+        "对文件运行所有检查，按严重程度从高到低列出发现的问题，并在 pngme 能修复时给出修复命令",
+    ),
+    (
+        "Run the parser over any input, such as a fuzzer's crash file, printing each chunk it reads and where and why it stops",
+        "对任意输入（例如模糊测试的崩溃文件）运行解析器，打印读到的每个数据块以及停止的位置和原因",
+    ),
+    (
+        "Tell which image container a file is in, how sure that is, and which pngme commands apply to it",
+        "判断文件使用哪种图像容器、把握有多大，以及哪些 pngme 命令适用于它",
+    ),
+    (
+        "Record the chunk hashes of every PNG under a directory",
+        "记录目录下每个 PNG 的数据块哈希",
+    ),
+    (
+        "Report PNGs whose ancillary chunks changed since the baseline",
+        "报告自基线以来辅助数据块发生变化的 PNG",
+    ),
+    (
+        "Run as a git hook",
+        "作为 git 钩子运行",
+    ),
+    (
+        "Apply the repository's chunk policy to staged PNG files: strip chunks it lists and restage the files, or block the commit when a file holds chunks it does not allow. Install by putting `pngme hook pre-commit` in .git/hooks/pre-commit",
+        "对暂存的 PNG 文件应用仓库的数据块策略：移除策略列出的数据块并重新暂存文件，或在文件含有策略不允许的数据块时阻止提交。将 `pngme hook pre-commit` 写入 .git/hooks/pre-commit 即可安装",
+    ),
+    (
+        "Policy file [default: .pngme-policy.json at the repository root]",
+        "策略文件 [默认：仓库根目录下的 .pngme-policy.json]",
+    ),
+];
+
+/// `text`, a help text from [`crate::args`], in `lang`. Text without an
+/// entry in [`HELP`] stays English.
+pub fn help(text: &str, lang: Lang) -> &str {
+    match lang {
+        Lang::En => text,
+        Lang::Zh => HELP
+            .iter()
+            .find(|(en, _)| *en == text)
+            .map_or(text, |(_, zh)| zh),
+    }
+}
+
+/// An error with a catalog entry. Its `Display` is the English message, so
+/// it reads the same as the plain string errors around it.
+#[derive(Debug)]
+pub struct CodedError {
+    pub message: &'static Message,
+    pub args: Vec<String>,
+}
+
+impl CodedError {
+    pub fn new(message: &'static Message, args: Vec<String>) -> CodedError {
+        CodedError { message, args }
+    }
+}
+
+impl Display for CodedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message.format(Lang::En, &self.args))
+    }
+}
+
+impl std::error::Error for CodedError {}
+
+/// An error that happened while reading or writing `path`. It displays as
+/// `source` alone; the path is only reported separately, as in JSON errors.
+pub struct FileError {
+    pub path: PathBuf,
+    pub source: Error,
+}
+
+impl FileError {
+    /// `error` attributed to `path`, unless it already names a file.
+    pub fn wrap(path: &Path, error: Error) -> Error {
+        if error.is::<FileError>() {
+            return error;
+        }
+        Box::new(FileError {
+            path: path.to_path_buf(),
+            source: error,
+        })
+    }
+}
+
+impl Display for FileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl std::fmt::Debug for FileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.to_string())
+    }
+}
+
+impl std::error::Error for FileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// The catalog entry and arguments describing `error`.
+pub fn describe(error: &Error) -> (&'static Message, Vec<String>) {
+    if let Some(file) = error.downcast_ref::<FileError>() {
+        describe(&file.source)
+    } else if let Some(coded) = error.downcast_ref::<CodedError>() {
+        (coded.message, coded.args.clone())
+    } else if let Some(parse) = error.downcast_ref::<ParseError>() {
+        parse.message()
+    } else if let Some(limit) = error.downcast_ref::<LimitError>() {
+        limit.message()
+    } else if let Some(output) = error.downcast_ref::<OutputLimit>() {
+        (&OUTPUT_LIMIT, vec![output.limit.to_string()])
+    } else if let Some(io) = error.downcast_ref::<io::Error>() {
+        (&IO, vec![io.to_string()])
+    } else {
+        (&OTHER, vec![error.to_string()])
+    }
+}
+
+/// `error` as `main` prints it: its code and its message in `lang`.
+pub fn render(error: &Error, lang: Lang) -> String {
+    let (message, args) = describe(error);
+    format!("error[{}]: {}", message.code(), message.format(lang, &args))
+}
+
+/// `error` as a JSON object for `--format json`: its code and message, and
+/// the file, offset and chunk type it concerns, each `null` when unknown.
+pub fn to_json(error: &Error, lang: Lang) -> Value {
+    let (message, args) = describe(error);
+    let (file, error) = match error.downcast_ref::<FileError>() {
+        Some(file) => (Some(&file.path), &file.source),
+        None => (None, error),
+    };
+    let parse = error.downcast_ref::<ParseError>();
+    let offset = parse
+        .and_then(ParseError::offset)
+        .or_else(|| error.downcast_ref::<LimitError>()?.offset());
+    let optional = |value: Option<Value>| value.unwrap_or(Value::Null);
+
+    Value::Object(vec![
+        ("code".to_string(), message.code().into()),
+        ("message".to_string(), message.format(lang, &args).into()),
+        (
+            "file".to_string(),
+            optional(file.map(|path| path.display().to_string().into())),
+        ),
+        (
+            "offset".to_string(),
+            optional(offset.map(|o| (o as f64).into())),
+        ),
+        (
+            "chunk_type".to_string(),
+            optional(parse.and_then(ParseError::chunk_type).map(Value::from)),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_are_unique() {
+        for (i, message) in CATALOG.iter().enumerate() {
+            assert!(CATALOG[..i].iter().all(|m| m.code != message.code));
+        }
+    }
+
+    #[test]
+    fn test_placeholders_match() {
+        for message in CATALOG {
+            for i in 0..4 {
+                let placeholder = format!("{{{}}}", i);
+                assert_eq!(
+                    message.en.contains(&placeholder),
+                    message.zh.contains(&placeholder),
+                    "{}",
+                    message.code()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        let args = ["{1}".to_string(), "IDAT".to_string()];
+        assert_eq!(
+            INVALID_CRC.format(Lang::En, &args),
+            "Invalid crc for {1} chunk at offset IDAT"
+        );
+        assert_eq!(
+            INVALID_CRC.format(Lang::En, &args[..1]),
+            "Invalid crc for {1} chunk at offset {1}"
+        );
+        assert_eq!(OTHER.format(Lang::Zh, &["{}{".to_string()]), "{}{");
+    }
+
+    fn help_texts(command: &clap::Command<'static>, texts: &mut Vec<&'static str>) {
+        texts.extend(command.get_about());
+        texts.extend(command.get_long_about());
+        // clap drops the --version flag of subcommands when it builds them.
+        let version = command.get_version().is_some();
+        for arg in command.get_arguments() {
+            if arg.get_id() == "version" && !version {
+                continue;
+            }
+            texts.extend(arg.get_help());
+            texts.extend(arg.get_long_help());
+        }
+        for subcommand in command.get_subcommands() {
+            help_texts(subcommand, texts);
+        }
+    }
+
+    #[test]
+    fn test_help_is_complete() {
+        use clap::CommandFactory;
+
+        let mut texts = Vec::new();
+        help_texts(&crate::args::Cli::command(), &mut texts);
+        for text in &texts {
+            assert!(HELP.iter().any(|(en, _)| en == text), "{}", text);
+        }
+        for (en, _) in HELP {
+            assert!(texts.contains(en), "{}", en);
+        }
+        for (i, (en, _)) in HELP.iter().enumerate() {
+            assert!(HELP[..i].iter().all(|(other, _)| other != en), "{}", en);
+        }
+
+        let command = crate::args::localize(crate::args::Cli::command(), Lang::Zh);
+        let mut texts = Vec::new();
+        help_texts(&command, &mut texts);
+        for text in &texts {
+            assert!(HELP.iter().any(|(_, zh)| zh == text), "{}", text);
+        }
+        command.debug_assert();
+    }
+
+    #[test]
+    fn test_lang_from_str() {
+        assert_eq!("zh_CN.UTF-8".parse(), Ok(Lang::Zh));
+        assert_eq!("en-GB".parse(), Ok(Lang::En));
+        assert_eq!("C".parse(), Ok(Lang::En));
+        assert!("fr".parse::<Lang>().is_err());
+        assert_eq!(Lang::detect(Some(Lang::Zh)), Lang::Zh);
+    }
+
+    #[test]
+    fn test_render() {
+        let error: Error = ParseError::InvalidCrc {
+            offset: 33,
+            chunk_type: "IDAT".to_string(),
+        }
+        .into();
+        assert_eq!(
+            render(&error, Lang::En),
+            "error[PNGME-0013]: Invalid crc for IDAT chunk at offset 33"
+        );
+        assert_eq!(
+            render(&error, Lang::Zh),
+            "error[PNGME-0013]: 偏移 33 处的 IDAT 数据块 CRC 校验失败"
+        );
+
+        let error = FileError::wrap(Path::new("a.png"), error);
+        assert_eq!(
+            to_json(&error, Lang::En).to_string(),
+            r#"{"code":"PNGME-0013","message":"Invalid crc for IDAT chunk at offset 33","file":"a.png","offset":33,"chunk_type":"IDAT"}"#
+        );
+        assert_eq!(error.to_string(), "Invalid crc for IDAT chunk at offset 33");
+
+        let error: Error = "something else".into();
+        assert_eq!(
+            to_json(&error, Lang::En).to_string(),
+            r#"{"code":"PNGME-0001","message":"something else","file":null,"offset":null,"chunk_type":null}"#
+        );
+        assert_eq!(
+            render(&error, Lang::Zh),
+            "error[PNGME-0001]: something else"
+        );
+    }
+}
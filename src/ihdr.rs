@@ -0,0 +1,164 @@
+use crate::png::Png;
+use crate::Result;
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale,
+    Truecolor,
+    Indexed,
+    GrayscaleAlpha,
+    TruecolorAlpha,
+}
+
+impl ColorType {
+    fn from_byte(byte: u8) -> Result<ColorType> {
+        Ok(match byte {
+            0 => ColorType::Grayscale,
+            2 => ColorType::Truecolor,
+            3 => ColorType::Indexed,
+            4 => ColorType::GrayscaleAlpha,
+            6 => ColorType::TruecolorAlpha,
+            _ => return Err(format!("invalid IHDR color type {}", byte).into()),
+        })
+    }
+
+    pub fn channels(&self) -> u8 {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Truecolor => 3,
+            ColorType::TruecolorAlpha => 4,
+        }
+    }
+}
+
+impl Display for ColorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Truecolor => "truecolor",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale+alpha",
+            ColorType::TruecolorAlpha => "truecolor+alpha",
+        })
+    }
+}
+
+/// The image parameters stored in IHDR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    /// Adam7 interlacing; anything working on pixel rows has to de-interlace
+    /// first since rows are stored as seven reduced passes.
+    pub interlaced: bool,
+}
+
+impl Header {
+    pub fn from_png(png: &Png) -> Result<Header> {
+        let chunk = png.chunk_by_type("IHDR").ok_or("missing IHDR chunk")?;
+        Header::parse(chunk.data())
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Header> {
+        if data.len() != 13 {
+            return Err(format!("IHDR must be 13 bytes, not {}", data.len()).into());
+        }
+        let dimension = |bytes: &[u8], name: &str| -> Result<u32> {
+            match u32::from_be_bytes(bytes.try_into().unwrap()) {
+                0 => Err(format!("IHDR {} must not be 0", name).into()),
+                size if size > i32::MAX as u32 => {
+                    Err(format!("IHDR {} {} is larger than 2^31-1", name, size).into())
+                }
+                size => Ok(size),
+            }
+        };
+        // Deflate is the only compression method and adaptive filtering the
+        // only filter method the PNG specification defines.
+        if data[10] != 0 {
+            return Err(format!("invalid IHDR compression method {}", data[10]).into());
+        }
+        if data[11] != 0 {
+            return Err(format!("invalid IHDR filter method {}", data[11]).into());
+        }
+        let header = Header {
+            width: dimension(&data[0..4], "width")?,
+            height: dimension(&data[4..8], "height")?,
+            bit_depth: data[8],
+            color_type: ColorType::from_byte(data[9])?,
+            interlaced: match data[12] {
+                0 => false,
+                1 => true,
+                method => return Err(format!("invalid IHDR interlace method {}", method).into()),
+            },
+        };
+
+        let allowed: &[u8] = match header.color_type {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            _ => &[8, 16],
+        };
+        if !allowed.contains(&header.bit_depth) {
+            return Err(format!(
+                "bit depth {} is not allowed for {} images",
+                header.bit_depth, header.color_type
+            )
+            .into());
+        }
+        Ok(header)
+    }
+
+    /// Bits per pixel across all channels.
+    pub fn bits_per_pixel(&self) -> u32 {
+        self.bit_depth as u32 * self.color_type.channels() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ihdr(bit_depth: u8, color_type: u8, interlace: u8) -> Vec<u8> {
+        let mut data = 640u32.to_be_bytes().to_vec();
+        data.extend_from_slice(&480u32.to_be_bytes());
+        data.extend_from_slice(&[bit_depth, color_type, 0, 0, interlace]);
+        data
+    }
+
+    #[test]
+    fn test_parse() {
+        let header = Header::parse(&ihdr(8, 6, 1)).unwrap();
+        assert_eq!(header.width, 640);
+        assert_eq!(header.height, 480);
+        assert_eq!(header.color_type, ColorType::TruecolorAlpha);
+        assert!(header.interlaced);
+        assert_eq!(header.bits_per_pixel(), 32);
+
+        let header = Header::parse(&ihdr(16, 0, 0)).unwrap();
+        assert!(!header.interlaced);
+        assert_eq!(header.bits_per_pixel(), 16);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(Header::parse(&ihdr(8, 5, 0)).is_err());
+        assert!(Header::parse(&ihdr(16, 3, 0)).is_err());
+        assert!(Header::parse(&ihdr(8, 2, 2)).is_err());
+        assert!(Header::parse(&ihdr(8, 2, 0)[..12]).is_err());
+
+        let with = |index: usize, bytes: &[u8]| {
+            let mut data = ihdr(8, 2, 0);
+            data[index..index + bytes.len()].copy_from_slice(bytes);
+            Header::parse(&data)
+        };
+        assert!(with(0, &[0, 0, 0, 0]).is_err());
+        assert!(with(4, &[0, 0, 0, 0]).is_err());
+        assert!(with(0, &[0x80, 0, 0, 0]).is_err());
+        assert!(with(0, &[0x7f, 0xff, 0xff, 0xff]).is_ok());
+        assert!(with(10, &[1]).is_err());
+        assert!(with(11, &[1]).is_err());
+    }
+}
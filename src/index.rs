@@ -0,0 +1,260 @@
+//! Chunk metadata for read-only listings such as `scan`.
+//!
+//! A [`Png`] keeps every chunk's bytes in an allocation of its own. A
+//! [`ChunkIndex`] keeps only the fixed-size metadata of each chunk, packed
+//! into one `Vec`, and reads chunk data through a single scratch buffer that
+//! is reused for every chunk. Listing a file with hundreds of thousands of
+//! chunks then costs a few dozen allocations instead of two per chunk.
+
+use crate::chunk::{Chunk, CrcProfile};
+use crate::chunk_type::ChunkType;
+use crate::limits::Limits;
+use crate::metrics::{self, Phase};
+use crate::png::{self, ParseError, Png};
+use crate::validation;
+use crate::warning::Warning;
+use crate::Result;
+use std::io::{Read, Seek, SeekFrom};
+
+/// Where a chunk sits and what it holds, without its data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkEntry {
+    /// File offset of the chunk's length field.
+    pub offset: usize,
+    pub chunk_type: ChunkType,
+    pub length: u32,
+    /// The standard CRC, which is what pngme would write for the chunk.
+    pub crc: u32,
+}
+
+impl ChunkEntry {
+    /// Seeks to the chunk in `reader`, the file it was indexed from, and
+    /// reads its data, failing when it no longer matches the stored CRC.
+    pub fn read_data<R: Read + Seek>(&self, mut reader: R) -> Result<Vec<u8>> {
+        reader.seek(SeekFrom::Start(self.offset as u64 + 4))?;
+        let mut bytes = vec![];
+        reader
+            .take(4 + self.length as u64)
+            .read_to_end(&mut bytes)?;
+        if bytes.len() != 4 + self.length as usize
+            || bytes[..4] != self.chunk_type.bytes()
+            || Chunk::crc_of(&bytes) != self.crc
+        {
+            Err(format!(
+                "chunk at offset {:#x} changed since the file was indexed",
+                self.offset
+            ))?
+        }
+        bytes.drain(..4);
+        Ok(bytes)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChunkIndex {
+    entries: Vec<ChunkEntry>,
+}
+
+impl ChunkIndex {
+    /// The index of a PNG that is already in memory.
+    pub fn of(png: &Png) -> ChunkIndex {
+        ChunkIndex {
+            entries: png
+                .chunks_with_offsets()
+                .map(|(offset, chunk)| ChunkEntry {
+                    offset,
+                    chunk_type: chunk.chunk_type(),
+                    length: chunk.length(),
+                    crc: chunk.crc(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Indexes a PNG, checking every CRC with `profile` as
+    /// `Png::read_with_crc_profile` does, but without keeping chunk data.
+    pub fn read<R: Read>(reader: R, profile: &CrcProfile, limits: &Limits) -> Result<ChunkIndex> {
+        match ChunkIndex::read_partial(reader, profile, limits)? {
+            (_, Some(offset)) => Err(ParseError::Truncated { offset })?,
+            (index, None) => Ok(index),
+        }
+    }
+
+    /// Like `read`, but a file cut off mid-chunk yields the complete chunks
+    /// before the cut and its offset, as with `Png::read_partial`.
+    pub fn read_partial<R: Read>(
+        mut reader: R,
+        profile: &CrcProfile,
+        limits: &Limits,
+    ) -> Result<(ChunkIndex, Option<usize>)> {
+        metrics::time(Phase::Parse, || {
+            Png::read_header(&mut reader)?;
+            let mut budget = limits.start();
+            let mut entries = vec![];
+            // Chunk type and data of the chunk being read.
+            let mut scratch = vec![];
+            let mut offset = Png::STANDARD_HEADER.len();
+            loop {
+                let mut length = [0u8; 4];
+                match png::read_fully(&mut reader, &mut length)? {
+                    0 => return Ok((ChunkIndex { entries }, None)),
+                    4 => {}
+                    _ => return Ok((ChunkIndex { entries }, Some(offset))),
+                }
+
+                let length = u32::from_be_bytes(length);
+                budget.admit(offset, length)?;
+                let expected = 4 + length as u64;
+                scratch.clear();
+                let mut crc = [0u8; 4];
+                if reader.by_ref().take(expected).read_to_end(&mut scratch)? as u64 != expected
+                    || png::read_fully(&mut reader, &mut crc)? != 4
+                {
+                    return Ok((ChunkIndex { entries }, Some(offset)));
+                }
+
+                let mut chunk_type = [0u8; 4];
+                chunk_type.copy_from_slice(&scratch[..4]);
+                let chunk_type = ChunkType::try_from(chunk_type)
+                    .map_err(|_| ParseError::InvalidChunkType { offset })?;
+                let crc = u32::from_be_bytes(crc);
+                if !profile.accepts(&scratch, crc) {
+                    return Err(ParseError::InvalidCrc {
+                        offset,
+                        chunk_type: chunk_type.to_string(),
+                    })?;
+                }
+                entries.push(ChunkEntry {
+                    offset,
+                    chunk_type,
+                    length,
+                    crc: match profile {
+                        CrcProfile::Standard => crc,
+                        _ => Chunk::crc_of(&scratch),
+                    },
+                });
+                offset += 12 + length as usize;
+            }
+        })
+    }
+
+    pub fn from_entries(entries: Vec<ChunkEntry>) -> ChunkIndex {
+        ChunkIndex { entries }
+    }
+
+    pub fn entries(&self) -> &[ChunkEntry] {
+        &self.entries
+    }
+
+    pub fn entry_at(&self, offset: usize) -> Result<&ChunkEntry> {
+        match self.entries.iter().find(|entry| entry.offset == offset) {
+            Some(entry) => Ok(entry),
+            None => Err(format!("no chunk starts at offset {:#x}", offset).into()),
+        }
+    }
+
+    /// The same warnings `Png::warnings` reports for the file.
+    pub fn warnings(&self) -> Vec<Warning> {
+        let chunks: Vec<(usize, [u8; 4])> = self
+            .entries
+            .iter()
+            .map(|entry| (entry.offset, entry.chunk_type.bytes()))
+            .collect();
+        validation::warnings_for_chunks(&chunks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_png() -> Png {
+        let chunk = |chunk_type: &str, data: &str| {
+            Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.into())
+        };
+        Png::from_chunks(vec![
+            chunk("IHDR", "not really a header"),
+            chunk("ruSt", "a payload"),
+            chunk("tIME", "first"),
+            chunk("tIME", "second"),
+            chunk("IEND", ""),
+        ])
+    }
+
+    #[test]
+    fn test_read_matches_parsed_png() {
+        let png = testing_png();
+        let index = ChunkIndex::read(
+            &png.as_bytes()[..],
+            &CrcProfile::Standard,
+            &Limits::default(),
+        )
+        .unwrap();
+        assert_eq!(index, ChunkIndex::of(&png));
+        assert_eq!(index.entries()[1].offset, 8 + 12 + 19);
+        assert_eq!(index.warnings(), png.warnings());
+    }
+
+    #[test]
+    fn test_read_partial() {
+        let bytes = testing_png().as_bytes();
+        let (index, truncated_at) = ChunkIndex::read_partial(
+            &bytes[..bytes.len() - 2],
+            &CrcProfile::Standard,
+            &Limits::default(),
+        )
+        .unwrap();
+        assert_eq!(index.entries().len(), 4);
+        assert_eq!(truncated_at, Some(bytes.len() - 12));
+
+        assert!(ChunkIndex::read(
+            &bytes[..bytes.len() - 2],
+            &CrcProfile::Standard,
+            &Limits::default()
+        )
+        .is_err());
+        assert!(ChunkIndex::read(&bytes[1..], &CrcProfile::Standard, &Limits::default()).is_err());
+    }
+
+    #[test]
+    fn test_read_checks_crcs() {
+        let png = testing_png();
+        let mut bytes = png.as_bytes();
+        let crc_start = 8 + 12 + 19 + 8 + 9;
+        bytes[crc_start] ^= 1;
+
+        let error = ChunkIndex::read(&bytes[..], &CrcProfile::Standard, &Limits::default())
+            .unwrap_err()
+            .downcast::<ParseError>()
+            .map(|e| *e)
+            .unwrap();
+        assert_eq!(
+            error,
+            ParseError::InvalidCrc {
+                offset: 8 + 12 + 19,
+                chunk_type: "ruSt".to_string()
+            }
+        );
+
+        // Accepted chunks are listed with the CRC pngme would write.
+        let index = ChunkIndex::read(&bytes[..], &CrcProfile::None, &Limits::default()).unwrap();
+        assert_eq!(index, ChunkIndex::of(&png));
+    }
+
+    #[test]
+    fn test_read_data() {
+        let png = testing_png();
+        let mut bytes = png.as_bytes();
+        let index = ChunkIndex::of(&png);
+        let entry = index.entry_at(8 + 12 + 19).unwrap();
+        assert_eq!(
+            entry.read_data(std::io::Cursor::new(&bytes)).unwrap(),
+            b"a payload"
+        );
+        assert!(index.entry_at(9).is_err());
+
+        bytes[entry.offset + 8] ^= 1;
+        assert!(entry.read_data(std::io::Cursor::new(&bytes)).is_err());
+    }
+}
@@ -0,0 +1,218 @@
+//! Chunk indexes kept on disk between runs, for `--index-cache`.
+//!
+//! Scripts often run several commands against the same large file. With a
+//! cache directory, the first `scan` or `extract` stores the file's
+//! [`ChunkIndex`] there, and later ones load it instead of parsing the file
+//! again: `scan` lists it directly and `extract` seeks straight to the chunk.
+//! An index is reused only while the file keeps the size, modification time
+//! and, on Unix, the device, inode and change time it had when indexed, and
+//! was checked with the same CRC profile. A copy made with `cp -p` keeps the
+//! modification time but not the rest. `extract` still checks the CRC of the
+//! chunk it reads.
+
+use crate::chunk::CrcProfile;
+use crate::chunk_type::ChunkType;
+use crate::hash;
+use crate::index::{ChunkEntry, ChunkIndex};
+use crate::Result;
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// What a cached index must have been built from to be reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    pub size: u64,
+    /// Modification time in nanoseconds since the Unix epoch.
+    pub modified: u128,
+    /// Device and inode numbers, and the change time in nanoseconds since
+    /// the Unix epoch, which no tool can set; 0 where unavailable.
+    pub device: u64,
+    pub inode: u64,
+    pub changed: i128,
+    pub profile: CrcProfile,
+}
+
+impl Key {
+    pub fn of(metadata: &Metadata, profile: &CrcProfile) -> Key {
+        #[cfg(unix)]
+        let (device, inode, changed) = {
+            use std::os::unix::fs::MetadataExt;
+            (
+                metadata.dev(),
+                metadata.ino(),
+                metadata.ctime() as i128 * 1_000_000_000 + metadata.ctime_nsec() as i128,
+            )
+        };
+        #[cfg(not(unix))]
+        let (device, inode, changed) = (0, 0, 0);
+        Key {
+            size: metadata.len(),
+            modified: metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |age| age.as_nanos()),
+            device,
+            inode,
+            changed,
+            profile: *profile,
+        }
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let (profile, polynomial) = match self.profile {
+            CrcProfile::Standard => (0, 0),
+            CrcProfile::None => (1, 0),
+            CrcProfile::Custom { polynomial } => (2, polynomial),
+        };
+        let mut bytes = self.size.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&self.modified.to_be_bytes());
+        bytes.extend_from_slice(&self.device.to_be_bytes());
+        bytes.extend_from_slice(&self.inode.to_be_bytes());
+        bytes.extend_from_slice(&self.changed.to_be_bytes());
+        bytes.push(profile);
+        bytes.extend_from_slice(&polynomial.to_be_bytes());
+        bytes
+    }
+}
+
+/// Layout: the magic bytes, the key, a `u32` entry count, then per entry its
+/// offset as `u64`, type, length and CRC, all big-endian.
+const MAGIC: [u8; 4] = *b"PMIX";
+const ENTRY_LEN: usize = 8 + 4 + 4 + 4;
+
+pub fn to_bytes(key: &Key, index: &ChunkIndex) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&key.to_bytes());
+    bytes.extend_from_slice(&(index.entries().len() as u32).to_be_bytes());
+    for entry in index.entries() {
+        bytes.extend_from_slice(&(entry.offset as u64).to_be_bytes());
+        bytes.extend_from_slice(&entry.chunk_type.bytes());
+        bytes.extend_from_slice(&entry.length.to_be_bytes());
+        bytes.extend_from_slice(&entry.crc.to_be_bytes());
+    }
+    bytes
+}
+
+/// The index in `bytes`, or `None` when they hold an index for another key
+/// or are not an index at all.
+pub fn from_bytes(key: &Key, bytes: &[u8]) -> Option<ChunkIndex> {
+    let rest = bytes.strip_prefix(&MAGIC)?;
+    let rest = rest.strip_prefix(&key.to_bytes()[..])?;
+    let (count, rest) = rest.split_first_chunk::<4>()?;
+    if rest.len() != u32::from_be_bytes(*count) as usize * ENTRY_LEN {
+        return None;
+    }
+    rest.chunks(ENTRY_LEN)
+        .map(|entry| {
+            let (offset, entry) = entry.split_first_chunk::<8>()?;
+            let (chunk_type, entry) = entry.split_first_chunk::<4>()?;
+            let (length, crc) = entry.split_first_chunk::<4>()?;
+            Some(ChunkEntry {
+                offset: u64::from_be_bytes(*offset).try_into().ok()?,
+                chunk_type: ChunkType::try_from(*chunk_type).ok()?,
+                length: u32::from_be_bytes(*length),
+                crc: u32::from_be_bytes(crc.try_into().ok()?),
+            })
+        })
+        .collect::<Option<_>>()
+        .map(ChunkIndex::from_entries)
+}
+
+/// A directory of cached indexes, one file per indexed path.
+#[derive(Debug)]
+pub struct IndexCache {
+    dir: PathBuf,
+}
+
+impl IndexCache {
+    pub fn new(dir: PathBuf) -> IndexCache {
+        IndexCache { dir }
+    }
+
+    /// Named after the hash of the absolute path, so that the same file
+    /// reached from different working directories shares one entry.
+    fn entry_path(&self, file: &Path) -> PathBuf {
+        let file = fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+        let digest = hash::sha256(file.as_os_str().as_encoded_bytes());
+        self.dir
+            .join(format!("{}.idx", hash::to_hex(&digest[..16])))
+    }
+
+    pub fn load(&self, file: &Path, key: &Key) -> Option<ChunkIndex> {
+        from_bytes(key, &fs::read(self.entry_path(file)).ok()?)
+    }
+
+    pub fn store(&self, file: &Path, key: &Key, index: &ChunkIndex) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("creating {}: {}", self.dir.display(), e))?;
+        // Rename into place so a concurrent reader never sees half an index.
+        let path = self.entry_path(file);
+        let temp = path.with_extension(format!("idx-{}", std::process::id()));
+        fs::write(&temp, to_bytes(key, index))
+            .and_then(|_| fs::rename(&temp, &path))
+            .map_err(|e| {
+                let _ = fs::remove_file(&temp);
+                format!("writing {}: {}", path.display(), e).into()
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::png::Png;
+    use std::str::FromStr;
+
+    fn testing_index() -> ChunkIndex {
+        let chunk = |chunk_type: &str, data: &str| {
+            Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.into())
+        };
+        ChunkIndex::of(&Png::from_chunks(vec![
+            chunk("IHDR", "not really a header"),
+            chunk("ruSt", "a payload"),
+            chunk("IEND", ""),
+        ]))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let key = Key {
+            size: 1234,
+            modified: 1_717_243_199_000_000_001,
+            device: 2049,
+            inode: 1_234_567,
+            changed: 1_717_243_199_000_000_002,
+            profile: CrcProfile::Custom {
+                polynomial: 0x1edc6f41,
+            },
+        };
+        let bytes = to_bytes(&key, &testing_index());
+        assert_eq!(from_bytes(&key, &bytes), Some(testing_index()));
+
+        for other in [
+            Key { size: 1235, ..key },
+            Key {
+                modified: key.modified + 1,
+                ..key
+            },
+            Key {
+                inode: key.inode + 1,
+                ..key
+            },
+            Key {
+                changed: key.changed + 1,
+                ..key
+            },
+            Key {
+                profile: CrcProfile::Standard,
+                ..key
+            },
+        ] {
+            assert_eq!(from_bytes(&other, &bytes), None);
+        }
+        assert_eq!(from_bytes(&key, &bytes[..bytes.len() - 1]), None);
+    }
+}
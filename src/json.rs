@@ -0,0 +1,537 @@
+//! A small JSON value type with a parser and writers, for the few places
+//! where pngme reads or writes JSON documents.
+
+use crate::Result;
+use std::fmt::{self, Display, Write};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// Members keep their document order, so written files are stable.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn parse(text: &str) -> Result<Value> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            position: 0,
+            depth: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.position != parser.bytes.len() {
+            return Err(parser.error("trailing characters after JSON value"));
+        }
+        Ok(value)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.as_object()?
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The JSON type name: null, boolean, number, string, array or object.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        }
+    }
+
+    /// Writes the value indented by two spaces per level.
+    pub fn to_pretty_string(&self) -> String {
+        let mut out = String::new();
+        self.write_pretty(&mut out, 0).unwrap();
+        out
+    }
+
+    fn write_pretty(&self, out: &mut String, depth: usize) -> fmt::Result {
+        let indent = |out: &mut String, depth: usize| out.push_str(&"  ".repeat(depth));
+        match self {
+            Value::Array(items) if !items.is_empty() => {
+                out.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    indent(out, depth + 1);
+                    item.write_pretty(out, depth + 1)?;
+                    out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+                }
+                indent(out, depth);
+                out.push(']');
+            }
+            Value::Object(members) if !members.is_empty() => {
+                out.push_str("{\n");
+                for (i, (name, value)) in members.iter().enumerate() {
+                    indent(out, depth + 1);
+                    write_string(out, name)?;
+                    out.push_str(": ");
+                    value.write_pretty(out, depth + 1)?;
+                    out.push_str(if i + 1 < members.len() { ",\n" } else { "\n" });
+                }
+                indent(out, depth);
+                out.push('}');
+            }
+            _ => write!(out, "{}", self)?,
+        }
+        Ok(())
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Number(number) if number.fract() == 0.0 && number.abs() < 9e15 => {
+                write!(f, "{}", *number as i64)
+            }
+            Value::Number(number) if number.is_finite() => write!(f, "{}", number),
+            Value::Number(_) => write!(f, "null"),
+            Value::String(string) => write_string(f, string),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, name)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Value {
+        Value::String(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Value {
+        Value::String(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Value {
+        Value::Number(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Value {
+        Value::Bool(value)
+    }
+}
+
+fn write_string(out: &mut impl Write, string: &str) -> fmt::Result {
+    out.write_char('"')?;
+    for c in string.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    /// Keeps hostile documents from overflowing the stack.
+    const MAX_DEPTH: usize = 128;
+
+    fn error(&self, message: &str) -> crate::Error {
+        format!("invalid JSON at byte {}: {}", self.position, message).into()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .bytes
+            .get(self.position)
+            .is_some_and(|byte| matches!(byte, b' ' | b'\t' | b'\n' | b'\r'))
+        {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.bytes.get(self.position).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<()> {
+        if self.peek() == Some(byte) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", byte as char)))
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value> {
+        if self.bytes[self.position..].starts_with(word.as_bytes()) {
+            self.position += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("unexpected character"))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.depth += 1;
+        if self.depth > Parser::MAX_DEPTH {
+            return Err(self.error("nesting is too deep"));
+        }
+        let value = self.value_inner();
+        self.depth -= 1;
+        value
+    }
+
+    fn value_inner(&mut self) -> Result<Value> {
+        match self.peek() {
+            None => Err(self.error("unexpected end of input")),
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b'[') => {
+                self.position += 1;
+                let mut items = vec![];
+                if self.peek() == Some(b']') {
+                    self.position += 1;
+                    return Ok(Value::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    match self.peek() {
+                        Some(b',') => self.position += 1,
+                        Some(b']') => {
+                            self.position += 1;
+                            return Ok(Value::Array(items));
+                        }
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.position += 1;
+                let mut members = vec![];
+                if self.peek() == Some(b'}') {
+                    self.position += 1;
+                    return Ok(Value::Object(members));
+                }
+                loop {
+                    if self.peek() != Some(b'"') {
+                        return Err(self.error("expected a member name"));
+                    }
+                    let name = self.string()?;
+                    self.expect(b':')?;
+                    members.push((name, self.value()?));
+                    match self.peek() {
+                        Some(b',') => self.position += 1,
+                        Some(b'}') => {
+                            self.position += 1;
+                            return Ok(Value::Object(members));
+                        }
+                        _ => return Err(self.error("expected ',' or '}'")),
+                    }
+                }
+            }
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+        }
+    }
+
+    /// A number as RFC 8259 writes it: `-?(0|[1-9]\d*)(\.\d+)?([eE][+-]?\d+)?`.
+    /// Numbers too large for an `f64` are rejected rather than becoming
+    /// infinite.
+    fn number(&mut self) -> Result<Value> {
+        let start = self.position;
+        self.eat(|byte| byte == b'-');
+        match self.bytes.get(self.position) {
+            Some(b'0') => self.position += 1,
+            Some(b'1'..=b'9') => self.digits()?,
+            _ => return Err(self.error("invalid number")),
+        }
+        if self.eat(|byte| byte == b'.') {
+            self.digits()?;
+        }
+        if self.eat(|byte| matches!(byte, b'e' | b'E')) {
+            self.eat(|byte| matches!(byte, b'+' | b'-'));
+            self.digits()?;
+        }
+        std::str::from_utf8(&self.bytes[start..self.position])
+            .ok()
+            .and_then(|number| number.parse::<f64>().ok())
+            .filter(|number| number.is_finite())
+            .map(Value::Number)
+            .ok_or_else(|| self.error("invalid number"))
+    }
+
+    /// Moves past the next byte if `accept` takes it.
+    fn eat(&mut self, accept: impl Fn(u8) -> bool) -> bool {
+        let eaten = self
+            .bytes
+            .get(self.position)
+            .is_some_and(|&byte| accept(byte));
+        if eaten {
+            self.position += 1;
+        }
+        eaten
+    }
+
+    /// Moves past one or more ASCII digits.
+    fn digits(&mut self) -> Result<()> {
+        if !self.eat(|byte| byte.is_ascii_digit()) {
+            return Err(self.error("invalid number"));
+        }
+        while self.eat(|byte| byte.is_ascii_digit()) {}
+        Ok(())
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.position += 1;
+        let mut string = String::new();
+        loop {
+            let start = self.position;
+            while self
+                .bytes
+                .get(self.position)
+                .is_some_and(|&byte| byte != b'"' && byte != b'\\' && byte >= 0x20)
+            {
+                self.position += 1;
+            }
+            string.push_str(
+                std::str::from_utf8(&self.bytes[start..self.position])
+                    .map_err(|_| self.error("invalid UTF-8 in string"))?,
+            );
+
+            match self.bytes.get(self.position) {
+                Some(b'"') => {
+                    self.position += 1;
+                    return Ok(string);
+                }
+                Some(b'\\') => {
+                    self.position += 1;
+                    let escape = *self
+                        .bytes
+                        .get(self.position)
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.position += 1;
+                    string.push(match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape")),
+                    });
+                }
+                Some(_) => return Err(self.error("control character in string")),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let digits = self
+            .bytes
+            .get(self.position..self.position + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("invalid \\u escape"))?;
+        self.position += 4;
+        Ok(digits)
+    }
+
+    fn unicode_escape(&mut self) -> Result<char> {
+        let mut code = self.hex4()?;
+        if (0xd800..0xdc00).contains(&code) {
+            if !self.bytes[self.position..].starts_with(b"\\u") {
+                return Err(self.error("unpaired surrogate"));
+            }
+            self.position += 2;
+            let low = self.hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(self.error("unpaired surrogate"));
+            }
+            code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+        }
+        char::from_u32(code).ok_or_else(|| self.error("invalid \\u escape"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let value =
+            Value::parse(r#" {"a": [1, 2.5, -3e2], "b": {"c": null}, "d": true} "#).unwrap();
+        assert_eq!(
+            value.get("a").unwrap().as_array().unwrap(),
+            &[
+                Value::Number(1.0),
+                Value::Number(2.5),
+                Value::Number(-300.0)
+            ]
+        );
+        assert_eq!(value.get("b").unwrap().get("c"), Some(&Value::Null));
+        assert_eq!(value.get("d").unwrap().as_bool(), Some(true));
+        assert!(value.get("e").is_none());
+    }
+
+    #[test]
+    fn test_parse_strings() {
+        let value = Value::parse(r#""tab\t quote\" snow\u2603 clef\ud834\udd1e""#).unwrap();
+        assert_eq!(
+            value.as_str().unwrap(),
+            "tab\t quote\" snow\u{2603} clef\u{1d11e}"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for text in [
+            "",
+            "{",
+            "[1,]",
+            "{\"a\" 1}",
+            "nul",
+            "\"open",
+            "1 2",
+            "\"\\ud834\"",
+            "01",
+            "-01",
+            "1.",
+            "+1",
+            ".5",
+            "-",
+            "1e",
+            "1e+",
+            "1.5e-",
+            "--1",
+            "1e400",
+            "-1e400",
+        ] {
+            assert!(Value::parse(text).is_err(), "{:?} should not parse", text);
+        }
+    }
+
+    #[test]
+    fn test_parse_numbers() {
+        for (text, number) in [
+            ("0", 0.0),
+            ("-0", -0.0),
+            ("10", 10.0),
+            ("0.25", 0.25),
+            ("1E+2", 100.0),
+            ("-5e-1", -0.5),
+            ("1e308", 1e308),
+        ] {
+            assert_eq!(
+                Value::parse(text).unwrap(),
+                Value::Number(number),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_depth_limit() {
+        assert!(Value::parse(&"[".repeat(100_000)).is_err());
+        assert!(Value::parse(&format!("{}{}", "[".repeat(100), "]".repeat(100))).is_ok());
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let text =
+            r#"{"name":"pngme","tags":["a\nb",""],"size":42,"ratio":0.5,"ok":false,"none":null}"#;
+        let value = Value::parse(text).unwrap();
+        assert_eq!(value.to_string(), text);
+        assert_eq!(Value::parse(&value.to_pretty_string()).unwrap(), value);
+    }
+
+    #[test]
+    fn test_pretty() {
+        let value = Value::parse(r#"{"a":[1,{}],"b":[]}"#).unwrap();
+        assert_eq!(
+            value.to_pretty_string(),
+            "{\n  \"a\": [\n    1,\n    {}\n  ],\n  \"b\": []\n}"
+        );
+    }
+}
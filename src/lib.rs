@@ -1,8 +1,46 @@
 pub mod args;
-mod chunk;
-mod chunk_type;
+pub mod baseline;
+pub mod chunk;
+pub mod chunk_type;
 pub mod commands;
-mod png;
+pub mod container;
+pub mod diff;
+pub mod doctor;
+pub mod gc;
+pub mod glob;
+pub mod graph;
+pub mod hash;
+pub mod hook;
+pub mod i18n;
+pub mod ihdr;
+pub mod index;
+pub mod index_cache;
+pub mod json;
+pub mod limits;
+pub mod lint;
+pub mod metrics;
+pub mod namespace;
+pub mod payload;
+pub mod platform;
+pub mod png;
+pub mod provenance;
+pub mod query;
+pub mod recovery;
+pub mod regex;
+pub mod replay;
+pub mod schema;
+pub mod sniff;
+pub mod storage;
+pub mod stripe;
+pub mod summary;
+pub mod template;
+pub mod text;
+pub mod time;
+pub mod transform;
+pub mod validation;
+pub mod view;
+pub mod warning;
+pub mod zlib;
 
 pub type Error = Box<dyn std::error::Error>;
 pub type Result<T> = std::result::Result<T, Error>;
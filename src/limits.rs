@@ -0,0 +1,189 @@
+//! Bounds on the work a single input file can cause.
+//!
+//! A service that runs pngme on uploaded files sets `--max-file-size`,
+//! `--max-chunks` and `--timeout` so that a pathological file, such as one
+//! declaring a 4 GiB chunk or holding millions of empty ones, fails quickly
+//! with a [`LimitError`] instead of tying up memory and CPU. The parser
+//! checks every limit before it reads each chunk's data.
+
+use crate::i18n::{self, Lang, Message};
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+/// Limits for parsing one file. The default has none.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub max_file_size: Option<u64>,
+    pub max_chunks: Option<usize>,
+    /// Wall-clock time allowed for parsing the file.
+    pub timeout: Option<Duration>,
+}
+
+impl Limits {
+    /// Starts the clock for parsing one file.
+    pub fn start(&self) -> Budget {
+        Budget {
+            limits: *self,
+            // A timeout too long to represent as an instant never expires.
+            deadline: self
+                .timeout
+                .and_then(|timeout| Instant::now().checked_add(timeout)),
+            chunks: 0,
+        }
+    }
+
+    /// Checks the size of a file that is read whole rather than parsed.
+    pub fn check_file_size(&self, size: u64) -> Result<(), LimitError> {
+        match self.max_file_size {
+            Some(limit) if size > limit => Err(LimitError::FileTooLarge { limit }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// What is left of the limits while a file is being parsed.
+#[derive(Debug)]
+pub struct Budget {
+    limits: Limits,
+    deadline: Option<Instant>,
+    chunks: usize,
+}
+
+impl Budget {
+    /// Accounts for the chunk at `offset` with `length` bytes of data,
+    /// before any of its data is read.
+    pub fn admit(&mut self, offset: usize, length: u32) -> Result<(), LimitError> {
+        if let Some(limit) = self.limits.max_chunks {
+            if self.chunks >= limit {
+                return Err(LimitError::TooManyChunks { offset, limit });
+            }
+        }
+        // The chunk ends after its length, type, data and CRC fields.
+        self.limits
+            .check_file_size(offset as u64 + 12 + length as u64)?;
+        self.check_time(offset)?;
+        self.chunks += 1;
+        Ok(())
+    }
+
+    /// Fails once the timeout has run out, for work done between chunks at
+    /// `offset`, such as scanning damaged bytes for the next chunk.
+    pub fn check_time(&self, offset: usize) -> Result<(), LimitError> {
+        match (self.deadline, self.limits.timeout) {
+            (Some(deadline), Some(limit)) if Instant::now() >= deadline => {
+                Err(LimitError::TimedOut { offset, limit })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Parses `--timeout`: seconds, possibly fractional.
+pub fn parse_seconds(s: &str) -> Result<Duration, String> {
+    s.parse()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("{:?} is not a number of seconds", s))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LimitError {
+    FileTooLarge { limit: u64 },
+    TooManyChunks { offset: usize, limit: usize },
+    TimedOut { offset: usize, limit: Duration },
+}
+
+impl LimitError {
+    /// Where parsing stopped, when it got as far as the chunks.
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            LimitError::FileTooLarge { .. } => None,
+            LimitError::TooManyChunks { offset, .. } | LimitError::TimedOut { offset, .. } => {
+                Some(*offset)
+            }
+        }
+    }
+
+    /// The catalog entry and arguments for the error.
+    pub fn message(&self) -> (&'static Message, Vec<String>) {
+        match self {
+            LimitError::FileTooLarge { limit } => (&i18n::FILE_TOO_LARGE, vec![limit.to_string()]),
+            LimitError::TooManyChunks { offset, limit } => (
+                &i18n::TOO_MANY_CHUNKS,
+                vec![limit.to_string(), offset.to_string()],
+            ),
+            LimitError::TimedOut { offset, limit } => (
+                &i18n::TIMED_OUT,
+                vec![limit.as_secs_f64().to_string(), offset.to_string()],
+            ),
+        }
+    }
+}
+
+impl Display for LimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (message, args) = self.message();
+        write!(f, "{}", message.format(Lang::En, &args))
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget() {
+        let limits = Limits {
+            max_file_size: Some(100),
+            max_chunks: Some(2),
+            timeout: None,
+        };
+        let mut budget = limits.start();
+        assert_eq!(budget.admit(8, 13), Ok(()));
+        assert_eq!(
+            budget.admit(33, 56),
+            Err(LimitError::FileTooLarge { limit: 100 })
+        );
+        assert_eq!(budget.admit(33, 55), Ok(()));
+        assert_eq!(
+            budget.admit(100, 0),
+            Err(LimitError::TooManyChunks {
+                offset: 100,
+                limit: 2
+            })
+        );
+
+        let mut unlimited = Limits::default().start();
+        assert!((0..1000).all(|i| unlimited.admit(i, u32::MAX).is_ok()));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let limits = Limits {
+            timeout: Some(Duration::ZERO),
+            ..Limits::default()
+        };
+        assert_eq!(
+            limits.start().admit(8, 0),
+            Err(LimitError::TimedOut {
+                offset: 8,
+                limit: Duration::ZERO
+            })
+        );
+
+        let limits = Limits {
+            timeout: Some(parse_seconds("1.8e19").unwrap()),
+            ..Limits::default()
+        };
+        assert_eq!(limits.start().admit(8, 0), Ok(()));
+    }
+
+    #[test]
+    fn test_parse_seconds() {
+        assert_eq!(parse_seconds("1.5"), Ok(Duration::from_millis(1500)));
+        assert!(parse_seconds("-1").is_err());
+        assert!(parse_seconds("soon").is_err());
+    }
+}
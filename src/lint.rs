@@ -0,0 +1,278 @@
+use crate::png::Png;
+use crate::validation::{self, Rule};
+use crate::warning::Warning;
+use crate::Result;
+use std::fmt::Display;
+
+/// Where an ancillary chunk may sit relative to PLTE and the first IDAT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placement {
+    BeforePlte,
+    AfterPlte,
+    BeforeIdat,
+}
+
+const PLACEMENTS: &[(&[u8; 4], Placement)] = &[
+    (b"cHRM", Placement::BeforePlte),
+    (b"cICP", Placement::BeforePlte),
+    (b"gAMA", Placement::BeforePlte),
+    (b"iCCP", Placement::BeforePlte),
+    (b"sBIT", Placement::BeforePlte),
+    (b"sRGB", Placement::BeforePlte),
+    (b"bKGD", Placement::AfterPlte),
+    (b"hIST", Placement::AfterPlte),
+    (b"tRNS", Placement::AfterPlte),
+    (b"pHYs", Placement::BeforeIdat),
+    (b"sPLT", Placement::BeforeIdat),
+    (b"eXIf", Placement::BeforeIdat),
+    (b"acTL", Placement::BeforeIdat),
+];
+
+impl Placement {
+    fn of(chunk_type: &[u8; 4]) -> Option<Placement> {
+        PLACEMENTS
+            .iter()
+            .find(|(t, _)| *t == chunk_type)
+            .map(|&(_, placement)| placement)
+    }
+
+    fn rule(self) -> &'static Rule {
+        match self {
+            Placement::BeforePlte => &validation::BEFORE_PLTE,
+            Placement::AfterPlte => &validation::AFTER_PLTE,
+            Placement::BeforeIdat => &validation::BEFORE_IDAT,
+        }
+    }
+
+    fn allows(self, index: usize, layout: &Layout) -> bool {
+        let before = |limit: Option<usize>| limit.is_none_or(|limit| index < limit);
+        match self {
+            Placement::BeforePlte => before(layout.plte) && before(layout.first_idat),
+            Placement::AfterPlte => {
+                layout.plte.is_none_or(|plte| index > plte) && before(layout.first_idat)
+            }
+            Placement::BeforeIdat => before(layout.first_idat),
+        }
+    }
+
+    /// The index a misplaced chunk is moved to, i.e. right before the chunk
+    /// that bounds it.
+    fn destination(self, layout: &Layout) -> usize {
+        match self {
+            Placement::BeforePlte => layout.plte.or(layout.first_idat),
+            Placement::AfterPlte => layout.first_idat.or(layout.plte.map(|plte| plte + 1)),
+            Placement::BeforeIdat => layout.first_idat,
+        }
+        .expect("a misplaced chunk has a bounding chunk")
+    }
+}
+
+struct Layout {
+    types: Vec<[u8; 4]>,
+    plte: Option<usize>,
+    first_idat: Option<usize>,
+}
+
+impl Layout {
+    fn of(png: &Png) -> Layout {
+        let types: Vec<[u8; 4]> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().bytes())
+            .collect();
+        Layout {
+            plte: types.iter().position(|t| t == b"PLTE"),
+            first_idat: types.iter().position(|t| t == b"IDAT"),
+            types,
+        }
+    }
+
+    fn has(&self, chunk_type: &[u8; 4]) -> bool {
+        self.types.contains(chunk_type)
+    }
+
+    /// The rule a chunk of this type breaks just by being in the file.
+    fn conflict_rule(&self, chunk_type: &[u8; 4]) -> Option<&'static Rule> {
+        if chunk_type == b"iCCP" && self.has(b"sRGB") {
+            Some(&validation::ICCP_SRGB)
+        } else if chunk_type == b"hIST" && self.plte.is_none() {
+            Some(&validation::HIST_NEEDS_PLTE)
+        } else {
+            None
+        }
+    }
+
+    /// The first chunk that must be dropped, with the rule it breaks.
+    fn conflict(&self) -> Option<(usize, &'static Rule)> {
+        self.types
+            .iter()
+            .enumerate()
+            .find_map(|(index, t)| Some((index, self.conflict_rule(t)?)))
+    }
+
+    fn misplaced(&self) -> impl Iterator<Item = (usize, Placement)> + '_ {
+        self.types
+            .iter()
+            .enumerate()
+            .filter_map(|(index, t)| Some((index, Placement::of(t)?)))
+            .filter(|&(index, placement)| !placement.allows(index, self))
+    }
+}
+
+/// A change made by [`fix`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fix {
+    Dropped {
+        chunk_type: String,
+        rule: &'static Rule,
+    },
+    Moved {
+        chunk_type: String,
+        from: usize,
+        to: usize,
+    },
+}
+
+impl Display for Fix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fix::Dropped { chunk_type, rule } => {
+                write!(f, "dropped {}: {}", chunk_type, rule.summary)
+            }
+            Fix::Moved {
+                chunk_type,
+                from,
+                to,
+            } => write!(f, "moved {} from index {} to {}", chunk_type, from, to),
+        }
+    }
+}
+
+/// Ancillary chunks that break the spec's ordering and exclusivity rules.
+pub fn check(png: &Png) -> Vec<Warning> {
+    let layout = Layout::of(png);
+    let mut warnings = vec![];
+    for (index, t) in layout.types.iter().enumerate() {
+        let chunk_type = String::from_utf8_lossy(t).into_owned();
+        if let Some(rule) = layout.conflict_rule(t) {
+            warnings.push(Warning::Conflict {
+                chunk_type,
+                index,
+                rule,
+            });
+        } else if let Some(placement) = Placement::of(t) {
+            if !placement.allows(index, &layout) {
+                warnings.push(Warning::OutOfOrder {
+                    chunk_type,
+                    index,
+                    rule: placement.rule(),
+                });
+            }
+        }
+    }
+    warnings
+}
+
+/// Drops conflicting chunks and moves misplaced ones right in front of the
+/// chunk that bounds them, keeping the relative order of everything else.
+pub fn fix(png: &mut Png) -> Result<Vec<Fix>> {
+    let layout = Layout::of(png);
+    if let (Some(plte), Some(first_idat)) = (layout.plte, layout.first_idat) {
+        if plte > first_idat {
+            return Err("PLTE comes after IDAT; fix the critical chunk order first".into());
+        }
+    }
+
+    let mut fixes = vec![];
+    while let Some((index, rule)) = Layout::of(png).conflict() {
+        let chunk = png.remove_chunk_at(index);
+        fixes.push(Fix::Dropped {
+            chunk_type: chunk.chunk_type().to_string(),
+            rule,
+        });
+    }
+
+    while let Some((from, placement)) = Layout::of(png).misplaced().next() {
+        let chunk = png.remove_chunk_at(from);
+        let to = placement.destination(&Layout::of(png));
+        fixes.push(Fix::Moved {
+            chunk_type: chunk.chunk_type().to_string(),
+            from,
+            to,
+        });
+        png.insert_chunk(to, chunk);
+    }
+
+    Ok(fixes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn png_of(types: &[&str]) -> Png {
+        Png::from_chunks(
+            types
+                .iter()
+                .map(|t| Chunk::new(ChunkType::from_str(t).unwrap(), vec![]))
+                .collect(),
+        )
+    }
+
+    fn types_of(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_order() {
+        let png = png_of(&[
+            "IHDR", "gAMA", "sRGB", "PLTE", "tRNS", "pHYs", "IDAT", "tEXt", "IEND",
+        ]);
+        assert_eq!(check(&png), vec![]);
+    }
+
+    #[test]
+    fn test_check() {
+        let png = png_of(&[
+            "IHDR", "iCCP", "sRGB", "tRNS", "PLTE", "IDAT", "gAMA", "pHYs", "IEND",
+        ]);
+        let rules: Vec<&str> = check(&png).iter().map(|w| w.rule().id).collect();
+        assert_eq!(
+            rules,
+            ["iccp-srgb", "after-plte", "before-plte", "before-idat"]
+        );
+    }
+
+    #[test]
+    fn test_trns_without_plte() {
+        let png = png_of(&["IHDR", "tRNS", "IDAT", "hIST", "IEND"]);
+        let rules: Vec<&str> = check(&png).iter().map(|w| w.rule().id).collect();
+        assert_eq!(rules, ["hist-needs-plte"]);
+    }
+
+    #[test]
+    fn test_fix() {
+        let mut png = png_of(&[
+            "IHDR", "iCCP", "sRGB", "tRNS", "PLTE", "IDAT", "gAMA", "pHYs", "IEND",
+        ]);
+        let fixes = fix(&mut png).unwrap();
+        assert_eq!(fixes.len(), 4);
+        assert_eq!(
+            types_of(&png),
+            ["IHDR", "sRGB", "gAMA", "PLTE", "tRNS", "pHYs", "IDAT", "IEND"]
+        );
+        assert_eq!(check(&png), vec![]);
+    }
+
+    #[test]
+    fn test_fix_refuses_bad_critical_order() {
+        let mut png = png_of(&["IHDR", "IDAT", "PLTE", "IEND"]);
+        assert!(fix(&mut png).is_err());
+    }
+}
@@ -1,14 +1,62 @@
-use clap::Parser;
 use pngme::{
-    args::{Cli, PngMeArgs},
-    commands, Result,
+    args::{Cli, Format, PngMeArgs},
+    commands, i18n, metrics, Result,
 };
+use std::process;
 
-fn main() -> Result<()> {
-    match &Cli::parse().command {
-        PngMeArgs::Encode(args) => commands::encode(args),
-        PngMeArgs::Decode(args) => commands::decode(args),
-        PngMeArgs::Remove(args) => commands::remove(args),
-        PngMeArgs::Print(args) => commands::print(args),
+fn main() {
+    let cli = Cli::parse_localized();
+    if let Err(error) = run(&cli) {
+        if error.is::<commands::ChunkAbsent>() {
+            process::exit(1);
+        }
+        let lang = i18n::Lang::detect(cli.global.lang);
+        match cli.global.format {
+            Format::Text => eprintln!("{}", i18n::render(&error, lang)),
+            Format::Json => eprintln!("{}", i18n::to_json(&error, lang)),
+        }
+        // decode --exists answers with 0 or 1, so its failures need another
+        // code.
+        match &cli.command {
+            PngMeArgs::Decode(args) if args.exists => process::exit(2),
+            _ => process::exit(1),
+        }
     }
 }
+
+fn run(cli: &Cli) -> Result<()> {
+    if cli.global.timings && !metrics::ENABLED {
+        return Err("--timings needs pngme built with the perf-metrics feature".into());
+    }
+    if cli.global.is_read_only() {
+        if let Some(command) = cli.command.writes_files(&cli.global) {
+            return Err(i18n::CodedError::new(&i18n::READ_ONLY, vec![command.to_string()]).into());
+        }
+    }
+
+    let result = match &cli.command {
+        PngMeArgs::Encode(args) => commands::encode(args, &cli.global),
+        PngMeArgs::Decode(args) => commands::decode(args, &cli.global),
+        PngMeArgs::Remove(args) => commands::remove(args, &cli.global),
+        PngMeArgs::Extract(args) => commands::extract(args, &cli.global),
+        PngMeArgs::History(args) => commands::history(args, &cli.global),
+        PngMeArgs::Gc(args) => commands::gc(args, &cli.global),
+        PngMeArgs::Print(args) => commands::print(args, &cli.global),
+        PngMeArgs::Scan(args) => commands::scan(args, &cli.global),
+        PngMeArgs::Verify(args) => commands::verify(args, &cli.global),
+        PngMeArgs::Count(args) => commands::count(args, &cli.global),
+        PngMeArgs::Tag(args) => commands::tag(args, &cli.global),
+        PngMeArgs::Lint(args) => commands::lint(args, &cli.global),
+        PngMeArgs::Recover(args) => commands::recover(args, &cli.global),
+        PngMeArgs::Repair(args) => commands::repair(args, &cli.global),
+        PngMeArgs::Doctor(args) => commands::doctor(args, &cli.global),
+        PngMeArgs::Replay(args) => commands::replay(args, &cli.global),
+        PngMeArgs::Identify(args) => commands::identify(args, &cli.global),
+        PngMeArgs::Baseline(args) => commands::baseline(args, &cli.global),
+        PngMeArgs::Hook(args) => commands::hook(args, &cli.global),
+    };
+    if cli.global.timings {
+        eprint!("{}", metrics::report(&metrics::snapshot()));
+    }
+    result
+}
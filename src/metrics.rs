@@ -0,0 +1,167 @@
+//! Wall-clock timings of the phases of an operation, dumped by `--timings`.
+//!
+//! Timing is only compiled in with the `perf-metrics` feature; without it
+//! [`time`] just runs the closure. Phases nest: the parse phase includes the
+//! CRC checks made while parsing, and the write phase any CRCs computed then.
+//!
+//! Each thread records its own timings. Worker threads hand theirs over with
+//! [`merge_thread`] when they finish, so that [`snapshot`] on the main thread
+//! covers the whole operation.
+
+use std::cell::RefCell;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Whether this build records timings.
+pub const ENABLED: bool = cfg!(feature = "perf-metrics");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Parse,
+    Crc,
+    Compression,
+    Write,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 4] = [Phase::Parse, Phase::Crc, Phase::Compression, Phase::Write];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Parse => "parse",
+            Phase::Crc => "crc",
+            Phase::Compression => "compression",
+            Phase::Write => "write",
+        }
+    }
+}
+
+/// How often a phase ran and how long it took in total.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    pub calls: u64,
+    pub total: Duration,
+}
+
+impl Timing {
+    const ZERO: Timing = Timing {
+        calls: 0,
+        total: Duration::ZERO,
+    };
+}
+
+thread_local! {
+    static TIMINGS: RefCell<[Timing; 4]> = const { RefCell::new([Timing::ZERO; 4]) };
+}
+
+/// Timings merged from worker threads that have finished.
+static MERGED: Mutex<[Timing; 4]> = Mutex::new([Timing::ZERO; 4]);
+
+/// Runs `f`, counting its duration towards `phase` on this thread.
+#[cfg(feature = "perf-metrics")]
+pub fn time<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    let start = std::time::Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+    TIMINGS.with(|timings| {
+        let timing = &mut timings.borrow_mut()[phase as usize];
+        timing.calls += 1;
+        timing.total += elapsed;
+    });
+    result
+}
+
+#[cfg(not(feature = "perf-metrics"))]
+pub fn time<T>(_phase: Phase, f: impl FnOnce() -> T) -> T {
+    f()
+}
+
+/// The timings recorded on this thread so far, plus those of the threads
+/// merged with [`merge_thread`], in [`Phase::ALL`] order.
+pub fn snapshot() -> Vec<(Phase, Timing)> {
+    let mut timings = *MERGED.lock().unwrap();
+    TIMINGS.with(|local| {
+        for (timing, local) in timings.iter_mut().zip(*local.borrow()) {
+            timing.calls += local.calls;
+            timing.total += local.total;
+        }
+    });
+    Phase::ALL.into_iter().zip(timings).collect()
+}
+
+/// Moves the timings recorded on this thread into the ones every
+/// [`snapshot`] includes. Worker threads call this as they finish.
+pub fn merge_thread() {
+    let local =
+        TIMINGS.with(|local| std::mem::replace(&mut *local.borrow_mut(), [Timing::ZERO; 4]));
+    let mut merged = MERGED.lock().unwrap();
+    for (timing, local) in merged.iter_mut().zip(local) {
+        timing.calls += local.calls;
+        timing.total += local.total;
+    }
+}
+
+pub fn reset() {
+    TIMINGS.with(|timings| *timings.borrow_mut() = [Timing::ZERO; 4]);
+    *MERGED.lock().unwrap() = [Timing::ZERO; 4];
+}
+
+/// A table of `timings`, one line per phase.
+pub fn report(timings: &[(Phase, Timing)]) -> String {
+    let mut out = format!("{:<12}  {:>8}  {:>12}\n", "PHASE", "CALLS", "TOTAL");
+    for (phase, timing) in timings {
+        out.push_str(&format!(
+            "{:<12}  {:>8}  {:>10.3}ms\n",
+            phase.name(),
+            timing.calls,
+            timing.total.as_secs_f64() * 1000.0
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let timings = [
+            (
+                Phase::Parse,
+                Timing {
+                    calls: 2,
+                    total: Duration::from_micros(1500),
+                },
+            ),
+            (Phase::Write, Timing::default()),
+        ];
+        assert_eq!(
+            report(&timings),
+            "PHASE            CALLS         TOTAL\n\
+             parse                2       1.500ms\n\
+             write                0       0.000ms\n"
+        );
+    }
+
+    #[test]
+    fn test_time() {
+        reset();
+        assert_eq!(time(Phase::Crc, || 42), 42);
+        time(Phase::Crc, || ());
+        let calls = snapshot()[Phase::Crc as usize].1.calls;
+        assert_eq!(calls, if ENABLED { 2 } else { 0 });
+        reset();
+        assert_eq!(snapshot()[Phase::Crc as usize].1, Timing::default());
+
+        std::thread::spawn(|| {
+            time(Phase::Compression, || ());
+            merge_thread();
+        })
+        .join()
+        .unwrap();
+        let calls = snapshot()[Phase::Compression as usize].1.calls;
+        assert_eq!(calls, if ENABLED { 1 } else { 0 });
+        reset();
+    }
+}
@@ -0,0 +1,152 @@
+use crate::chunk_type::ChunkType;
+use crate::json::Value;
+use crate::Result;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Environment variable naming the org registry file when none is given.
+pub const REGISTRY_ENV: &str = "PNGME_ORGS";
+
+/// The two letters an organization owns at the start of private chunk types.
+///
+/// Org-scoped types are always ancillary and private, which fixes the case of
+/// the first two letters, and the third letter must be uppercase. That leaves
+/// the org two letters of its own: `prefix.chunk_type("Cf", true)` for prefix
+/// `ac` is `acCf`. The case of the last letter is the safe-to-copy bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrgPrefix([u8; 2]);
+
+impl OrgPrefix {
+    /// Builds an org-scoped chunk type from a two-letter name.
+    pub fn chunk_type(&self, name: &str, safe_to_copy: bool) -> Result<ChunkType> {
+        let name = name.as_bytes();
+        if name.len() != 2 || !name.iter().all(u8::is_ascii_alphabetic) {
+            return Err("org chunk names are two ASCII letters".into());
+        }
+        let last = if safe_to_copy {
+            name[1].to_ascii_lowercase()
+        } else {
+            name[1].to_ascii_uppercase()
+        };
+        ChunkType::try_from([self.0[0], self.0[1], name[0].to_ascii_uppercase(), last])
+    }
+
+    /// Whether the chunk type lies in this org's namespace.
+    pub fn owns(&self, chunk_type: &ChunkType) -> bool {
+        let bytes = chunk_type.bytes();
+        bytes[..2] == self.0 && chunk_type.is_reserved_bit_valid()
+    }
+}
+
+impl FromStr for OrgPrefix {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.as_bytes() {
+            &[a, b] if a.is_ascii_alphabetic() && b.is_ascii_alphabetic() => {
+                Ok(OrgPrefix([a.to_ascii_lowercase(), b.to_ascii_lowercase()]))
+            }
+            _ => Err(format!("org prefix {:?} is not two ASCII letters", s).into()),
+        }
+    }
+}
+
+impl Display for OrgPrefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.0[0] as char, self.0[1] as char)
+    }
+}
+
+/// Which org owns which prefix, read from a JSON object such as
+/// `{"acme": "ac", "globex": "gx"}`. Prefixes must be unique.
+#[derive(Debug, Default)]
+pub struct Registry {
+    orgs: Vec<(String, OrgPrefix)>,
+}
+
+impl Registry {
+    /// Reads `path`, or the file named by `PNGME_ORGS`; with neither the
+    /// registry is empty.
+    pub fn load(path: Option<&Path>) -> Result<Registry> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match env::var_os(REGISTRY_ENV) {
+                Some(path) => path.into(),
+                None => return Ok(Registry::default()),
+            },
+        };
+        let json = fs::read_to_string(&path)
+            .map_err(|e| format!("reading org registry {}: {}", path.display(), e))?;
+        Registry::from_json(&Value::parse(&json)?)
+    }
+
+    pub fn from_json(value: &Value) -> Result<Registry> {
+        let mut registry = Registry::default();
+        for (org, prefix) in value.as_object().ok_or("org registry must be an object")? {
+            let prefix: OrgPrefix = prefix
+                .as_str()
+                .ok_or_else(|| format!("prefix of org {:?} must be a string", org))?
+                .parse()?;
+            if let Some((owner, _)) = registry.orgs.iter().find(|(_, p)| *p == prefix) {
+                return Err(
+                    format!("orgs {:?} and {:?} share prefix {}", owner, org, prefix).into(),
+                );
+            }
+            registry.orgs.push((org.clone(), prefix));
+        }
+        Ok(registry)
+    }
+
+    /// The prefix of a registered org, or `org` itself if it is a prefix.
+    pub fn resolve(&self, org: &str) -> Result<OrgPrefix> {
+        match self.orgs.iter().find(|(name, _)| name == org) {
+            Some((_, prefix)) => Ok(*prefix),
+            None => org
+                .parse()
+                .map_err(|_| format!("unknown org {:?}", org).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_type() {
+        let prefix: OrgPrefix = "AC".parse().unwrap();
+        let safe = prefix.chunk_type("cf", true).unwrap();
+        assert_eq!(safe.to_string(), "acCf");
+        assert!(!safe.is_critical() && !safe.is_public() && safe.is_safe_to_copy());
+        assert_eq!(prefix.chunk_type("cf", false).unwrap().to_string(), "acCF");
+        assert!(prefix.chunk_type("c", true).is_err());
+        assert!(prefix.chunk_type("c1", true).is_err());
+    }
+
+    #[test]
+    fn test_owns() {
+        let prefix: OrgPrefix = "ac".parse().unwrap();
+        assert!(prefix.owns(&ChunkType::from_str("acCf").unwrap()));
+        assert!(!prefix.owns(&ChunkType::from_str("gxCf").unwrap()));
+        assert!(!prefix.owns(&ChunkType::from_str("acxf").unwrap()));
+        assert!(!prefix.owns(&ChunkType::from_str("ACCf").unwrap()));
+    }
+
+    #[test]
+    fn test_registry() {
+        let registry =
+            Registry::from_json(&Value::parse(r#"{"acme": "ac", "globex": "GX"}"#).unwrap())
+                .unwrap();
+        assert_eq!(registry.resolve("globex").unwrap().to_string(), "gx");
+        assert_eq!(registry.resolve("zz").unwrap().to_string(), "zz");
+        assert!(registry.resolve("initech").is_err());
+
+        assert!(
+            Registry::from_json(&Value::parse(r#"{"acme": "ac", "acorn": "ac"}"#).unwrap())
+                .is_err()
+        );
+    }
+}
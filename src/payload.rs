@@ -0,0 +1,619 @@
+use crate::hash;
+use crate::i18n::{self, CodedError};
+use crate::transform::{self, Options, Transform};
+use crate::view::{ChunkRef, PngRef};
+use crate::{chunk::Chunk, chunk_type::ChunkType, png::Png, Result};
+use std::io::{self, Write};
+
+/// Metadata stored in front of every payload pngme writes.
+///
+/// Layout: the 4 magic bytes, a version byte, a field count byte, then that
+/// many `tag: u8, length: u16 BE, value` fields. Decoders skip tags they do not
+/// know, so new fields can be added without breaking older readers.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PayloadHeader {
+    /// Ids of the transforms in the order they were applied.
+    pub transforms: Vec<u8>,
+    /// Identifies the preset dictionary the payload was compressed with.
+    pub dictionary_id: Option<u32>,
+    /// When a versioned payload was stored, in seconds since the Unix epoch.
+    pub timestamp: Option<u64>,
+    /// BLAKE3 hash of the payload before any transform, checked on decode.
+    /// Only payloads written before checksums existed lack one.
+    pub checksum: Option<[u8; 32]>,
+}
+
+impl PayloadHeader {
+    /// A leading NUL never starts a plain text message.
+    pub const MAGIC: [u8; 4] = *b"\0PMH";
+    pub const VERSION: u8 = 1;
+
+    const TAG_TRANSFORMS: u8 = 1;
+    const TAG_DICTIONARY: u8 = 2;
+    const TAG_TIMESTAMP: u8 = 3;
+    const TAG_CHECKSUM: u8 = 4;
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut fields = vec![(PayloadHeader::TAG_TRANSFORMS, self.transforms.clone())];
+        if let Some(id) = self.dictionary_id {
+            fields.push((PayloadHeader::TAG_DICTIONARY, id.to_be_bytes().to_vec()));
+        }
+        if let Some(timestamp) = self.timestamp {
+            fields.push((
+                PayloadHeader::TAG_TIMESTAMP,
+                timestamp.to_be_bytes().to_vec(),
+            ));
+        }
+        if let Some(checksum) = self.checksum {
+            fields.push((PayloadHeader::TAG_CHECKSUM, checksum.to_vec()));
+        }
+
+        let mut bytes = PayloadHeader::MAGIC.to_vec();
+        bytes.push(PayloadHeader::VERSION);
+        bytes.push(fields.len() as u8);
+        for (tag, value) in fields {
+            bytes.push(tag);
+            bytes.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(&value);
+        }
+        bytes
+    }
+
+    /// Splits `data` into its header and body, or returns `None` when the data
+    /// carries no header at all.
+    pub fn parse(data: &[u8]) -> Result<Option<(PayloadHeader, &[u8])>> {
+        let Some(rest) = data.strip_prefix(&PayloadHeader::MAGIC) else {
+            return Ok(None);
+        };
+        let (&[version, field_count], mut rest) = rest.split_at(2.min(rest.len())) else {
+            return Err(CodedError::new(&i18n::PAYLOAD_HEADER_TRUNCATED, vec![]))?;
+        };
+        if version != PayloadHeader::VERSION {
+            return Err(CodedError::new(
+                &i18n::PAYLOAD_HEADER_VERSION,
+                vec![version.to_string()],
+            ))?;
+        }
+
+        let mut header = PayloadHeader::default();
+        for _ in 0..field_count {
+            if rest.len() < 3 {
+                return Err(CodedError::new(&i18n::PAYLOAD_HEADER_TRUNCATED, vec![]))?;
+            }
+            let tag = rest[0];
+            let length = u16::from_be_bytes([rest[1], rest[2]]) as usize;
+            let value = rest
+                .get(3..3 + length)
+                .ok_or_else(|| CodedError::new(&i18n::PAYLOAD_HEADER_TRUNCATED, vec![]))?;
+            match tag {
+                PayloadHeader::TAG_TRANSFORMS => header.transforms = value.to_vec(),
+                PayloadHeader::TAG_DICTIONARY => {
+                    let id: [u8; 4] = value
+                        .try_into()
+                        .map_err(|_| "invalid dictionary id in payload header")?;
+                    header.dictionary_id = Some(u32::from_be_bytes(id));
+                }
+                PayloadHeader::TAG_TIMESTAMP => {
+                    let timestamp: [u8; 8] = value
+                        .try_into()
+                        .map_err(|_| "invalid timestamp in payload header")?;
+                    header.timestamp = Some(u64::from_be_bytes(timestamp));
+                }
+                PayloadHeader::TAG_CHECKSUM => {
+                    let checksum = value
+                        .try_into()
+                        .map_err(|_| "invalid checksum in payload header")?;
+                    header.checksum = Some(checksum);
+                }
+                _ => {}
+            }
+            rest = &rest[3 + length..];
+        }
+
+        Ok(Some((header, rest)))
+    }
+}
+
+/// Runs `data` through `transforms` in order and puts a header with a
+/// checksum of `data` in front, even when there are no transforms.
+pub fn encode_payload(
+    data: &[u8],
+    transforms: &[Box<dyn Transform>],
+    options: &Options,
+) -> Result<Vec<u8>> {
+    encode_versioned_payload(data, transforms, options, None)
+}
+
+/// Like [`encode_payload`], but stamps the header with `timestamp` so the
+/// payload can sit next to earlier versions of itself.
+pub fn encode_versioned_payload(
+    data: &[u8],
+    transforms: &[Box<dyn Transform>],
+    options: &Options,
+    timestamp: Option<u64>,
+) -> Result<Vec<u8>> {
+    let mut body = data.to_vec();
+    for transform in transforms {
+        body = transform.apply(body, options)?;
+    }
+
+    let uses_dictionary = transforms
+        .iter()
+        .any(|transform| transform.uses_dictionary());
+    let header = PayloadHeader {
+        transforms: transforms.iter().map(|transform| transform.id()).collect(),
+        dictionary_id: options
+            .dictionary
+            .as_deref()
+            .filter(|_| uses_dictionary)
+            .map(transform::dictionary_id),
+        timestamp,
+        checksum: Some(hash::blake3(data)),
+    };
+    let mut payload = header.to_bytes();
+    payload.extend_from_slice(&body);
+    Ok(payload)
+}
+
+/// Encodes `data` with each of `pipelines` and keeps the smallest payload,
+/// the earliest pipeline winning ties. Its header records which one it was.
+pub fn encode_smallest_payload(
+    data: &[u8],
+    pipelines: &[Vec<Box<dyn Transform>>],
+    options: &Options,
+    timestamp: Option<u64>,
+) -> Result<Vec<u8>> {
+    let mut smallest: Option<Vec<u8>> = None;
+    for transforms in pipelines {
+        let payload = encode_versioned_payload(data, transforms, options, timestamp)?;
+        if smallest.as_ref().is_none_or(|s| payload.len() < s.len()) {
+            smallest = Some(payload);
+        }
+    }
+    smallest.ok_or_else(|| "no transform pipeline to encode with".into())
+}
+
+/// Undoes whatever transforms the payload header records, then checks the
+/// result against the header's checksum, if it has one. Data without a
+/// header is a plain payload from an older pngme and is returned as-is.
+pub fn decode_payload(data: &[u8], options: &Options) -> Result<Vec<u8>> {
+    let Some((header, body)) = PayloadHeader::parse(data)? else {
+        return Ok(data.to_vec());
+    };
+
+    if let Some(id) = header.dictionary_id {
+        match options.dictionary.as_deref().map(transform::dictionary_id) {
+            None => Err(CodedError::new(
+                &i18n::DICTIONARY_MISSING,
+                vec![format!("{:08x}", id)],
+            ))?,
+            Some(given) if given != id => Err(CodedError::new(
+                &i18n::DICTIONARY_MISMATCH,
+                vec![format!("{:08x}", id), format!("{:08x}", given)],
+            ))?,
+            Some(_) => {}
+        }
+    }
+
+    let mut body = body.to_vec();
+    for &id in header.transforms.iter().rev() {
+        body = transform::by_id(id)?.reverse(body, options)?;
+    }
+    if let Some(expected) = header.checksum {
+        let actual = hash::blake3(&body);
+        if actual != expected {
+            Err(CodedError::new(
+                &i18n::PAYLOAD_CORRUPT,
+                vec![hash::to_hex(&expected), hash::to_hex(&actual)],
+            ))?
+        }
+    }
+    Ok(body)
+}
+
+/// One stored payload under a chunk type, as listed by `history`.
+#[derive(Debug, Clone, Copy)]
+pub struct Version<'a> {
+    /// Position among the chunks of the file.
+    pub index: usize,
+    /// `None` for payloads stored without `--versioned`.
+    pub timestamp: Option<u64>,
+    pub chunk: ChunkRef<'a>,
+}
+
+/// Every payload stored under `chunk_type`, in file order.
+pub fn versions<'a>(png: &PngRef<'a>, chunk_type: ChunkType) -> Vec<Version<'a>> {
+    png.chunks()
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|(_, chunk)| chunk.chunk_type() == chunk_type)
+        .map(|(index, chunk)| Version {
+            index,
+            timestamp: PayloadHeader::parse(chunk.data())
+                .ok()
+                .flatten()
+                .and_then(|(header, _)| header.timestamp),
+            chunk,
+        })
+        .collect()
+}
+
+/// The version to read. Without `at` that is the newest versioned payload,
+/// or the first payload when none are versioned; with `at` it is the newest
+/// versioned payload stored no later than `at`.
+pub fn version_at<'a>(versions: &[Version<'a>], at: Option<u64>) -> Option<Version<'a>> {
+    let newest = versions
+        .iter()
+        .filter(|version| {
+            version
+                .timestamp
+                .is_some_and(|t| at.is_none_or(|at| t <= at))
+        })
+        // On equal timestamps the later chunk wins.
+        .max_by_key(|version| (version.timestamp, version.index));
+    match (newest, at) {
+        (Some(version), _) => Some(*version),
+        (None, None) => versions.first().copied(),
+        (None, Some(_)) => None,
+    }
+}
+
+/// Appends a payload to a PNG as a run of same-typed chunks. Every chunk
+/// starts with a big-endian `u32` sequence number, so a payload can grow over
+/// several sessions and still be read back in order with [`read_payload`].
+///
+/// Bytes are buffered until a full segment is available; the remainder is
+/// written as a final chunk on [`flush`](Write::flush), [`close`](Self::close)
+/// or drop. Writing fails once the sequence numbers run out.
+pub struct PayloadWriter<'a> {
+    png: &'a mut Png,
+    chunk_type: ChunkType,
+    /// The number for the next segment, or `None` after segment `u32::MAX`.
+    sequence: Option<u32>,
+    segment_size: usize,
+    buffer: Vec<u8>,
+}
+
+impl<'a> PayloadWriter<'a> {
+    pub const DEFAULT_SEGMENT_SIZE: usize = 8 * 1024;
+
+    /// A writer continuing after the last segment already in `png`.
+    pub fn new(png: &'a mut Png, chunk_type: ChunkType) -> Result<PayloadWriter<'a>> {
+        let sequence = match segments(png, chunk_type)
+            .map(|(sequence, _)| sequence)
+            .max()
+        {
+            Some(last) => last.checked_add(1).ok_or_else(|| {
+                format!("the {} payload has no sequence numbers left", chunk_type)
+            })?,
+            None => 0,
+        };
+
+        Ok(PayloadWriter {
+            png,
+            chunk_type,
+            sequence: Some(sequence),
+            segment_size: PayloadWriter::DEFAULT_SEGMENT_SIZE,
+            buffer: vec![],
+        })
+    }
+
+    pub fn with_segment_size(mut self, segment_size: usize) -> PayloadWriter<'a> {
+        self.segment_size = segment_size.max(1);
+        self
+    }
+
+    /// Writes out any buffered bytes. Equivalent to dropping the writer,
+    /// except that a failure is reported.
+    pub fn close(mut self) -> io::Result<()> {
+        self.emit_segment(self.buffer.len())
+    }
+
+    fn emit_segment(&mut self, len: usize) -> io::Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
+        let Some(sequence) = self.sequence else {
+            return Err(io::Error::other(format!(
+                "the {} payload has no sequence numbers left",
+                self.chunk_type
+            )));
+        };
+
+        let data = sequence
+            .to_be_bytes()
+            .iter()
+            .chain(self.buffer.drain(..len).as_slice())
+            .copied()
+            .collect();
+        self.sequence = sequence.checked_add(1);
+
+        self.png
+            .insert_before_iend(Chunk::new(self.chunk_type, data));
+        Ok(())
+    }
+}
+
+impl Write for PayloadWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        while self.buffer.len() >= self.segment_size {
+            self.emit_segment(self.segment_size)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.emit_segment(self.buffer.len())
+    }
+}
+
+impl Drop for PayloadWriter<'_> {
+    fn drop(&mut self) {
+        let _ = self.emit_segment(self.buffer.len());
+    }
+}
+
+/// Reassembles a payload written by [`PayloadWriter`], ordering the segments
+/// by sequence number.
+pub fn read_payload(png: &Png, chunk_type: ChunkType) -> Vec<u8> {
+    let mut segments: Vec<_> = segments(png, chunk_type).collect();
+    segments.sort_by_key(|&(sequence, _)| sequence);
+    segments
+        .into_iter()
+        .flat_map(|(_, data)| data)
+        .copied()
+        .collect()
+}
+
+fn segments(png: &Png, chunk_type: ChunkType) -> impl Iterator<Item = (u32, &[u8])> {
+    png.chunks()
+        .iter()
+        .filter(move |chunk| chunk.chunk_type() == chunk_type && chunk.data().len() >= 4)
+        .map(|chunk| {
+            let (sequence, data) = chunk.data().split_at(4);
+            let mut buffer = [0u8; 4];
+            buffer.copy_from_slice(sequence);
+            (u32::from_be_bytes(buffer), data)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ])
+    }
+
+    fn log_type() -> ChunkType {
+        ChunkType::from_str("loGs").unwrap()
+    }
+
+    #[test]
+    fn test_header_round_trip() {
+        let header = PayloadHeader {
+            transforms: vec![1, 2],
+            dictionary_id: Some(0xdeadbeef),
+            timestamp: Some(1717243199),
+            checksum: Some([7; 32]),
+        };
+        let mut bytes = header.to_bytes();
+        bytes.extend_from_slice(b"body");
+
+        let (parsed, body) = PayloadHeader::parse(&bytes).unwrap().unwrap();
+        assert_eq!(parsed, header);
+        assert_eq!(body, b"body");
+
+        assert!(PayloadHeader::parse(b"plain message").unwrap().is_none());
+        assert!(PayloadHeader::parse(&bytes[..7]).is_err());
+    }
+
+    #[test]
+    fn test_versions() {
+        let mut png = testing_png();
+        let options = Options::default();
+        png.insert_before_iend(Chunk::new(log_type(), b"legacy".to_vec()));
+        for (timestamp, message) in [(200, "second"), (100, "first"), (300, "third")] {
+            let data = encode_versioned_payload(message.as_bytes(), &[], &options, Some(timestamp))
+                .unwrap();
+            png.insert_before_iend(Chunk::new(log_type(), data));
+        }
+
+        let versions = versions(&png.view(), log_type());
+        assert_eq!(
+            versions.iter().map(|v| v.timestamp).collect::<Vec<_>>(),
+            [None, Some(200), Some(100), Some(300)]
+        );
+        let read = |at| {
+            version_at(&versions, at)
+                .map(|version| decode_payload(version.chunk.data(), &options).unwrap())
+        };
+        assert_eq!(read(None).unwrap(), b"third");
+        assert_eq!(read(Some(250)).unwrap(), b"second");
+        assert_eq!(read(Some(100)).unwrap(), b"first");
+        assert_eq!(read(Some(99)), None);
+
+        let unversioned = versions.into_iter().take(1).collect::<Vec<_>>();
+        assert_eq!(version_at(&unversioned, None).unwrap().index, 1);
+    }
+
+    #[test]
+    fn test_header_skips_unknown_fields() {
+        let mut bytes = PayloadHeader::MAGIC.to_vec();
+        bytes.extend_from_slice(&[PayloadHeader::VERSION, 2, 99, 0, 1, 0xff, 1, 0, 1, 2]);
+        bytes.extend_from_slice(b"body");
+
+        let (parsed, body) = PayloadHeader::parse(&bytes).unwrap().unwrap();
+        assert_eq!(parsed.transforms, vec![2]);
+        assert_eq!(body, b"body");
+    }
+
+    #[test]
+    fn test_payload_pipeline() {
+        let transforms = vec![
+            transform::by_name("compress").unwrap(),
+            transform::by_name("base64").unwrap(),
+        ];
+        let message = "a message worth compressing ".repeat(10);
+
+        let options = Options::default();
+
+        let encoded = encode_payload(message.as_bytes(), &transforms, &options).unwrap();
+        assert!(encoded.starts_with(&PayloadHeader::MAGIC));
+        assert_eq!(
+            decode_payload(&encoded, &options).unwrap(),
+            message.as_bytes()
+        );
+
+        let encoded = encode_payload(b"plain", &[], &options).unwrap();
+        let (header, body) = PayloadHeader::parse(&encoded).unwrap().unwrap();
+        assert_eq!(header.checksum, Some(hash::blake3(b"plain")));
+        assert_eq!(body, b"plain");
+        assert_eq!(decode_payload(&encoded, &options).unwrap(), b"plain");
+        assert_eq!(decode_payload(b"legacy", &options).unwrap(), b"legacy");
+    }
+
+    #[test]
+    fn test_payload_checksum() {
+        let transforms = vec![transform::by_name("base64").unwrap()];
+        let options = Options::default();
+        let mut encoded = encode_payload(b"a message", &transforms, &options).unwrap();
+        let (header, _) = PayloadHeader::parse(&encoded).unwrap().unwrap();
+        assert_eq!(header.checksum, Some(hash::blake3(b"a message")));
+
+        // Flip a bit that still decodes as base64, as bit rot might.
+        let last = encoded.len() - 3;
+        encoded[last] ^= 0x20;
+        let error = decode_payload(&encoded, &options).unwrap_err();
+        let error = error.downcast_ref::<CodedError>().unwrap();
+        assert_eq!(error.message, &i18n::PAYLOAD_CORRUPT);
+        assert_eq!(error.args[0], hash::to_hex(&hash::blake3(b"a message")));
+    }
+
+    #[test]
+    fn test_smallest_payload() {
+        let pipelines = || vec![vec![], vec![transform::by_name("compress").unwrap()]];
+        let options = Options::default();
+
+        let repetitive = "a message worth compressing ".repeat(10);
+        let encoded =
+            encode_smallest_payload(repetitive.as_bytes(), &pipelines(), &options, None).unwrap();
+        let (header, _) = PayloadHeader::parse(&encoded).unwrap().unwrap();
+        assert_eq!(header.transforms, vec![1]);
+        assert_eq!(
+            decode_payload(&encoded, &options).unwrap(),
+            repetitive.as_bytes()
+        );
+
+        let short = b"hi";
+        let encoded = encode_smallest_payload(short, &pipelines(), &options, None).unwrap();
+        let (header, body) = PayloadHeader::parse(&encoded).unwrap().unwrap();
+        assert_eq!(header.transforms, vec![]);
+        assert_eq!(body, short);
+    }
+
+    #[test]
+    fn test_payload_dictionary() {
+        let transforms = vec![transform::by_name("compress").unwrap()];
+        let dictionary = Options {
+            dictionary: Some(br#"{"release": "", "commit": ""}"#.to_vec()),
+        };
+        let message = br#"{"release": "1.0.0", "commit": "abc123"}"#;
+
+        let encoded = encode_payload(message, &transforms, &dictionary).unwrap();
+        let (header, _) = PayloadHeader::parse(&encoded).unwrap().unwrap();
+        assert_eq!(
+            header.dictionary_id,
+            Some(transform::dictionary_id(
+                dictionary.dictionary.as_ref().unwrap()
+            ))
+        );
+        assert_eq!(decode_payload(&encoded, &dictionary).unwrap(), message);
+
+        assert!(decode_payload(&encoded, &Options::default()).is_err());
+        let other = Options {
+            dictionary: Some(b"something else".to_vec()),
+        };
+        assert!(decode_payload(&encoded, &other).is_err());
+
+        let base64 = vec![transform::by_name("base64").unwrap()];
+        let encoded = encode_payload(message, &base64, &dictionary).unwrap();
+        let (header, _) = PayloadHeader::parse(&encoded).unwrap().unwrap();
+        assert_eq!(header.dictionary_id, None);
+    }
+
+    #[test]
+    fn test_write_segments() {
+        let mut png = testing_png();
+        let mut writer = PayloadWriter::new(&mut png, log_type())
+            .unwrap()
+            .with_segment_size(4);
+        writer.write_all(b"0123456789").unwrap();
+        writer.close().unwrap();
+
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, vec!["IHDR", "loGs", "loGs", "loGs", "IEND"]);
+        assert_eq!(read_payload(&png, log_type()), b"0123456789");
+    }
+
+    #[test]
+    fn test_append_over_time() {
+        let mut png = testing_png();
+        {
+            let mut writer = PayloadWriter::new(&mut png, log_type()).unwrap();
+            writer.write_all(b"first line\n").unwrap();
+        }
+        {
+            let mut writer = PayloadWriter::new(&mut png, log_type()).unwrap();
+            writer.write_all(b"second line\n").unwrap();
+        }
+
+        assert_eq!(read_payload(&png, log_type()), b"first line\nsecond line\n");
+    }
+
+    #[test]
+    fn test_read_payload_orders_by_sequence() {
+        let mut png = testing_png();
+        png.append_chunk(Chunk::new(log_type(), b"\0\0\0\x01world".to_vec()));
+        png.append_chunk(Chunk::new(log_type(), b"\0\0\0\x00hello ".to_vec()));
+
+        assert_eq!(read_payload(&png, log_type()), b"hello world");
+    }
+
+    #[test]
+    fn test_empty_writer_adds_nothing() {
+        let mut png = testing_png();
+        PayloadWriter::new(&mut png, log_type())
+            .unwrap()
+            .close()
+            .unwrap();
+        assert_eq!(png.chunks().len(), 2);
+    }
+
+    #[test]
+    fn test_sequence_numbers_run_out() {
+        let mut png = testing_png();
+        png.append_chunk(Chunk::new(log_type(), b"\xff\xff\xff\xfelast".to_vec()));
+        let mut writer = PayloadWriter::new(&mut png, log_type())
+            .unwrap()
+            .with_segment_size(1);
+        assert!(writer.write_all(b"ab").is_err());
+        drop(writer);
+        let last = segments(&png, log_type())
+            .map(|(sequence, _)| sequence)
+            .max();
+        assert_eq!(last, Some(u32::MAX));
+
+        assert!(PayloadWriter::new(&mut png, log_type()).is_err());
+    }
+}
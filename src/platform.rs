@@ -0,0 +1,175 @@
+//! Differences between Windows and Unix consoles and file systems.
+//!
+//! Windows limits ordinary paths to 260 UTF-16 units unless they carry the
+//! `\\?\` prefix, needs virtual terminal processing switched on before it
+//! renders ANSI colors, and its editors tend to save text with CRLF line
+//! endings. Elsewhere the helpers here do nothing.
+
+use std::borrow::Cow;
+use std::path::Path;
+use std::str::FromStr;
+
+/// `path` in a form Windows can open even when it is longer than MAX_PATH.
+/// Short paths and other platforms get `path` back unchanged.
+#[cfg(windows)]
+pub fn long_path(path: &Path) -> Cow<'_, Path> {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use std::path::PathBuf;
+
+    const MAX_PATH: usize = 260;
+    // Unlike `canonicalize`, this works for files that do not exist yet.
+    let Ok(absolute) = std::path::absolute(path) else {
+        return Cow::Borrowed(path);
+    };
+    let wide: Vec<u16> = absolute.as_os_str().encode_wide().collect();
+    if wide.len() < MAX_PATH {
+        return Cow::Borrowed(path);
+    }
+    Cow::Owned(PathBuf::from(OsString::from_wide(&verbatim(&wide))))
+}
+
+#[cfg(not(windows))]
+pub fn long_path(path: &Path) -> Cow<'_, Path> {
+    Cow::Borrowed(path)
+}
+
+/// Adds the `\\?\` prefix to an absolute, normalized Windows path, given as
+/// UTF-16 so file names that are not valid Unicode survive.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn verbatim(path: &[u16]) -> Vec<u16> {
+    let wide = |s: &str| s.encode_utf16().collect::<Vec<u16>>();
+    if path.starts_with(&wide(r"\\?\")) || path.starts_with(&wide(r"\\.\")) {
+        return path.to_vec();
+    }
+    match path.strip_prefix(&wide(r"\\")[..]) {
+        // \\server\share\file becomes \\?\UNC\server\share\file.
+        Some(unc) => [wide(r"\\?\UNC\"), unc.to_vec()].concat(),
+        None => [wide(r"\\?\"), path.to_vec()].concat(),
+    }
+}
+
+/// Prepares the console for the ANSI colors `sniff::pretty` writes and says
+/// whether it will show them. Consoles older than Windows 10 cannot.
+#[cfg(windows)]
+pub fn enable_colors() -> bool {
+    use std::ffi::c_void;
+
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetStdHandle(std_handle: u32) -> *mut c_void;
+        fn GetConsoleMode(console: *mut c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: *mut c_void, mode: u32) -> i32;
+    }
+
+    // SAFETY: the handle comes from GetStdHandle and `mode` outlives the call.
+    unsafe {
+        let console = GetStdHandle(STD_OUTPUT_HANDLE);
+        let mut mode = 0;
+        GetConsoleMode(console, &mut mode) != 0
+            && (mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+                || SetConsoleMode(console, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0)
+    }
+}
+
+#[cfg(not(windows))]
+pub fn enable_colors() -> bool {
+    true
+}
+
+/// Line endings to give text payloads, chosen with `--newline`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Newline {
+    /// Leave line endings as they are.
+    #[default]
+    Preserve,
+    /// Turn CRLF into LF.
+    Lf,
+    /// Turn lone LF into CRLF.
+    Crlf,
+}
+
+impl Newline {
+    /// `text` with its line endings converted. Data that is not UTF-8 text is
+    /// returned unchanged, so binary payloads are never altered.
+    pub fn apply<'a>(&self, text: &'a [u8]) -> Cow<'a, [u8]> {
+        let Ok(string) = std::str::from_utf8(text) else {
+            return Cow::Borrowed(text);
+        };
+        match self {
+            Newline::Preserve => Cow::Borrowed(text),
+            Newline::Lf => Cow::Owned(string.replace("\r\n", "\n").into_bytes()),
+            Newline::Crlf => Cow::Owned(
+                string
+                    .replace("\r\n", "\n")
+                    .replace('\n', "\r\n")
+                    .into_bytes(),
+            ),
+        }
+    }
+}
+
+impl FromStr for Newline {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "preserve" => Ok(Newline::Preserve),
+            "lf" => Ok(Newline::Lf),
+            "crlf" => Ok(Newline::Crlf),
+            _ => Err(format!("expected lf, crlf or preserve, not {:?}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn test_verbatim() {
+        assert_eq!(
+            verbatim(&wide(r"C:\images\a.png")),
+            wide(r"\\?\C:\images\a.png")
+        );
+        assert_eq!(
+            verbatim(&wide(r"\\server\share\a.png")),
+            wide(r"\\?\UNC\server\share\a.png")
+        );
+        assert_eq!(
+            verbatim(&wide(r"\\?\C:\images\a.png")),
+            wide(r"\\?\C:\images\a.png")
+        );
+
+        // An unpaired surrogate, which only UTF-16 file names can hold.
+        let mut unpaired = wide(r"C:\");
+        unpaired.push(0xd800);
+        assert_eq!(verbatim(&unpaired)[4..], unpaired[..]);
+    }
+
+    #[test]
+    fn test_long_path_keeps_short_paths() {
+        assert_eq!(long_path(Path::new("a.png")), Path::new("a.png"));
+    }
+
+    #[test]
+    fn test_newline() {
+        let text = b"one\r\ntwo\nthree";
+        assert_eq!(Newline::Preserve.apply(text), &text[..]);
+        assert_eq!(Newline::Lf.apply(text), &b"one\ntwo\nthree"[..]);
+        assert_eq!(Newline::Crlf.apply(text), &b"one\r\ntwo\r\nthree"[..]);
+
+        let binary = b"\xff\n\r\n";
+        assert_eq!(Newline::Lf.apply(binary), &binary[..]);
+
+        assert_eq!("crlf".parse::<Newline>(), Ok(Newline::Crlf));
+        assert!("cr".parse::<Newline>().is_err());
+    }
+}
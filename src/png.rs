@@ -1,19 +1,24 @@
+use crate::chunk::{Chunk, CrcProfile};
+use crate::chunk_type::ChunkType;
+use crate::i18n::{self, CodedError, Lang, Message};
+use crate::limits::{Budget, Limits};
+use crate::metrics::{self, Phase};
+use crate::validation::{self, Rule};
+use crate::view::PngRef;
+use crate::warning::Warning;
 use crate::Error;
-use crate::{
-    chunk::{self, Chunk},
-    chunk_type::ChunkType,
-};
-use crate::{chunk_type, Result};
+use crate::Result;
 use std::fmt::Display;
+use std::io::{self, Read, Write};
 use std::str::FromStr;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Png {
     chunks: Vec<Chunk>,
 }
 
 impl Png {
-    const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+    pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
     pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
         Png { chunks }
@@ -23,6 +28,25 @@ impl Png {
         self.chunks.push(chunk);
     }
 
+    pub fn insert_chunk(&mut self, index: usize, chunk: Chunk) {
+        self.chunks.insert(index, chunk);
+    }
+
+    /// Adds a chunk right before IEND, which has to stay the last chunk.
+    /// Without an IEND this is the same as `append_chunk`.
+    pub fn insert_before_iend(&mut self, chunk: Chunk) {
+        let index = self
+            .chunks
+            .iter()
+            .rposition(|chunk| chunk.chunk_type().bytes() == *b"IEND")
+            .unwrap_or(self.chunks.len());
+        self.chunks.insert(index, chunk);
+    }
+
+    pub fn remove_chunk_at(&mut self, index: usize) -> Chunk {
+        self.chunks.remove(index)
+    }
+
     pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
         let chunk_type = ChunkType::from_str(chunk_type)?;
 
@@ -32,7 +56,32 @@ impl Png {
             }
         }
 
-        Err("Not Found")?
+        Err(CodedError::new(&i18n::CHUNK_NOT_FOUND, vec![]))?
+    }
+
+    /// Unknown ancillary chunks whose safe-to-copy bit is 0. Editors that modify
+    /// critical chunks must not copy these to the output unchanged.
+    pub fn unsafe_to_copy_chunks(&self) -> impl Iterator<Item = &Chunk> {
+        self.chunks
+            .iter()
+            .filter(|chunk| Png::is_unsafe_to_copy(chunk))
+    }
+
+    pub fn remove_unsafe_to_copy_chunks(&mut self) -> Vec<Chunk> {
+        let (removed, kept) = self.chunks.drain(..).partition(Png::is_unsafe_to_copy);
+        self.chunks = kept;
+        removed
+    }
+
+    fn is_unsafe_to_copy(chunk: &Chunk) -> bool {
+        let chunk_type = chunk.chunk_type();
+        !chunk_type.is_critical() && !chunk_type.is_known() && !chunk_type.is_safe_to_copy()
+    }
+
+    /// Structural problems that do not stop the file from being parsed.
+    /// See [`validation::warnings`].
+    pub fn warnings(&self) -> Vec<Warning> {
+        validation::warnings(self)
     }
 
     pub fn header(&self) -> &[u8; 8] {
@@ -43,8 +92,44 @@ impl Png {
         &self.chunks
     }
 
+    /// A borrowed view, for code written against `PngRef`.
+    pub fn view(&self) -> PngRef<'_> {
+        PngRef::from_chunks(self.chunks.iter().map(Chunk::view).collect())
+    }
+
+    /// Each chunk with the file offset of its length field.
+    pub fn chunks_with_offsets(&self) -> impl Iterator<Item = (usize, &Chunk)> {
+        self.chunks
+            .iter()
+            .scan(Png::STANDARD_HEADER.len(), |offset, chunk| {
+                let start = *offset;
+                *offset += chunk.length() as usize + 12;
+                Some((start, chunk))
+            })
+    }
+
+    /// Index of the chunk whose length field starts at `offset`, as listed
+    /// by `scan`.
+    pub fn chunk_index_at(&self, offset: usize) -> Result<usize> {
+        match self
+            .chunks_with_offsets()
+            .position(|(start, _)| start == offset)
+        {
+            Some(index) => Ok(index),
+            None => Err(CodedError::new(
+                &i18n::NO_CHUNK_AT_OFFSET,
+                vec![format!("{:#x}", offset)],
+            ))?,
+        }
+    }
+
+    pub fn chunks_of_type(&self, chunk_type: ChunkType) -> impl Iterator<Item = &Chunk> {
+        self.chunks
+            .iter()
+            .filter(move |chunk| chunk.chunk_type() == chunk_type)
+    }
+
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
-        
         let chunk_type = ChunkType::from_str(chunk_type).ok()?;
 
         self.chunks()
@@ -52,6 +137,94 @@ impl Png {
             .find(|&chunk| chunk.chunk_type() == chunk_type)
     }
 
+    pub fn chunk_data_reader(&self, chunk_type: &str) -> Option<impl Read + '_> {
+        self.chunk_by_type(chunk_type).map(Chunk::data_reader)
+    }
+
+    /// Parses a PNG one chunk at a time, so only a single chunk's bytes are
+    /// ever waiting in memory on top of the chunks already read.
+    pub fn read_from<R: Read>(reader: R) -> Result<Png> {
+        Png::read_with_crc_profile(reader, &CrcProfile::Standard, &Limits::default())
+    }
+
+    /// Like `read_from`, checking CRCs with `profile` and failing with a
+    /// `LimitError` when the file exceeds `limits`.
+    pub fn read_with_crc_profile<R: Read>(
+        mut reader: R,
+        profile: &CrcProfile,
+        limits: &Limits,
+    ) -> Result<Png> {
+        metrics::time(Phase::Parse, || {
+            Png::read_header(&mut reader)?;
+            let mut chunks = vec![];
+            Png::read_chunks(&mut reader, &mut chunks, profile, limits)?;
+            Ok(Png { chunks })
+        })
+    }
+
+    /// Like `read_from`, but a file that was cut off mid-chunk still yields
+    /// every complete chunk before the cut, together with the offset of the
+    /// incomplete one. Any other damage is still an error.
+    pub fn read_partial<R: Read>(
+        mut reader: R,
+        profile: &CrcProfile,
+        limits: &Limits,
+    ) -> Result<(Png, Option<usize>)> {
+        metrics::time(Phase::Parse, || {
+            Png::read_header(&mut reader)?;
+            let mut chunks = vec![];
+            let truncated_at = match Png::read_chunks(&mut reader, &mut chunks, profile, limits) {
+                Ok(()) => None,
+                Err(e) => match e.downcast_ref::<ParseError>() {
+                    Some(&ParseError::Truncated { offset }) => Some(offset),
+                    _ => return Err(e),
+                },
+            };
+            Ok((Png { chunks }, truncated_at))
+        })
+    }
+
+    pub(crate) fn read_header<R: Read>(reader: &mut R) -> Result<()> {
+        let mut header = [0u8; 8];
+        match reader.read_exact(&mut header) {
+            // A file too short for the signature simply does not have one.
+            Err(e) if e.kind() != io::ErrorKind::UnexpectedEof => Err(e)?,
+            Ok(()) if header == Png::STANDARD_HEADER => Ok(()),
+            _ => Err(ParseError::InvalidSignature)?,
+        }
+    }
+
+    fn read_chunks<R: Read>(
+        reader: &mut R,
+        chunks: &mut Vec<Chunk>,
+        profile: &CrcProfile,
+        limits: &Limits,
+    ) -> Result<()> {
+        let mut walker = ChunkWalker::new(profile, limits);
+        while let Some(bytes) = walker.read_from(reader)? {
+            walker.accept(&bytes)?;
+            chunks.push(Chunk::from_accepted(bytes, profile));
+        }
+        Ok(())
+    }
+
+    /// Like `read_from`, also returning the warnings for the parsed file.
+    pub fn read_with_warnings<R: Read>(reader: R) -> Result<(Png, Vec<Warning>)> {
+        let png = Png::read_from(reader)?;
+        let warnings = png.warnings();
+        Ok((png, warnings))
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        metrics::time(Phase::Write, || {
+            writer.write_all(&Png::STANDARD_HEADER)?;
+            for chunk in self.chunks() {
+                chunk.write_to(&mut writer)?;
+            }
+            writer.flush()
+        })
+    }
+
     pub fn as_bytes(&self) -> Vec<u8> {
         let chunks_bytes = self
             .chunks()
@@ -71,26 +244,166 @@ impl TryFrom<&[u8]> for Png {
     type Error = Error;
 
     fn try_from(value: &[u8]) -> Result<Self> {
-        let header = &value[0..8];
-        if header != Png::STANDARD_HEADER {
-            return Err("Invalid header")?;
+        Png::read_from(value)
+    }
+}
+
+/// The checks every parser of chunks applies, in this order: the length
+/// against the `Limits`, then the chunk type, then the CRC. `Png`, `PngRef`
+/// and `replay` each frame chunks from their own input and hand them to a
+/// walker, so they accept the same files and fail with the same errors.
+pub(crate) struct ChunkWalker<'p> {
+    profile: &'p CrcProfile,
+    budget: Budget,
+    offset: usize,
+}
+
+impl<'p> ChunkWalker<'p> {
+    pub(crate) fn new(profile: &'p CrcProfile, limits: &Limits) -> ChunkWalker<'p> {
+        ChunkWalker {
+            profile,
+            budget: limits.start(),
+            offset: Png::STANDARD_HEADER.len(),
         }
+    }
+
+    /// File offset of the next chunk's length field.
+    pub(crate) fn offset(&self) -> usize {
+        self.offset
+    }
 
-        let mut chunks = vec![];
-        let mut buffer = [0u8; 4];
-
-        let mut i = 8;
-        while i < value.len() - 1 {
-            buffer.copy_from_slice(&value[i..(i + 4)]);
-            let data_length = u32::from_be_bytes(buffer) as usize;
-            let chunk_end = i + 4 + 4 + data_length + 4;
-            let chunk = Chunk::try_from(&value[i..chunk_end])?;
-            chunks.push(chunk);
-            i = chunk_end;
+    /// The next whole chunk in `bytes`, a file held in memory, or `None` at
+    /// its end. The chunk still has to be passed to `accept`.
+    pub(crate) fn next_in<'a>(&mut self, bytes: &'a [u8]) -> Result<Option<&'a [u8]>> {
+        if self.offset >= bytes.len() {
+            return Ok(None);
         }
+        let offset = self.offset;
+        let length = bytes
+            .get(offset..offset + 4)
+            .ok_or(ParseError::Truncated { offset })?;
+        let length = u32::from_be_bytes(length.try_into().unwrap());
+        self.budget.admit(offset, length)?;
+        match bytes.get(offset..offset + 12 + length as usize) {
+            Some(chunk) => Ok(Some(chunk)),
+            None => Err(ParseError::Truncated { offset })?,
+        }
+    }
 
-        Ok(Png { chunks })
+    /// Like `next_in`, reading the chunk from `reader`.
+    pub(crate) fn read_from<R: Read>(&mut self, reader: &mut R) -> Result<Option<Vec<u8>>> {
+        let offset = self.offset;
+        let mut length = [0u8; 4];
+        match read_fully(reader, &mut length)? {
+            0 => return Ok(None),
+            4 => {}
+            _ => return Err(ParseError::Truncated { offset })?,
+        }
+        self.budget.admit(offset, u32::from_be_bytes(length))?;
+        let mut bytes = length.to_vec();
+        let expected = (4 + u32::from_be_bytes(length) as usize + 4) as u64;
+        if reader.by_ref().take(expected).read_to_end(&mut bytes)? as u64 != expected {
+            return Err(ParseError::Truncated { offset })?;
+        }
+        Ok(Some(bytes))
     }
+
+    /// Checks the type and CRC of the whole `chunk` at the current offset
+    /// and moves past it.
+    pub(crate) fn accept(&mut self, chunk: &[u8]) -> Result<()> {
+        let offset = self.offset;
+        let chunk_type: [u8; 4] = chunk[4..8].try_into().unwrap();
+        let chunk_type =
+            ChunkType::try_from(chunk_type).map_err(|_| ParseError::InvalidChunkType { offset })?;
+        let (checked, crc) = chunk[4..].split_at(chunk.len() - 8);
+        if !self
+            .profile
+            .accepts(checked, u32::from_be_bytes(crc.try_into().unwrap()))
+        {
+            Err(ParseError::InvalidCrc {
+                offset,
+                chunk_type: chunk_type.to_string(),
+            })?;
+        }
+        self.offset += chunk.len();
+        Ok(())
+    }
+}
+
+/// Why a PNG could not be parsed. Offsets point at the chunk's length field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    InvalidSignature,
+    Truncated { offset: usize },
+    InvalidChunkType { offset: usize },
+    InvalidCrc { offset: usize, chunk_type: String },
+}
+
+impl ParseError {
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            ParseError::InvalidSignature => None,
+            ParseError::Truncated { offset }
+            | ParseError::InvalidChunkType { offset }
+            | ParseError::InvalidCrc { offset, .. } => Some(*offset),
+        }
+    }
+
+    pub fn chunk_type(&self) -> Option<&str> {
+        match self {
+            ParseError::InvalidCrc { chunk_type, .. } => Some(chunk_type),
+            _ => None,
+        }
+    }
+
+    /// The catalog entry and arguments for the error.
+    pub fn message(&self) -> (&'static Message, Vec<String>) {
+        match self {
+            ParseError::InvalidSignature => (&i18n::INVALID_SIGNATURE, vec![]),
+            ParseError::Truncated { offset } => (&i18n::TRUNCATED, vec![offset.to_string()]),
+            ParseError::InvalidChunkType { offset } => {
+                (&i18n::INVALID_CHUNK_TYPE, vec![offset.to_string()])
+            }
+            ParseError::InvalidCrc { offset, chunk_type } => (
+                &i18n::INVALID_CRC,
+                vec![chunk_type.clone(), offset.to_string()],
+            ),
+        }
+    }
+
+    /// The validation rule the file breaks.
+    pub fn rule(&self) -> &'static Rule {
+        match self {
+            ParseError::InvalidSignature => &validation::SIGNATURE,
+            ParseError::Truncated { .. } => &validation::TRUNCATED,
+            ParseError::InvalidChunkType { .. } => &validation::CHUNK_TYPE,
+            ParseError::InvalidCrc { .. } => &validation::CRC,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (message, args) = self.message();
+        write!(f, "{}", message.format(Lang::En, &args))
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Like `read_exact`, but a clean end of input before the first byte is not
+/// an error. Returns how many bytes were read.
+pub(crate) fn read_fully<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
 }
 
 impl Display for Png {
@@ -111,16 +424,13 @@ mod tests {
     use crate::chunk::Chunk;
     use crate::chunk_type::ChunkType;
     use std::convert::TryFrom;
-    use std::str::FromStr;
 
     fn testing_chunks() -> Vec<Chunk> {
-        let mut chunks = Vec::new();
-
-        chunks.push(chunk_from_strings("FrSt", "I am the first chunk").unwrap());
-        chunks.push(chunk_from_strings("miDl", "I am another chunk").unwrap());
-        chunks.push(chunk_from_strings("LASt", "I am the last chunk").unwrap());
-
-        chunks
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
     }
 
     fn testing_png() -> Png {
@@ -203,6 +513,81 @@ mod tests {
         assert!(png.is_err());
     }
 
+    #[test]
+    fn test_truncated_png() {
+        assert!(Png::try_from(&PNG_FILE[..4]).is_err());
+        assert!(Png::try_from(&PNG_FILE[..PNG_FILE.len() - 6]).is_err());
+        assert!(Png::try_from(&PNG_FILE[..20]).is_err());
+    }
+
+    #[test]
+    fn test_read_partial() {
+        let (png, truncated_at) =
+            Png::read_partial(&PNG_FILE[..], &CrcProfile::Standard, &Limits::default()).unwrap();
+        assert_eq!(
+            png.chunks().len(),
+            Png::try_from(&PNG_FILE[..]).unwrap().chunks().len()
+        );
+        assert_eq!(truncated_at, None);
+
+        let (png, truncated_at) =
+            Png::read_partial(&PNG_FILE[..40], &CrcProfile::Standard, &Limits::default()).unwrap();
+        assert_eq!(png.chunks().len(), 1);
+        assert_eq!(truncated_at, Some(33));
+
+        assert!(
+            Png::read_partial(&PNG_FILE[..4], &CrcProfile::Standard, &Limits::default()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse_error = |bytes: &[u8]| {
+            Png::try_from(bytes)
+                .unwrap_err()
+                .downcast::<ParseError>()
+                .map(|e| *e)
+                .unwrap()
+        };
+
+        assert_eq!(parse_error(&PNG_FILE[1..]), ParseError::InvalidSignature);
+        assert_eq!(
+            parse_error(&PNG_FILE[..20]),
+            ParseError::Truncated { offset: 8 }
+        );
+
+        let mut bad_crc = PNG_FILE.to_vec();
+        bad_crc[8 + 4 + 4 + 13] ^= 1;
+        assert_eq!(
+            parse_error(&bad_crc),
+            ParseError::InvalidCrc {
+                offset: 8,
+                chunk_type: "IHDR".to_string()
+            }
+        );
+
+        let mut bad_type = PNG_FILE.to_vec();
+        bad_type[8 + 4] = b'1';
+        assert_eq!(
+            parse_error(&bad_type),
+            ParseError::InvalidChunkType { offset: 8 }
+        );
+        assert_eq!(ParseError::InvalidSignature.rule().id, "signature");
+    }
+
+    #[test]
+    fn test_read_from_and_write_to() {
+        let png = Png::read_from(io::BufReader::new(&PNG_FILE[..])).unwrap();
+        assert_eq!(
+            png.chunks().len(),
+            Png::try_from(&PNG_FILE[..]).unwrap().chunks().len()
+        );
+
+        let mut written = vec![];
+        png.write_to(&mut written).unwrap();
+        assert_eq!(written, PNG_FILE.to_vec());
+    }
+
     #[test]
     fn test_list_chunks() {
         let png = testing_png();
@@ -210,6 +595,16 @@ mod tests {
         assert_eq!(chunks.len(), 3);
     }
 
+    #[test]
+    fn test_chunks_with_offsets() {
+        let png = testing_png();
+        let offsets: Vec<usize> = png
+            .chunks_with_offsets()
+            .map(|(offset, _)| offset)
+            .collect();
+        assert_eq!(offsets, vec![8, 8 + 12 + 20, 8 + 12 + 20 + 12 + 18]);
+    }
+
     #[test]
     fn test_chunk_by_type() {
         let png = testing_png();
@@ -218,6 +613,26 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
     }
 
+    #[test]
+    fn test_chunk_data_reader() {
+        let png = testing_png();
+        let mut data = vec![];
+        png.chunk_data_reader("miDl")
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, b"I am another chunk");
+        assert!(png.chunk_data_reader("NoPe").is_none());
+    }
+
+    #[test]
+    fn test_chunks_of_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("miDl", "Another middle").unwrap());
+        let chunk_type = ChunkType::from_str("miDl").unwrap();
+        assert_eq!(png.chunks_of_type(chunk_type).count(), 2);
+    }
+
     #[test]
     fn test_append_chunk() {
         let mut png = testing_png();
@@ -236,6 +651,83 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_unsafe_to_copy_chunks() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("tIME", "known ancillary").unwrap());
+        png.append_chunk(chunk_from_strings("ruST", "unknown unsafe").unwrap());
+        png.append_chunk(chunk_from_strings("ruSt", "unknown safe").unwrap());
+
+        let unsafe_types: Vec<String> = png
+            .unsafe_to_copy_chunks()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(unsafe_types, vec!["ruST"]);
+
+        let removed = png.remove_unsafe_to_copy_chunks();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(png.chunks().len(), 5);
+        assert!(png.chunk_by_type("ruST").is_none());
+    }
+
+    #[test]
+    fn test_insert_before_iend() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.insert_before_iend(chunk_from_strings("ruSt", "Message").unwrap());
+        let chunks = png.chunks();
+        assert_eq!(&chunks[chunks.len() - 2].chunk_type().to_string(), "ruSt");
+        assert_eq!(&chunks[chunks.len() - 1].chunk_type().to_string(), "IEND");
+    }
+
+    #[test]
+    fn test_image_file_has_no_warnings() {
+        let (_, warnings) = Png::read_with_warnings(&PNG_FILE[..]).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_chunk_index_at() {
+        let png = testing_png();
+        let offsets: Vec<usize> = png
+            .chunks_with_offsets()
+            .map(|(offset, _)| offset)
+            .collect();
+        assert_eq!(png.chunk_index_at(offsets[2]).unwrap(), 2);
+        assert_eq!(png.chunk_index_at(8).unwrap(), 0);
+        assert!(png.chunk_index_at(9).is_err());
+    }
+
+    #[test]
+    fn test_warnings() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.append_chunk(chunk_from_strings("tIME", "first").unwrap());
+        png.append_chunk(chunk_from_strings("tIME", "second").unwrap());
+        png.insert_chunk(0, chunk_from_strings("ruSt", "Message").unwrap());
+
+        let warnings = png.warnings();
+        let count = png.chunks().len();
+        assert_eq!(
+            warnings,
+            vec![
+                Warning::OutOfOrder {
+                    chunk_type: "IHDR".to_string(),
+                    index: 1,
+                    rule: &validation::IHDR_FIRST,
+                },
+                Warning::OutOfOrder {
+                    chunk_type: "IEND".to_string(),
+                    index: count - 3,
+                    rule: &validation::IEND_LAST,
+                },
+                Warning::Duplicate {
+                    chunk_type: "tIME".to_string(),
+                    index: count - 1,
+                    offset: png.as_bytes().len() - (12 + "second".len()),
+                },
+            ]
+        );
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -246,7 +738,7 @@ mod tests {
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
         let actual = png.as_bytes();
-        let expected: Vec<u8> = PNG_FILE.iter().copied().collect();
+        let expected: Vec<u8> = PNG_FILE.to_vec();
         assert_eq!(actual, expected);
     }
 
@@ -0,0 +1,110 @@
+//! Build provenance for release artifacts, gathered from git and CI.
+
+use crate::time;
+use std::env;
+use std::process::Command;
+
+pub const GIT_COMMIT: &str = "pngme.git.commit";
+pub const GIT_BRANCH: &str = "pngme.git.branch";
+pub const BUILD_TIMESTAMP: &str = "pngme.build.timestamp";
+pub const CI_JOB_URL: &str = "pngme.ci.job-url";
+
+/// Every text keyword provenance is stored under.
+pub const KEYWORDS: &[&str] = &[GIT_COMMIT, GIT_BRANCH, BUILD_TIMESTAMP, CI_JOB_URL];
+
+/// The provenance keys and values that could be determined. Anything that
+/// is not available (no git, not running in CI) is left out.
+pub fn collect() -> Vec<(&'static str, String)> {
+    let lookup = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+    let mut provenance = vec![];
+
+    if let Some(commit) = git(&["rev-parse", "HEAD"]) {
+        provenance.push((GIT_COMMIT, commit));
+    }
+    if let Some(branch) = branch(git(&["rev-parse", "--abbrev-ref", "HEAD"]), lookup) {
+        provenance.push((GIT_BRANCH, branch));
+    }
+    provenance.push((BUILD_TIMESTAMP, time::format_rfc3339(time::build_time())));
+    if let Some(url) = ci_job_url(lookup) {
+        provenance.push((CI_JOB_URL, url));
+    }
+
+    provenance
+}
+
+pub fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let value = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+/// CI systems check out a detached HEAD, so fall back to the branch name they
+/// export when git only reports "HEAD".
+fn branch(git_branch: Option<String>, env: impl Fn(&str) -> Option<String>) -> Option<String> {
+    git_branch
+        .filter(|branch| branch != "HEAD")
+        .or_else(|| env("GITHUB_REF_NAME"))
+        .or_else(|| env("CI_COMMIT_REF_NAME"))
+        .or_else(|| env("BRANCH_NAME"))
+}
+
+fn ci_job_url(env: impl Fn(&str) -> Option<String>) -> Option<String> {
+    if let (Some(server), Some(repository), Some(run)) = (
+        env("GITHUB_SERVER_URL"),
+        env("GITHUB_REPOSITORY"),
+        env("GITHUB_RUN_ID"),
+    ) {
+        return Some(format!("{}/{}/actions/runs/{}", server, repository, run));
+    }
+    [
+        "CI_JOB_URL",
+        "BUILD_URL",
+        "CIRCLE_BUILD_URL",
+        "BUILDKITE_BUILD_URL",
+    ]
+    .into_iter()
+    .find_map(env)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_ci_job_url() {
+        let github = env(&[
+            ("GITHUB_SERVER_URL", "https://github.com"),
+            ("GITHUB_REPOSITORY", "hallovon/pngme"),
+            ("GITHUB_RUN_ID", "42"),
+        ]);
+        assert_eq!(
+            ci_job_url(github).unwrap(),
+            "https://github.com/hallovon/pngme/actions/runs/42"
+        );
+
+        let gitlab = env(&[("CI_JOB_URL", "https://gitlab.example/jobs/7")]);
+        assert_eq!(ci_job_url(gitlab).unwrap(), "https://gitlab.example/jobs/7");
+
+        assert!(ci_job_url(env(&[])).is_none());
+    }
+
+    #[test]
+    fn test_branch() {
+        let ci = env(&[("GITHUB_REF_NAME", "release")]);
+        assert_eq!(branch(Some("main".to_string()), &ci).unwrap(), "main");
+        assert_eq!(branch(Some("HEAD".to_string()), &ci).unwrap(), "release");
+        assert!(branch(None, env(&[])).is_none());
+    }
+}
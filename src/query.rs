@@ -0,0 +1,374 @@
+//! A small subset of jq for picking values out of JSON payloads:
+//!
+//! - paths: `.`, `.name`, `."any key"`, `.[0]`, `.[-1]`, `.["key"]`, `.items[]`
+//! - pipes between stages: `.items[] | .name`
+//! - `length`, `keys` and `select(PATH OP JSON)` with `==`, `!=`, `<`, `<=`,
+//!   `>` or `>=`
+
+use crate::json::Value;
+use crate::Result;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Key(String),
+    Index(i64),
+    Iterate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Stage {
+    Path(Vec<Step>),
+    Length,
+    Keys,
+    Select {
+        path: Vec<Step>,
+        op: Op,
+        operand: Value,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query(Vec<Stage>);
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Query> {
+        split_outside_strings(query, "|")
+            .into_iter()
+            .map(|stage| parse_stage(stage.trim()))
+            .collect::<Result<_>>()
+            .map(Query)
+            .map_err(|e| format!("invalid query {:?}: {}", query, e).into())
+    }
+
+    /// Every value the query selects, in order.
+    pub fn evaluate(&self, input: &Value) -> Result<Vec<Value>> {
+        let mut values = vec![input.clone()];
+        for stage in &self.0 {
+            let mut next = vec![];
+            for value in &values {
+                next.extend(apply(stage, value)?);
+            }
+            values = next;
+        }
+        Ok(values)
+    }
+}
+
+/// Splits on `separator` where it does not appear inside a JSON string.
+fn split_outside_strings<'a>(text: &'a str, separator: &str) -> Vec<&'a str> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if text[i..].starts_with(separator) {
+            parts.push(&text[start..i]);
+            start = i + separator.len();
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+fn parse_stage(stage: &str) -> Result<Stage> {
+    match stage {
+        "length" => Ok(Stage::Length),
+        "keys" => Ok(Stage::Keys),
+        _ => match stage
+            .strip_prefix("select(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            Some(condition) => parse_select(condition),
+            None => Ok(Stage::Path(parse_path(stage)?)),
+        },
+    }
+}
+
+fn parse_select(condition: &str) -> Result<Stage> {
+    const OPS: [(&str, Op); 6] = [
+        ("==", Op::Eq),
+        ("!=", Op::Ne),
+        ("<=", Op::Le),
+        (">=", Op::Ge),
+        ("<", Op::Lt),
+        (">", Op::Gt),
+    ];
+    for (token, op) in OPS {
+        if let [path, operand] = split_outside_strings(condition, token)[..] {
+            return Ok(Stage::Select {
+                path: parse_path(path.trim())?,
+                op,
+                operand: Value::parse(operand.trim())?,
+            });
+        }
+    }
+    Err("select needs a comparison such as select(.x == 1)".into())
+}
+
+fn parse_path(path: &str) -> Result<Vec<Step>> {
+    let bytes = path.as_bytes();
+    if bytes.first() != Some(&b'.') {
+        return Err(format!("expected a path starting with '.', found {:?}", path).into());
+    }
+
+    let mut steps = vec![];
+    let mut i = 1;
+    let mut after_dot = true;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' if after_dot => {
+                let (key, end) = parse_string(path, i)?;
+                steps.push(Step::Key(key));
+                i = end;
+            }
+            c if after_dot && (c.is_ascii_alphabetic() || c == b'_') => {
+                let end = path[i..]
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+                    .map_or(path.len(), |n| i + n);
+                steps.push(Step::Key(path[i..end].to_string()));
+                i = end;
+            }
+            b'[' => {
+                let close = path[i..].find(']').map(|n| i + n).ok_or("unclosed '['")?;
+                let inside = path[i + 1..close].trim();
+                if inside.is_empty() {
+                    steps.push(Step::Iterate);
+                    i = close + 1;
+                } else if inside.starts_with('"') {
+                    let start = i + 1 + path[i + 1..].find('"').unwrap();
+                    let (key, end) = parse_string(path, start)?;
+                    let close = end
+                        + path[end..]
+                            .find(']')
+                            .filter(|n| path[end..end + n].trim().is_empty())
+                            .ok_or("expected ']' after key")?;
+                    steps.push(Step::Key(key));
+                    i = close + 1;
+                } else {
+                    let index = inside
+                        .parse()
+                        .map_err(|_| format!("invalid index {:?}", inside))?;
+                    steps.push(Step::Index(index));
+                    i = close + 1;
+                }
+            }
+            _ => return Err(format!("unexpected {:?} in path", &path[i..]).into()),
+        }
+        after_dot = bytes.get(i) == Some(&b'.');
+        if after_dot {
+            i += 1;
+            if i == bytes.len() {
+                return Err("path ends with '.'".into());
+            }
+        }
+    }
+    Ok(steps)
+}
+
+/// Parses the JSON string starting at `start`, returning it and the index
+/// just past its closing quote.
+fn parse_string(text: &str, start: usize) -> Result<(String, usize)> {
+    let mut escaped = false;
+    for (n, c) in text[start + 1..].char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => {
+                let end = start + 1 + n + 1;
+                let key = Value::parse(&text[start..end])?;
+                return Ok((key.as_str().unwrap().to_string(), end));
+            }
+            _ => {}
+        }
+    }
+    Err("unterminated string".into())
+}
+
+fn step(step: &Step, value: &Value) -> Result<Vec<Value>> {
+    Ok(match (step, value) {
+        (Step::Key(_) | Step::Index(_), Value::Null) => vec![Value::Null],
+        (Step::Key(key), Value::Object(_)) => vec![value.get(key).cloned().unwrap_or(Value::Null)],
+        (Step::Index(index), Value::Array(items)) => {
+            let index = if *index < 0 {
+                items.len() as i64 + index
+            } else {
+                *index
+            };
+            vec![usize::try_from(index)
+                .ok()
+                .and_then(|index| items.get(index))
+                .cloned()
+                .unwrap_or(Value::Null)]
+        }
+        (Step::Iterate, Value::Array(items)) => items.clone(),
+        (Step::Iterate, Value::Object(members)) => {
+            members.iter().map(|(_, value)| value.clone()).collect()
+        }
+        (Step::Key(key), _) => {
+            return Err(format!("cannot index {} with {:?}", value.type_name(), key).into())
+        }
+        (Step::Index(_), _) => {
+            return Err(format!("cannot index {} with a number", value.type_name()).into())
+        }
+        (Step::Iterate, _) => {
+            return Err(format!("cannot iterate over {}", value.type_name()).into())
+        }
+    })
+}
+
+fn select_path(path: &[Step], value: &Value) -> Result<Vec<Value>> {
+    let mut values = vec![value.clone()];
+    for s in path {
+        let mut next = vec![];
+        for value in &values {
+            next.extend(step(s, value)?);
+        }
+        values = next;
+    }
+    Ok(values)
+}
+
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => (a == b).then_some(Ordering::Equal),
+    }
+}
+
+fn apply(stage: &Stage, value: &Value) -> Result<Vec<Value>> {
+    match stage {
+        Stage::Path(path) => select_path(path, value),
+        Stage::Length => {
+            let length = match value {
+                Value::Null => 0.0,
+                Value::Number(n) => n.abs(),
+                Value::String(s) => s.chars().count() as f64,
+                Value::Array(items) => items.len() as f64,
+                Value::Object(members) => members.len() as f64,
+                Value::Bool(_) => return Err("boolean has no length".into()),
+            };
+            Ok(vec![Value::Number(length)])
+        }
+        Stage::Keys => {
+            let members = value
+                .as_object()
+                .ok_or_else(|| format!("{} has no keys", value.type_name()))?;
+            let mut keys: Vec<&str> = members.iter().map(|(key, _)| key.as_str()).collect();
+            keys.sort();
+            Ok(vec![Value::Array(
+                keys.into_iter().map(Value::from).collect(),
+            )])
+        }
+        Stage::Select { path, op, operand } => {
+            let matches = select_path(path, value)?.iter().any(|found| {
+                let ordering = compare(found, operand);
+                match op {
+                    Op::Eq => found == operand,
+                    Op::Ne => found != operand,
+                    Op::Lt => ordering == Some(Ordering::Less),
+                    Op::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                    Op::Gt => ordering == Some(Ordering::Greater),
+                    Op::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+                }
+            });
+            Ok(if matches { vec![value.clone()] } else { vec![] })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENT: &str = r#"{
+        "version": "1.4.2",
+        "build": {"number": 17, "tags": ["ci", "nightly"]},
+        "odd key": true,
+        "targets": [
+            {"name": "linux", "size": 120},
+            {"name": "windows", "size": 180},
+            {"name": "macos", "size": 150}
+        ]
+    }"#;
+
+    fn query(query: &str) -> Vec<String> {
+        Query::parse(query)
+            .unwrap()
+            .evaluate(&Value::parse(DOCUMENT).unwrap())
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_paths() {
+        assert_eq!(query(".version"), ["\"1.4.2\""]);
+        assert_eq!(query(".build.number"), ["17"]);
+        assert_eq!(query(".build.tags[-1]"), ["\"nightly\""]);
+        assert_eq!(query(r#"."odd key""#), ["true"]);
+        assert_eq!(query(r#".["odd key"]"#), ["true"]);
+        assert_eq!(query(".targets[1].name"), ["\"windows\""]);
+        assert_eq!(query(".missing.deeper"), ["null"]);
+        assert_eq!(query(".build.tags[5]"), ["null"]);
+        assert_eq!(query(".").len(), 1);
+    }
+
+    #[test]
+    fn test_pipes_and_builtins() {
+        assert_eq!(
+            query(".targets[] | .name"),
+            ["\"linux\"", "\"windows\"", "\"macos\""]
+        );
+        assert_eq!(query(".targets | length"), ["3"]);
+        assert_eq!(query(".build | keys"), [r#"["number","tags"]"#]);
+        assert_eq!(
+            query(".targets[] | select(.size >= 150) | .name"),
+            ["\"windows\"", "\"macos\""]
+        );
+        assert_eq!(
+            query(r#".targets[] | select(.name == "a|b") | .size"#),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(Query::parse("version").is_err());
+        assert!(Query::parse(".a[").is_err());
+        assert!(Query::parse(".a.").is_err());
+        assert!(Query::parse("select(.a)").is_err());
+
+        let document = Value::parse(DOCUMENT).unwrap();
+        assert!(Query::parse(".version.major")
+            .unwrap()
+            .evaluate(&document)
+            .is_err());
+        assert!(Query::parse(".build.number[]")
+            .unwrap()
+            .evaluate(&document)
+            .is_err());
+    }
+}
@@ -0,0 +1,392 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::limits::Limits;
+use crate::png::Png;
+use crate::Result;
+use std::fmt::Display;
+
+/// What could be salvaged from a damaged file.
+#[derive(Debug, Default)]
+pub struct Recovered {
+    pub chunks: Vec<RecoveredChunk>,
+    /// Byte ranges that did not belong to any intact chunk.
+    pub skipped: Vec<(usize, usize)>,
+}
+
+#[derive(Debug)]
+pub struct RecoveredChunk {
+    pub offset: usize,
+    pub chunk: Chunk,
+    /// The stored length field was wrong and has been rewritten.
+    pub repaired_length: bool,
+}
+
+impl Recovered {
+    /// A well-formed PNG holding every recovered chunk in file order.
+    pub fn into_png(self) -> Png {
+        Png::from_chunks(self.chunks.into_iter().map(|r| r.chunk).collect())
+    }
+}
+
+/// Where the chunk starting at `offset` ends, if its type is plausible and
+/// its length keeps it within the file.
+fn chunk_end(bytes: &[u8], offset: usize) -> Option<usize> {
+    let header = bytes.get(offset..offset.checked_add(8)?)?;
+    if !header[4..8].iter().all(u8::is_ascii_alphabetic) {
+        return None;
+    }
+    let length = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+    let end = offset.checked_add(12)?.checked_add(length)?;
+    (end <= bytes.len()).then_some(end)
+}
+
+/// The intact chunk starting at `offset`, if its type is plausible and its
+/// CRC matches.
+fn chunk_at(bytes: &[u8], offset: usize) -> Option<Chunk> {
+    let end = chunk_end(bytes, offset)?;
+    Chunk::try_from(&bytes[offset..end]).ok()
+}
+
+/// Whether a chunk ending at `end` could be followed by the rest of the
+/// file: the file ends there, too little is left for another chunk, or
+/// another plausible chunk type follows.
+fn plausible_next(bytes: &[u8], end: usize) -> bool {
+    bytes
+        .get(end + 4..end + 8)
+        .is_none_or(|chunk_type| chunk_type.iter().all(u8::is_ascii_alphabetic))
+}
+
+/// Rebuilds the chunk at `start` from its type, data and CRC when the next
+/// intact chunk begins at `end`, i.e. only the length field was damaged.
+fn repair_length(bytes: &[u8], start: usize, end: usize) -> Option<Chunk> {
+    if end < start + 12
+        || !bytes[start + 4..start + 8]
+            .iter()
+            .all(u8::is_ascii_alphabetic)
+    {
+        return None;
+    }
+    let mut repaired = ((end - start - 12) as u32).to_be_bytes().to_vec();
+    repaired.extend_from_slice(&bytes[start + 4..end]);
+    Chunk::try_from(repaired.as_slice()).ok()
+}
+
+/// Walks the chunks of a possibly damaged PNG. Where a chunk does not parse,
+/// scans forward byte by byte for the next plausible chunk whose CRC
+/// checks out and continues from there, so a single corrupted length field
+/// only costs the chunk it belongs to, and often not even that. Every
+/// chunk salvaged counts against `limits`.
+pub fn recover(bytes: &[u8], limits: &Limits) -> Result<Recovered> {
+    let mut budget = limits.start();
+    let mut recovered = Recovered::default();
+    let mut offset = if bytes.starts_with(&Png::STANDARD_HEADER) {
+        Png::STANDARD_HEADER.len()
+    } else {
+        0
+    };
+
+    while offset < bytes.len() {
+        if let Some(chunk) = chunk_at(bytes, offset) {
+            budget.admit(offset, chunk.length())?;
+            let length = chunk.as_bytes().len();
+            recovered.chunks.push(RecoveredChunk {
+                offset,
+                chunk,
+                repaired_length: false,
+            });
+            offset += length;
+            continue;
+        }
+
+        // A hostile file can declare a long chunk at every byte, so only
+        // candidates that fit the file and are followed by another plausible
+        // chunk get their CRC computed, and the timeout is checked for each.
+        let mut next = bytes.len();
+        for candidate in offset + 1..bytes.len() {
+            let Some(end) = chunk_end(bytes, candidate) else {
+                continue;
+            };
+            if !plausible_next(bytes, end) {
+                continue;
+            }
+            budget.check_time(candidate)?;
+            if chunk_at(bytes, candidate).is_some() {
+                next = candidate;
+                break;
+            }
+        }
+        match repair_length(bytes, offset, next) {
+            Some(chunk) => {
+                budget.admit(offset, chunk.length())?;
+                recovered.chunks.push(RecoveredChunk {
+                    offset,
+                    chunk,
+                    repaired_length: true,
+                })
+            }
+            None => recovered.skipped.push((offset, next)),
+        }
+        offset = next;
+    }
+
+    Ok(recovered)
+}
+
+/// A change made by [`repair_structure`]. Offsets are into the input file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Repair {
+    PrependedSignature,
+    ReplacedSignature,
+    DroppedIncomplete { offset: usize, length: usize },
+    DroppedAfterIend { offset: usize, length: usize },
+    AppendedIend,
+}
+
+impl Display for Repair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Repair::PrependedSignature => write!(f, "prepended missing PNG signature"),
+            Repair::ReplacedSignature => write!(f, "replaced damaged PNG signature"),
+            Repair::DroppedIncomplete { offset, length } => write!(
+                f,
+                "dropped incomplete chunk at offset {} ({} bytes)",
+                offset, length
+            ),
+            Repair::DroppedAfterIend { offset, length } => write!(
+                f,
+                "dropped {} bytes after IEND at offset {}",
+                length, offset
+            ),
+            Repair::AppendedIend => write!(f, "appended missing IEND"),
+        }
+    }
+}
+
+/// Fixes the file-level structure that buggy exporters get wrong: a missing
+/// or damaged signature, a missing IEND, a cut-off last chunk and anything
+/// after IEND. Chunks themselves must be intact; damaged ones are left to
+/// [`recover`]. Every chunk kept counts against `limits`.
+pub fn repair_structure(bytes: &[u8], limits: &Limits) -> Result<(Png, Vec<Repair>)> {
+    let mut budget = limits.start();
+    let mut repairs = vec![];
+    let mut offset = if bytes.starts_with(&Png::STANDARD_HEADER) {
+        Png::STANDARD_HEADER.len()
+    } else if chunk_at(bytes, 0).is_some() {
+        repairs.push(Repair::PrependedSignature);
+        0
+    } else if chunk_at(bytes, Png::STANDARD_HEADER.len()).is_some() {
+        repairs.push(Repair::ReplacedSignature);
+        Png::STANDARD_HEADER.len()
+    } else {
+        return Err("neither a PNG signature nor a chunk at the start of the file".into());
+    };
+
+    let mut chunks = vec![];
+    while offset < bytes.len() {
+        let Some(chunk) = chunk_at(bytes, offset) else {
+            let complete = bytes.get(offset..offset + 4).is_some_and(|length| {
+                let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+                length <= bytes.len() && offset + 12 + length <= bytes.len()
+            });
+            if complete {
+                return Err(format!(
+                    "damaged chunk at offset {}; use `pngme recover` instead",
+                    offset
+                )
+                .into());
+            }
+            repairs.push(Repair::DroppedIncomplete {
+                offset,
+                length: bytes.len() - offset,
+            });
+            break;
+        };
+
+        budget.admit(offset, chunk.length())?;
+        offset += chunk.as_bytes().len();
+        let is_iend = chunk.chunk_type().bytes() == *b"IEND";
+        chunks.push(chunk);
+        if is_iend {
+            if offset < bytes.len() {
+                repairs.push(Repair::DroppedAfterIend {
+                    offset,
+                    length: bytes.len() - offset,
+                });
+            }
+            break;
+        }
+    }
+
+    if chunks
+        .last()
+        .is_none_or(|chunk| chunk.chunk_type().bytes() != *b"IEND")
+    {
+        chunks.push(Chunk::new(ChunkType::try_from(*b"IEND")?, vec![]));
+        repairs.push(Repair::AppendedIend);
+    }
+
+    Ok((Png::from_chunks(chunks), repairs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::limits::LimitError;
+    use std::str::FromStr;
+
+    fn sample() -> Vec<u8> {
+        Png::from_chunks(
+            ["IHDR", "tEXt", "ruSt", "IEND"]
+                .iter()
+                .map(|t| {
+                    let data = match *t {
+                        "IEND" => vec![],
+                        _ => format!("{} data", t).into_bytes(),
+                    };
+                    Chunk::new(ChunkType::from_str(t).unwrap(), data)
+                })
+                .collect(),
+        )
+        .as_bytes()
+    }
+
+    fn types(recovered: &Recovered) -> Vec<String> {
+        recovered
+            .chunks
+            .iter()
+            .map(|r| r.chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_intact_file() {
+        let recovered = recover(&sample(), &Limits::default()).unwrap();
+        assert_eq!(types(&recovered), ["IHDR", "tEXt", "ruSt", "IEND"]);
+        assert!(recovered.skipped.is_empty());
+        assert_eq!(recovered.into_png().as_bytes(), sample());
+    }
+
+    #[test]
+    fn test_corrupted_length_is_repaired() {
+        let mut bytes = sample();
+        let text_offset = 8 + 12 + 9;
+        bytes[text_offset + 3] = 0xff;
+
+        let recovered = recover(&bytes, &Limits::default()).unwrap();
+        assert_eq!(types(&recovered), ["IHDR", "tEXt", "ruSt", "IEND"]);
+        assert!(recovered.chunks[1].repaired_length);
+        assert_eq!(recovered.chunks[1].offset, text_offset);
+        assert_eq!(recovered.into_png().as_bytes(), sample());
+    }
+
+    #[test]
+    fn test_repair_intact_file() {
+        let (png, repairs) = repair_structure(&sample(), &Limits::default()).unwrap();
+        assert_eq!(repairs, []);
+        assert_eq!(png.as_bytes(), sample());
+    }
+
+    #[test]
+    fn test_repair_structure() {
+        let bytes = sample();
+        let iend_offset = bytes.len() - 12;
+
+        let (png, repairs) = repair_structure(&bytes[8..iend_offset], &Limits::default()).unwrap();
+        assert_eq!(repairs, [Repair::PrependedSignature, Repair::AppendedIend]);
+        assert_eq!(png.as_bytes(), sample());
+
+        let mut damaged = bytes.clone();
+        damaged[0] = 0;
+        damaged.extend_from_slice(b"trailing junk");
+        let (png, repairs) = repair_structure(&damaged, &Limits::default()).unwrap();
+        assert_eq!(
+            repairs,
+            [
+                Repair::ReplacedSignature,
+                Repair::DroppedAfterIend {
+                    offset: bytes.len(),
+                    length: 13
+                }
+            ]
+        );
+        assert_eq!(png.as_bytes(), sample());
+
+        let (png, repairs) =
+            repair_structure(&bytes[..iend_offset - 5], &Limits::default()).unwrap();
+        assert_eq!(
+            repairs,
+            [
+                Repair::DroppedIncomplete {
+                    offset: iend_offset - 21,
+                    length: 16
+                },
+                Repair::AppendedIend
+            ]
+        );
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_repair_refuses_damaged_chunks() {
+        let mut bytes = sample();
+        bytes[8 + 10] ^= 1;
+        assert!(repair_structure(&bytes, &Limits::default()).is_err());
+        assert!(repair_structure(b"not a png at all", &Limits::default()).is_err());
+    }
+
+    #[test]
+    fn test_corrupted_data_is_skipped() {
+        let mut bytes = sample();
+        let text_offset = 8 + 12 + 9;
+        bytes[text_offset + 3] = 0xff;
+        bytes[text_offset + 9] ^= 1;
+
+        let recovered = recover(&bytes, &Limits::default()).unwrap();
+        assert_eq!(types(&recovered), ["IHDR", "ruSt", "IEND"]);
+        assert_eq!(recovered.skipped, [(text_offset, text_offset + 21)]);
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        for _ in 0..1000 {
+            bytes.extend(Chunk::new(ChunkType::from_str("teXt").unwrap(), vec![]).as_bytes());
+        }
+        let limits = Limits {
+            max_chunks: Some(10),
+            ..Limits::default()
+        };
+        let error = recover(&bytes, &limits).unwrap_err();
+        assert!(error.is::<LimitError>());
+        let error = repair_structure(&bytes, &limits).unwrap_err();
+        assert!(error.is::<LimitError>());
+    }
+
+    #[test]
+    fn test_resync_is_bounded() {
+        // A long chunk declared every 8 bytes: no candidate is followed by
+        // another chunk, so none of them costs a CRC over its data.
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        for _ in 0..1 << 16 {
+            bytes.extend_from_slice(&(1u32 << 16).to_be_bytes());
+            bytes.extend_from_slice(b"AAAA");
+        }
+        let recovered = recover(&bytes, &Limits::default()).unwrap();
+        assert!(recovered.chunks.is_empty());
+        assert_eq!(recovered.skipped, [(8, bytes.len())]);
+
+        // The timeout also stops the scan that follows a damaged first chunk.
+        let mut bytes = sample();
+        bytes[8 + 3] = 0xff;
+        let limits = Limits {
+            timeout: Some(std::time::Duration::ZERO),
+            ..Limits::default()
+        };
+        let error = recover(&bytes, &limits).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<LimitError>(),
+            Some(LimitError::TimedOut { .. })
+        ));
+    }
+}
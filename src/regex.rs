@@ -0,0 +1,499 @@
+//! A small regular expression matcher for `remove --matching`.
+//!
+//! Supports literals, `.`, `^`, `$`, classes such as `[a-z0-9_]` and
+//! `[^,]`, the escapes `\d`, `\w` and `\s` and their negations, groups with
+//! `|`, and the quantifiers `*`, `+`, `?` and `{m,n}`. Only whether a pattern
+//! matches is reported, so lazy quantifiers behave like greedy ones.
+//!
+//! Patterns are compiled to a program for a Pike VM, which runs every
+//! alternative in lockstep over the text. Matching takes time proportional
+//! to the text length times the program size and never backtracks, so
+//! patterns such as `(a|aa)*c` stay fast on chunks of any size.
+
+use crate::Result;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Char(char),
+    Any,
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+    Start,
+    End,
+    Group(Vec<Vec<Node>>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+
+const DIGIT: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+const SPACE: &[(char, char)] = &[('\t', '\r'), (' ', ' ')];
+
+/// An instruction of the compiled program.
+#[derive(Debug, Clone, PartialEq)]
+enum Inst {
+    Char(char),
+    Any,
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+    Start,
+    End,
+    /// Continues at both targets.
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+/// Bounds the program, and so the work per character, that a pattern such
+/// as `(a{1000}){1000}` can expand to.
+const MAX_PROGRAM: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regex {
+    program: Vec<Inst>,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+        };
+        let program = parser
+            .alternation()
+            .and_then(|branches| match parser.peek() {
+                Some(c) => Err(format!("unexpected {:?}", c).into()),
+                None => Ok(branches),
+            })
+            .and_then(|branches| {
+                let mut compiler = Compiler { program: vec![] };
+                compiler.alternation(&branches)?;
+                compiler.push(Inst::Match)?;
+                Ok(compiler.program)
+            })
+            .map_err(|e| format!("invalid pattern {:?}: {}", pattern, e))?;
+        Ok(Regex { program })
+    }
+
+    /// Whether the pattern matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        for pos in 0..=text.len() {
+            // A new match attempt starts at every position.
+            if self.add(&mut current, 0, pos, &text) {
+                return true;
+            }
+            let Some(&c) = text.get(pos) else {
+                break;
+            };
+            next.clear();
+            for &pc in &current.list {
+                let accepted = match &self.program[pc] {
+                    Inst::Char(expected) => c == *expected,
+                    Inst::Any => c != '\n',
+                    Inst::Class { ranges, negated } => {
+                        ranges.iter().any(|&(a, b)| (a..=b).contains(&c)) != *negated
+                    }
+                    _ => false,
+                };
+                if accepted && self.add(&mut next, pc + 1, pos + 1, &text) {
+                    return true;
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+        }
+        false
+    }
+
+    /// Adds the thread at `pc` to `threads`, following jumps, splits and
+    /// anchors at `pos` until each path waits on a character. Returns whether
+    /// one of them reached a match.
+    fn add(&self, threads: &mut Threads, pc: usize, pos: usize, text: &[char]) -> bool {
+        let mut pending = vec![pc];
+        while let Some(pc) = pending.pop() {
+            if !threads.insert(pc) {
+                continue;
+            }
+            match self.program[pc] {
+                Inst::Match => return true,
+                Inst::Jump(to) => pending.push(to),
+                Inst::Split(first, second) => pending.extend([second, first]),
+                Inst::Start if pos == 0 => pending.push(pc + 1),
+                Inst::End if pos == text.len() => pending.push(pc + 1),
+                _ => {}
+            }
+        }
+        false
+    }
+}
+
+/// The program counters of the threads at one text position, each once.
+struct Threads {
+    seen: Vec<bool>,
+    list: Vec<usize>,
+}
+
+impl Threads {
+    fn new(len: usize) -> Threads {
+        Threads {
+            seen: vec![false; len],
+            list: vec![],
+        }
+    }
+
+    fn insert(&mut self, pc: usize) -> bool {
+        let new = !self.seen[pc];
+        if new {
+            self.seen[pc] = true;
+            self.list.push(pc);
+        }
+        new
+    }
+
+    fn clear(&mut self) {
+        for pc in self.list.drain(..) {
+            self.seen[pc] = false;
+        }
+    }
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> Result<usize> {
+        if self.program.len() >= MAX_PROGRAM {
+            Err("pattern is too large")?
+        }
+        self.program.push(inst);
+        Ok(self.program.len() - 1)
+    }
+
+    fn alternation(&mut self, branches: &[Vec<Node>]) -> Result<()> {
+        let (last, others) = branches.split_last().unwrap();
+        let mut jumps = vec![];
+        for branch in others {
+            let split = self.push(Inst::Split(0, 0))?;
+            self.sequence(branch)?;
+            jumps.push(self.push(Inst::Jump(0))?);
+            self.program[split] = Inst::Split(split + 1, self.program.len());
+        }
+        self.sequence(last)?;
+        for jump in jumps {
+            self.program[jump] = Inst::Jump(self.program.len());
+        }
+        Ok(())
+    }
+
+    fn sequence(&mut self, nodes: &[Node]) -> Result<()> {
+        nodes.iter().try_for_each(|node| self.node(node))
+    }
+
+    fn node(&mut self, node: &Node) -> Result<()> {
+        match node {
+            Node::Char(c) => self.push(Inst::Char(*c)).map(drop),
+            Node::Any => self.push(Inst::Any).map(drop),
+            Node::Class { ranges, negated } => self
+                .push(Inst::Class {
+                    ranges: ranges.clone(),
+                    negated: *negated,
+                })
+                .map(drop),
+            Node::Start => self.push(Inst::Start).map(drop),
+            Node::End => self.push(Inst::End).map(drop),
+            Node::Group(branches) => self.alternation(branches),
+            Node::Repeat { node, min, max } => {
+                // An empty group repeated a billion times adds no
+                // instructions, so the counts are bounded separately.
+                if (*min).max(max.unwrap_or(0)) > MAX_PROGRAM {
+                    Err("pattern is too large")?
+                }
+                for _ in 0..*min {
+                    self.node(node)?;
+                }
+                match max {
+                    None => {
+                        let split = self.push(Inst::Split(0, 0))?;
+                        self.node(node)?;
+                        self.push(Inst::Jump(split))?;
+                        self.program[split] = Inst::Split(split + 1, self.program.len());
+                    }
+                    Some(max) => {
+                        let mut splits = vec![];
+                        for _ in *min..*max {
+                            splits.push(self.push(Inst::Split(0, 0))?);
+                            self.node(node)?;
+                        }
+                        for split in splits {
+                            self.program[split] = Inst::Split(split + 1, self.program.len());
+                        }
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn alternation(&mut self) -> Result<Vec<Vec<Node>>> {
+        let mut branches = vec![self.sequence()?];
+        while self.eat('|') {
+            branches.push(self.sequence()?);
+        }
+        Ok(branches)
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Node>> {
+        let mut nodes = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantified(atom)?);
+        }
+        Ok(nodes)
+    }
+
+    fn atom(&mut self) -> Result<Node> {
+        Ok(match self.next().unwrap() {
+            '(' => {
+                if self.eat('?') && !self.eat(':') {
+                    return Err("only (?:...) groups are supported".into());
+                }
+                let branches = self.alternation()?;
+                if !self.eat(')') {
+                    return Err("unclosed '('".into());
+                }
+                Node::Group(branches)
+            }
+            '[' => self.class()?,
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '\\' => self.escape()?,
+            c @ ('*' | '+' | '?' | '{') => {
+                return Err(format!("nothing to repeat before {:?}", c).into())
+            }
+            c => Node::Char(c),
+        })
+    }
+
+    fn escape(&mut self) -> Result<Node> {
+        let class = |ranges: &[(char, char)], negated| Node::Class {
+            ranges: ranges.to_vec(),
+            negated,
+        };
+        Ok(match self.next().ok_or("pattern ends with '\\'")? {
+            'd' => class(DIGIT, false),
+            'D' => class(DIGIT, true),
+            'w' => class(WORD, false),
+            'W' => class(WORD, true),
+            's' => class(SPACE, false),
+            'S' => class(SPACE, true),
+            c => Node::Char(unescape(c)?),
+        })
+    }
+
+    fn class(&mut self) -> Result<Node> {
+        let negated = self.eat('^');
+        let mut ranges = vec![];
+        let mut first = true;
+        loop {
+            let c = match self.next().ok_or("unclosed '['")? {
+                ']' if !first => break,
+                '\\' => match self.next().ok_or("unclosed '['")? {
+                    'd' => {
+                        ranges.extend_from_slice(DIGIT);
+                        continue;
+                    }
+                    'w' => {
+                        ranges.extend_from_slice(WORD);
+                        continue;
+                    }
+                    's' => {
+                        ranges.extend_from_slice(SPACE);
+                        continue;
+                    }
+                    c => unescape(c)?,
+                },
+                c => c,
+            };
+            first = false;
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') {
+                self.pos += 1;
+                let end = match self.next().unwrap() {
+                    '\\' => unescape(self.next().ok_or("unclosed '['")?)?,
+                    end => end,
+                };
+                if end < c {
+                    return Err(format!("invalid range {}-{}", c, end).into());
+                }
+                ranges.push((c, end));
+            } else {
+                ranges.push((c, c));
+            }
+        }
+        Ok(Node::Class { ranges, negated })
+    }
+
+    fn quantified(&mut self, node: Node) -> Result<Node> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                let close = self.chars[self.pos..]
+                    .iter()
+                    .position(|&c| c == '}')
+                    .ok_or("unclosed '{'")?;
+                let inside: String = self.chars[self.pos + 1..self.pos + close].iter().collect();
+                let bound = |s: &str| {
+                    s.parse::<usize>()
+                        .map_err(|_| format!("invalid repetition {{{}}}", inside))
+                };
+                let (min, max) = match inside.split_once(',') {
+                    None => (bound(&inside)?, Some(bound(&inside)?)),
+                    Some((min, "")) => (bound(min)?, None),
+                    Some((min, max)) => (bound(min)?, Some(bound(max)?)),
+                };
+                if max.is_some_and(|max| max < min) {
+                    return Err(format!("invalid repetition {{{}}}", inside).into());
+                }
+                self.pos += close;
+                (min, max)
+            }
+            _ => return Ok(node),
+        };
+        self.pos += 1;
+        self.eat('?');
+        if matches!(node, Node::Start | Node::End) {
+            return Err("anchors cannot be repeated".into());
+        }
+        Ok(Node::Repeat {
+            node: Box::new(node),
+            min,
+            max,
+        })
+    }
+}
+
+fn unescape(c: char) -> Result<char> {
+    Ok(match c {
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        '0' => '\0',
+        c if c.is_ascii_alphanumeric() => return Err(format!("unknown escape \\{}", c).into()),
+        c => c,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn test_literals_and_anchors() {
+        assert!(is_match("intra", "host=build.intra.example.com"));
+        assert!(!is_match("intra", "host=example.com"));
+        assert!(is_match("^host", "host=a"));
+        assert!(!is_match("^host", "a host"));
+        assert!(is_match(r"\.com$", "example.com"));
+        assert!(!is_match(r"\.com$", "example.com.au"));
+        assert!(is_match("", "anything"));
+        assert!(is_match("a.c", "abc"));
+        assert!(!is_match("a.c", "a\nc"));
+    }
+
+    #[test]
+    fn test_classes_and_quantifiers() {
+        assert!(is_match(r"\d{1,3}(\.\d{1,3}){3}", "ip 10.0.12.7 seen"));
+        assert!(!is_match(r"^\d{1,3}(\.\d{1,3}){3}$", "10.0.12"));
+        assert!(is_match("[a-z]+-[0-9]+", "runner build-42"));
+        assert!(is_match("^[^,]*$", "no commas here"));
+        assert!(!is_match("^[^,]*$", "a, b"));
+        assert!(is_match(r"[\w.]+@corp", "me.dev@corp"));
+        assert!(is_match("colou?r", "color"));
+        assert!(is_match("ab*c", "ac"));
+        assert!(is_match("ab+?c", "abbbc"));
+        assert!(is_match("a{2,}", "caab"));
+        assert!(!is_match("a{3}", "aab"));
+        assert!(is_match("^(a*)*b$", "aaab"));
+        assert!(is_match("^(a*){2}$", ""));
+    }
+
+    #[test]
+    fn test_alternation() {
+        assert!(is_match("(?:staging|prod)\\.internal", "db.prod.internal"));
+        assert!(is_match("^cat|dog$", "hotdog"));
+        assert!(!is_match("^(cat|dog)$", "hotdog"));
+        assert!(is_match("a(b|bc)d", "abcd"));
+    }
+
+    #[test]
+    fn test_errors() {
+        for pattern in [
+            "(",
+            "a)",
+            "[a",
+            "*a",
+            "a{2,1}",
+            r"\q",
+            "a{x}",
+            "[z-a]",
+            "(?=a)",
+            "^*",
+            "(a{1000}){1000}",
+            "(){1000000000}",
+        ] {
+            assert!(Regex::new(pattern).is_err(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_large_inputs() {
+        let text = "a".repeat(200_000);
+        assert!(!is_match("(a|aa)*c", &text));
+        assert!(is_match("^(a|aa)*$", &text));
+        assert!(!is_match("^(a*)*b$", &text));
+    }
+}
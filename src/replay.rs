@@ -0,0 +1,149 @@
+//! Step-by-step replays of the parser over arbitrary bytes.
+//!
+//! `pngme replay` exists for analyzing malformed files that users report or
+//! that a fuzzer produced. It applies the same checks, in the same order, as
+//! `Png::read_partial`, recording each step instead of only the outcome, and
+//! stops where the parser would. The wall-clock `--timeout` is left out so
+//! that replaying the same input always prints the same trace.
+
+use crate::chunk::{Chunk, CrcProfile};
+use crate::limits::Limits;
+use crate::png::{ChunkWalker, ParseError, Png};
+use crate::Error;
+use std::fmt::Display;
+
+/// A chunk the parser accepted or stopped at.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Step {
+    pub offset: usize,
+    /// The type field as stored, which may not be a valid chunk type.
+    pub chunk_type: [u8; 4],
+    pub length: u32,
+    /// The stored CRC and the standard CRC of the chunk's type and data.
+    pub crc: (u32, u32),
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:>10}  {:<4}  length {:>10}  crc {:08x}",
+            self.offset,
+            String::from_utf8_lossy(&self.chunk_type).escape_debug(),
+            self.length,
+            self.crc.0
+        )?;
+        if self.crc.0 != self.crc.1 {
+            write!(f, " (standard crc {:08x})", self.crc.1)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Trace {
+    /// Every chunk the parser read completely, in file order.
+    pub steps: Vec<Step>,
+    /// Where the parser stopped and why, or `None` when it reached the end
+    /// of the input cleanly.
+    pub stop: Option<(usize, Error)>,
+    /// Bytes from the stopping point on, which the parser gave up on.
+    pub unread: usize,
+}
+
+/// Replays the permissive parser over `bytes`, checking CRCs with `profile`
+/// and counting chunks and bytes against `limits`.
+pub fn replay(bytes: &[u8], profile: &CrcProfile, limits: &Limits) -> Trace {
+    let stop = |steps: Vec<Step>, offset: usize, error: Error| Trace {
+        steps,
+        stop: Some((offset, error)),
+        unread: bytes.len() - offset,
+    };
+    if !bytes.starts_with(&Png::STANDARD_HEADER) {
+        return stop(vec![], 0, ParseError::InvalidSignature.into());
+    }
+
+    let limits = Limits {
+        timeout: None,
+        ..*limits
+    };
+    let mut walker = ChunkWalker::new(profile, &limits);
+    let mut steps = vec![];
+    loop {
+        let offset = walker.offset();
+        let chunk = match walker.next_in(bytes) {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => return stop(steps, offset, e),
+        };
+        let stored = u32::from_be_bytes(chunk[chunk.len() - 4..].try_into().unwrap());
+        steps.push(Step {
+            offset,
+            chunk_type: chunk[4..8].try_into().unwrap(),
+            length: (chunk.len() - 12) as u32,
+            crc: (stored, Chunk::crc_of(&chunk[4..chunk.len() - 4])),
+        });
+        if let Err(e) = walker.accept(chunk) {
+            return stop(steps, offset, e);
+        }
+    }
+
+    Trace {
+        steps,
+        stop: None,
+        unread: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn testing_bytes() -> Vec<u8> {
+        let chunk = |chunk_type: &str, data: &str| {
+            Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.into())
+        };
+        Png::from_chunks(vec![chunk("IHDR", "header"), chunk("IEND", "")]).as_bytes()
+    }
+
+    #[test]
+    fn test_replay_clean_file() {
+        let trace = replay(&testing_bytes(), &CrcProfile::Standard, &Limits::default());
+        assert_eq!(trace.steps.len(), 2);
+        assert_eq!(trace.steps[1].offset, 8 + 12 + 6);
+        assert!(trace.stop.is_none());
+    }
+
+    #[test]
+    fn test_replay_stops_where_the_parser_does() {
+        let mut bytes = testing_bytes();
+        bytes[8 + 12 + 5] ^= 1;
+        let trace = replay(&bytes, &CrcProfile::Standard, &Limits::default());
+        let (offset, error) = trace.stop.unwrap();
+        assert_eq!(offset, 8);
+        assert_eq!(
+            error.to_string(),
+            Png::read_partial(&bytes[..], &CrcProfile::Standard, &Limits::default())
+                .unwrap_err()
+                .to_string()
+        );
+        assert_ne!(trace.steps[0].crc.0, trace.steps[0].crc.1);
+        assert_eq!(trace.unread, bytes.len() - 8);
+
+        let trace = replay(&bytes, &CrcProfile::None, &Limits::default());
+        assert_eq!(trace.steps.len(), 2);
+
+        let trace = replay(
+            &bytes[..bytes.len() - 3],
+            &CrcProfile::None,
+            &Limits::default(),
+        );
+        assert_eq!(trace.steps.len(), 1);
+        assert_eq!(trace.stop.unwrap().0, 8 + 12 + 6);
+
+        let trace = replay(b"GIF89a", &CrcProfile::Standard, &Limits::default());
+        assert_eq!(trace.stop.unwrap().0, 0);
+    }
+}
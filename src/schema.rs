@@ -0,0 +1,438 @@
+//! Validation of JSON payloads against a JSON Schema. Only the keywords
+//! `check` knows are supported; a schema using any other validation keyword
+//! is rejected up front instead of being silently half-enforced.
+
+use crate::json::Value;
+use crate::Result;
+use std::fmt::Display;
+
+/// Keywords that carry no constraint and are always accepted.
+const ANNOTATIONS: &[&str] = &[
+    "$schema",
+    "$id",
+    "$comment",
+    "title",
+    "description",
+    "default",
+    "examples",
+    "format",
+    "deprecated",
+    "readOnly",
+    "writeOnly",
+];
+
+/// A place where a document breaks its schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// JSON Pointer to the offending value; empty for the document itself.
+    pub path: String,
+    pub message: String,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        };
+        write!(f, "{}: {}", path, self.message)
+    }
+}
+
+pub struct Schema(Value);
+
+impl Schema {
+    pub fn new(schema: Value) -> Result<Schema> {
+        check(&schema, "")?;
+        Ok(Schema(schema))
+    }
+
+    pub fn parse(text: &str) -> Result<Schema> {
+        Schema::new(Value::parse(text)?)
+    }
+
+    pub fn validate(&self, document: &Value) -> Vec<Violation> {
+        let mut violations = vec![];
+        validate(&self.0, document, String::new(), &mut violations);
+        violations
+    }
+}
+
+/// Rejects unsupported keywords and malformed keyword values.
+fn check(schema: &Value, path: &str) -> Result<()> {
+    let members = match schema {
+        Value::Bool(_) => return Ok(()),
+        Value::Object(members) => members,
+        _ => return Err(format!("schema at {:?} must be an object or boolean", path).into()),
+    };
+
+    for (keyword, value) in members {
+        let at = format!("{}/{}", path, keyword);
+        let number = || {
+            value
+                .as_f64()
+                .map(|_| ())
+                .ok_or_else(|| format!("{} must be a number", at))
+        };
+        let schemas = || {
+            value
+                .as_array()
+                .filter(|items| !items.is_empty())
+                .ok_or_else(|| format!("{} must be a non-empty array", at))
+        };
+        match keyword.as_str() {
+            keyword if ANNOTATIONS.contains(&keyword) => {}
+            "type" => {
+                let names: Vec<&Value> = match value {
+                    Value::Array(names) => names.iter().collect(),
+                    name => vec![name],
+                };
+                for name in names {
+                    let known = [
+                        "null", "boolean", "number", "integer", "string", "array", "object",
+                    ];
+                    if !name.as_str().is_some_and(|name| known.contains(&name)) {
+                        return Err(format!("{} has unknown type {}", at, name).into());
+                    }
+                }
+            }
+            "enum" => {
+                value
+                    .as_array()
+                    .ok_or_else(|| format!("{} must be an array", at))?;
+            }
+            "const" => {}
+            "minimum" | "maximum" | "exclusiveMinimum" | "exclusiveMaximum" => number()?,
+            "multipleOf" => {
+                if !value.as_f64().is_some_and(|n| n > 0.0) {
+                    return Err(format!("{} must be a positive number", at).into());
+                }
+            }
+            "minLength" | "maxLength" | "minProperties" | "maxProperties" | "minItems"
+            | "maxItems" => {
+                if !value.as_f64().is_some_and(|n| n >= 0.0 && n.fract() == 0.0) {
+                    return Err(format!("{} must be a non-negative integer", at).into());
+                }
+            }
+            "uniqueItems" => {
+                value
+                    .as_bool()
+                    .ok_or_else(|| format!("{} must be a boolean", at))?;
+            }
+            "required" => {
+                if !value
+                    .as_array()
+                    .is_some_and(|names| names.iter().all(|name| name.as_str().is_some()))
+                {
+                    return Err(format!("{} must be an array of strings", at).into());
+                }
+            }
+            "properties" => {
+                for (name, property) in value
+                    .as_object()
+                    .ok_or_else(|| format!("{} must be an object", at))?
+                {
+                    check(property, &format!("{}/{}", at, name))?;
+                }
+            }
+            "additionalProperties" | "items" | "not" => check(value, &at)?,
+            "allOf" | "anyOf" | "oneOf" => {
+                for (i, schema) in schemas()?.iter().enumerate() {
+                    check(schema, &format!("{}/{}", at, i))?;
+                }
+            }
+            _ => {
+                return Err(format!("unsupported schema keyword {} at {:?}", keyword, path).into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Equality where object members compare regardless of order.
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter().all(|(name, a)| {
+                    b.iter()
+                        .find(|(other, _)| other == name)
+                        .is_some_and(|(_, b)| same(a, b))
+                })
+        }
+        _ => a == b,
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "integer" => value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        name => value.type_name() == name,
+    }
+}
+
+/// A numeric keyword, when its limit is broken, and how to describe it.
+type Bound = (&'static str, fn(f64, f64) -> bool, &'static str);
+
+const NUMBER_BOUNDS: &[Bound] = &[
+    ("minimum", |n, limit| n < limit, "less than the minimum"),
+    ("maximum", |n, limit| n > limit, "more than the maximum"),
+    ("exclusiveMinimum", |n, limit| n <= limit, "not more than"),
+    ("exclusiveMaximum", |n, limit| n >= limit, "not less than"),
+    (
+        "multipleOf",
+        |n, limit| (n / limit).fract() != 0.0,
+        "not a multiple of",
+    ),
+];
+
+fn size_bounds(min: &'static str, max: &'static str) -> [Bound; 2] {
+    [
+        (min, |n, limit| n < limit, "less than the minimum"),
+        (max, |n, limit| n > limit, "more than the maximum"),
+    ]
+}
+
+fn validate(schema: &Value, value: &Value, path: String, violations: &mut Vec<Violation>) {
+    let members = match schema {
+        Value::Bool(true) => return,
+        Value::Bool(false) => {
+            violations.push(Violation {
+                path,
+                message: "no value is allowed here".to_string(),
+            });
+            return;
+        }
+        Value::Object(members) => members,
+        _ => return,
+    };
+    let mut fail = |message: String| {
+        violations.push(Violation {
+            path: path.clone(),
+            message,
+        })
+    };
+    let limit = |keyword: &str| schema.get(keyword).and_then(Value::as_f64);
+    let mut nested = vec![];
+
+    for (keyword, constraint) in members {
+        match keyword.as_str() {
+            "type" => {
+                let names: Vec<&str> = match constraint {
+                    Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+                    name => name.as_str().into_iter().collect(),
+                };
+                if !names.iter().any(|name| has_type(value, name)) {
+                    fail(format!(
+                        "expected {}, found {}",
+                        names.join(" or "),
+                        value.type_name()
+                    ));
+                }
+            }
+            "enum" => {
+                let allowed = constraint.as_array().unwrap_or_default();
+                if !allowed.iter().any(|allowed| same(allowed, value)) {
+                    fail(format!("{} is not one of the allowed values", value));
+                }
+            }
+            "const" if !same(constraint, value) => {
+                fail(format!("expected {}, found {}", constraint, value));
+            }
+            "allOf" | "anyOf" | "oneOf" => {
+                let schemas = constraint.as_array().unwrap_or_default();
+                let passing = schemas
+                    .iter()
+                    .filter(|schema| {
+                        let mut inner = vec![];
+                        validate(schema, value, path.clone(), &mut inner);
+                        inner.is_empty()
+                    })
+                    .count();
+                let ok = match keyword.as_str() {
+                    "allOf" => passing == schemas.len(),
+                    "anyOf" => passing > 0,
+                    _ => passing == 1,
+                };
+                if !ok {
+                    fail(format!(
+                        "{} of {} {} subschemas match",
+                        passing,
+                        schemas.len(),
+                        keyword
+                    ));
+                }
+            }
+            "not" => {
+                let mut inner = vec![];
+                validate(constraint, value, path.clone(), &mut inner);
+                if inner.is_empty() {
+                    fail("value matches a schema it must not match".to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut bounds = |what: String, actual: f64, bounds: &[Bound]| {
+        for (keyword, broken, relation) in bounds {
+            if let Some(limit) = limit(keyword).filter(|&limit| broken(actual, limit)) {
+                fail(format!("{} is {} {}", what, relation, limit));
+            }
+        }
+    };
+
+    match value {
+        Value::Number(n) => bounds(n.to_string(), *n, NUMBER_BOUNDS),
+        Value::String(s) => {
+            let length = s.chars().count();
+            bounds(
+                format!("length {}", length),
+                length as f64,
+                &size_bounds("minLength", "maxLength"),
+            );
+        }
+        Value::Array(items) => {
+            bounds(
+                format!("item count {}", items.len()),
+                items.len() as f64,
+                &size_bounds("minItems", "maxItems"),
+            );
+            if schema.get("uniqueItems").and_then(Value::as_bool) == Some(true) {
+                for (i, item) in items.iter().enumerate() {
+                    if items[..i].iter().any(|earlier| same(earlier, item)) {
+                        fail(format!("item {} is a duplicate", i));
+                    }
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    nested.push((item_schema, item, format!("{}/{}", path, i)));
+                }
+            }
+        }
+        Value::Object(members) => {
+            bounds(
+                format!("property count {}", members.len()),
+                members.len() as f64,
+                &size_bounds("minProperties", "maxProperties"),
+            );
+            for name in schema
+                .get("required")
+                .and_then(Value::as_array)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(Value::as_str)
+            {
+                if value.get(name).is_none() {
+                    fail(format!("missing required property {:?}", name));
+                }
+            }
+            let properties = schema.get("properties");
+            for (name, member) in members {
+                let member_path = format!("{}/{}", path, escape(name));
+                match properties.and_then(|properties| properties.get(name)) {
+                    Some(property) => nested.push((property, member, member_path)),
+                    None => {
+                        if let Some(additional) = schema.get("additionalProperties") {
+                            nested.push((additional, member, member_path));
+                        }
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+
+    for (schema, value, path) in nested {
+        validate(schema, value, path, violations);
+    }
+}
+
+/// Escapes a member name for use in a JSON Pointer.
+fn escape(name: &str) -> String {
+    name.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn violations(schema: &str, document: &str) -> Vec<String> {
+        Schema::parse(schema)
+            .unwrap()
+            .validate(&Value::parse(document).unwrap())
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    const CONFIG: &str = r#"{
+        "type": "object",
+        "required": ["name", "retries"],
+        "properties": {
+            "name": {"type": "string", "minLength": 1},
+            "retries": {"type": "integer", "minimum": 0, "maximum": 10},
+            "mode": {"enum": ["fast", "safe"]},
+            "tags": {"type": "array", "items": {"type": "string"}, "uniqueItems": true}
+        },
+        "additionalProperties": false
+    }"#;
+
+    #[test]
+    fn test_valid_document() {
+        assert_eq!(
+            violations(
+                CONFIG,
+                r#"{"name": "x", "retries": 3, "mode": "safe", "tags": ["a", "b"]}"#
+            ),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_violations() {
+        assert_eq!(
+            violations(
+                CONFIG,
+                r#"{"name": "", "retries": 2.5, "mode": "slow", "tags": ["a", 1, "a"], "x/y": 1}"#
+            ),
+            [
+                "/name: length 0 is less than the minimum 1",
+                "/retries: expected integer, found number",
+                "/mode: \"slow\" is not one of the allowed values",
+                "/tags: item 2 is a duplicate",
+                "/tags/1: expected string, found number",
+                "/x~1y: no value is allowed here",
+            ]
+        );
+        assert_eq!(
+            violations(CONFIG, "[]"),
+            ["/: expected object, found array"]
+        );
+        assert_eq!(
+            violations(CONFIG, r#"{"name": "x"}"#),
+            ["/: missing required property \"retries\""]
+        );
+    }
+
+    #[test]
+    fn test_combinators() {
+        let schema = r#"{"oneOf": [{"type": "integer"}, {"minimum": 5}], "not": {"const": 7}}"#;
+        assert!(violations(schema, "3").is_empty());
+        assert_eq!(violations(schema, "6").len(), 1);
+        assert_eq!(violations(schema, "7").len(), 2);
+    }
+
+    #[test]
+    fn test_unsupported_keywords() {
+        assert!(Schema::parse(r#"{"pattern": "^a"}"#).is_err());
+        assert!(Schema::parse(r##"{"properties": {"a": {"$ref": "#"}}}"##).is_err());
+        assert!(Schema::parse(r#"{"type": "float"}"#).is_err());
+        assert!(Schema::parse(r#"{"title": "ok", "format": "uri"}"#).is_ok());
+    }
+}
@@ -0,0 +1,334 @@
+//! Payload type detection and terminal-friendly rendering for `decode`.
+
+use crate::ihdr::Header;
+use crate::json::Value;
+use crate::png::Png;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Png,
+    Gzip,
+    Json,
+    Xml,
+    Text,
+    Binary,
+}
+
+impl Kind {
+    pub fn mime(&self) -> &'static str {
+        match self {
+            Kind::Png => "image/png",
+            Kind::Gzip => "application/gzip",
+            Kind::Json => "application/json",
+            Kind::Xml => "application/xml",
+            Kind::Text => "text/plain",
+            Kind::Binary => "application/octet-stream",
+        }
+    }
+}
+
+/// Identifies a payload by its magic bytes, falling back to its content.
+pub fn sniff(data: &[u8]) -> Kind {
+    if data.starts_with(&Png::STANDARD_HEADER) {
+        return Kind::Png;
+    }
+    if data.starts_with(&[0x1f, 0x8b]) {
+        return Kind::Gzip;
+    }
+    let Ok(text) = std::str::from_utf8(data) else {
+        return Kind::Binary;
+    };
+    let trimmed = text.trim_start_matches('\u{feff}').trim();
+    if trimmed.starts_with(['{', '[']) && Value::parse(trimmed).is_ok() {
+        Kind::Json
+    } else if trimmed.starts_with("<?xml")
+        || (trimmed.starts_with('<') && trimmed.ends_with('>') && trimmed.len() > 2)
+    {
+        Kind::Xml
+    } else if text
+        .chars()
+        .all(|c| !c.is_control() || c.is_ascii_whitespace())
+    {
+        Kind::Text
+    } else {
+        Kind::Binary
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const KEY: &str = "\x1b[34;1m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[36m";
+const LITERAL: &str = "\x1b[35m";
+const TAG: &str = "\x1b[36m";
+const DIM: &str = "\x1b[2m";
+
+/// The payload laid out for reading on a terminal: JSON is indented, JSON
+/// and XML are highlighted when `color` is set, and binary formats are
+/// summarized or hex-dumped instead of being written raw. Control characters
+/// in text are shown escaped, so a payload cannot drive the terminal.
+pub fn pretty(data: &[u8], color: bool) -> String {
+    match sniff(data) {
+        Kind::Png => describe_png(data),
+        Kind::Gzip => format!("gzip data, {} bytes", data.len()),
+        Kind::Json => {
+            let text = std::str::from_utf8(data).unwrap();
+            let pretty = escape_controls(&indent_json(text.trim_start_matches('\u{feff}').trim()));
+            if color {
+                highlight_json(&pretty)
+            } else {
+                pretty
+            }
+        }
+        Kind::Xml if color => highlight_xml(&escape_controls(std::str::from_utf8(data).unwrap())),
+        Kind::Xml | Kind::Text => escape_controls(std::str::from_utf8(data).unwrap()),
+        Kind::Binary => hex_dump(data, color),
+    }
+}
+
+/// Indents valid JSON two spaces per level like
+/// [`Value::to_pretty_string`], but copies strings and numbers exactly as
+/// written: going through `f64` would round integers such as large IDs.
+fn indent_json(text: &str) -> String {
+    let newline = |out: &mut String, depth: usize| {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth));
+    };
+    let mut out = String::new();
+    let mut depth = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                out.push(c);
+                let mut escaped = false;
+                for c in chars.by_ref() {
+                    out.push(c);
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '{' | '[' => {
+                out.push(c);
+                while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+                match chars.next_if(|&c| c == '}' || c == ']') {
+                    Some(close) => out.push(close),
+                    None => {
+                        depth += 1;
+                        newline(&mut out, depth);
+                    }
+                }
+            }
+            '}' | ']' => {
+                depth -= 1;
+                newline(&mut out, depth);
+                out.push(c);
+            }
+            ',' => {
+                out.push(c);
+                newline(&mut out, depth);
+            }
+            ':' => out.push_str(": "),
+            c if c.is_ascii_whitespace() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// `text` with control characters other than tabs and line breaks written
+/// as `\xNN` or `\u{NNNN}`.
+fn escape_controls(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\t' | '\n' => out.push(c),
+            '\r' if chars.peek() == Some(&'\n') => out.push(c),
+            c if c.is_control() && (c as u32) < 0x80 => write!(out, "\\x{:02x}", c as u32).unwrap(),
+            c if c.is_control() => write!(out, "\\u{{{:04x}}}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn describe_png(data: &[u8]) -> String {
+    match Png::try_from(data) {
+        Ok(png) => match Header::from_png(&png) {
+            Ok(header) => format!(
+                "PNG image, {}x{} {}, {} chunks, {} bytes",
+                header.width,
+                header.height,
+                header.color_type,
+                png.chunks().len(),
+                data.len()
+            ),
+            Err(_) => format!(
+                "PNG image, {} chunks, {} bytes",
+                png.chunks().len(),
+                data.len()
+            ),
+        },
+        Err(e) => format!("PNG image ({}), {} bytes", e, data.len()),
+    }
+}
+
+/// Colors the tokens of valid JSON text.
+fn highlight_json(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '"' => {
+                let mut end = text.len();
+                let mut escaped = false;
+                for (i, c) in chars.by_ref() {
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => {
+                            end = i + 1;
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                let is_key = text[end..].trim_start().starts_with(':');
+                let style = if is_key { KEY } else { STRING };
+                write!(out, "{}{}{}", style, &text[start..end], RESET).unwrap();
+            }
+            '-' | '0'..='9' | 't' | 'f' | 'n' => {
+                let mut end = start + 1;
+                while let Some(&(i, c)) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '-') {
+                        chars.next();
+                        end = i + 1;
+                    } else {
+                        break;
+                    }
+                }
+                let style = if c.is_ascii_alphabetic() {
+                    LITERAL
+                } else {
+                    NUMBER
+                };
+                write!(out, "{}{}{}", style, &text[start..end], RESET).unwrap();
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Colors tags, leaving text content as it is.
+fn highlight_xml(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(open) = rest.find('<') {
+        out.push_str(&rest[..open]);
+        let close = rest[open..].find('>').map_or(rest.len(), |n| open + n + 1);
+        write!(out, "{}{}{}", TAG, &rest[open..close], RESET).unwrap();
+        rest = &rest[close..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// 16 bytes per line: offset, hex and printable ASCII.
+fn hex_dump(data: &[u8], color: bool) -> String {
+    const LIMIT: usize = 1024;
+    let mut out = String::new();
+    for (line, bytes) in data[..data.len().min(LIMIT)].chunks(16).enumerate() {
+        let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = bytes
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        let offset = format!("{:08x}", line * 16);
+        if color {
+            write!(out, "{}{}{}", DIM, offset, RESET).unwrap();
+        } else {
+            out.push_str(&offset);
+        }
+        writeln!(out, "  {:<47}  {}", hex.join(" "), ascii).unwrap();
+    }
+    if data.len() > LIMIT {
+        writeln!(out, "... {} more bytes", data.len() - LIMIT).unwrap();
+    }
+    out.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(&Png::STANDARD_HEADER), Kind::Png);
+        assert_eq!(sniff(&[0x1f, 0x8b, 8, 0]), Kind::Gzip);
+        assert_eq!(sniff(br#" {"a": [1, 2]} "#), Kind::Json);
+        assert_eq!(sniff(b"{not json"), Kind::Text);
+        assert_eq!(sniff(b"<?xml version=\"1.0\"?><a/>"), Kind::Xml);
+        assert_eq!(sniff(b"<config><a>1</a></config>\n"), Kind::Xml);
+        assert_eq!(sniff(b"hello\tworld\n"), Kind::Text);
+        assert_eq!(sniff(&[0, 1, 2, 0xff]), Kind::Binary);
+        assert_eq!(sniff(b"bell\x07"), Kind::Binary);
+        assert_eq!(Kind::Json.mime(), "application/json");
+    }
+
+    #[test]
+    fn test_pretty_json() {
+        assert_eq!(
+            pretty(br#"{"a":[1,true]}"#, false),
+            "{\n  \"a\": [\n    1,\n    true\n  ]\n}"
+        );
+        assert_eq!(
+            pretty(br#"{"a":"b:c","n":-1.5e3,"z":null}"#, true),
+            "{\n  \x1b[34;1m\"a\"\x1b[0m: \x1b[32m\"b:c\"\x1b[0m,\n  \
+             \x1b[34;1m\"n\"\x1b[0m: \x1b[36m-1.5e3\x1b[0m,\n  \
+             \x1b[34;1m\"z\"\x1b[0m: \x1b[35mnull\x1b[0m\n}"
+        );
+    }
+
+    #[test]
+    fn test_pretty_json_keeps_lexemes() {
+        assert_eq!(
+            pretty(br#" { "id" : 12345678901234567890, "s": "a,\"[b]", "e": [ ], "o": {} } "#, false),
+            "{\n  \"id\": 12345678901234567890,\n  \"s\": \"a,\\\"[b]\",\n  \"e\": [],\n  \"o\": {}\n}"
+        );
+    }
+
+    #[test]
+    fn test_pretty_escapes_controls() {
+        assert_eq!(
+            pretty(b"<a>\x1b[2J\xc2\x9b</a>", false),
+            "<a>\\x1b[2J\\u{009b}</a>"
+        );
+        assert_eq!(pretty(b"line\r\nnext\r", false), "line\r\nnext\\x0d");
+    }
+
+    #[test]
+    fn test_pretty_xml_and_binary() {
+        assert_eq!(
+            pretty(b"<a x=\"1\">hi</a>", true),
+            "\x1b[36m<a x=\"1\">\x1b[0mhi\x1b[36m</a>\x1b[0m"
+        );
+        assert_eq!(
+            pretty(b"\x00abc", false),
+            format!("00000000  {:<47}  .abc", "00 61 62 63")
+        );
+        assert_eq!(pretty(&[0x1f, 0x8b], false), "gzip data, 2 bytes");
+    }
+}
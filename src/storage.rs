@@ -0,0 +1,379 @@
+//! `s3://` and `gs://` objects as the files of commands.
+//!
+//! With the `cloud` feature, every command that reads or rewrites a PNG
+//! accepts an object URL in place of a file, and `verify` and `gc` also take
+//! prefixes and name patterns, so an asset pipeline that lives in object
+//! storage needs no local sync step. Objects are streamed through the `aws`
+//! and `gcloud` command line tools, which bring their own credentials and
+//! configuration; `--jobs` bounds how many transfers `verify` runs at once.
+//! Without the feature such paths are rejected.
+
+use crate::glob;
+use crate::Result;
+use std::fmt::Display;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::thread::{self, JoinHandle};
+
+/// Whether this build can reach object storage.
+pub const ENABLED: bool = cfg!(feature = "cloud");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scheme {
+    S3,
+    Gcs,
+}
+
+impl Scheme {
+    const ALL: [Scheme; 2] = [Scheme::S3, Scheme::Gcs];
+
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Scheme::S3 => "s3://",
+            Scheme::Gcs => "gs://",
+        }
+    }
+
+    fn program(self) -> &'static str {
+        match self {
+            Scheme::S3 => "aws",
+            Scheme::Gcs => "gcloud",
+        }
+    }
+}
+
+/// An object, or with an empty or `/`-terminated key a prefix of objects.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ObjectUrl {
+    pub scheme: Scheme,
+    pub bucket: String,
+    pub key: String,
+}
+
+impl ObjectUrl {
+    /// `path` as an object URL, or `None` when it is a local path.
+    pub fn from_path(path: &Path) -> Result<Option<ObjectUrl>> {
+        let Some(path) = path.to_str() else {
+            return Ok(None);
+        };
+        let Some((scheme, rest)) = Scheme::ALL
+            .iter()
+            .find_map(|&scheme| Some((scheme, path.strip_prefix(scheme.prefix())?)))
+        else {
+            return Ok(None);
+        };
+        let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            Err(format!("{} has no bucket", path))?
+        }
+        Ok(Some(ObjectUrl {
+            scheme,
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        }))
+    }
+
+    /// The URL as a path, for commands that report on files.
+    pub fn to_path(&self) -> PathBuf {
+        PathBuf::from(self.to_string())
+    }
+}
+
+impl Display for ObjectUrl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}/{}", self.scheme.prefix(), self.bucket, self.key)
+    }
+}
+
+#[cfg(feature = "cloud")]
+fn command(scheme: Scheme, args: &[&str]) -> Result<Command> {
+    let mut command = Command::new(scheme.program());
+    command.args(args).stderr(Stdio::piped());
+    Ok(command)
+}
+
+#[cfg(not(feature = "cloud"))]
+fn command(scheme: Scheme, _args: &[&str]) -> Result<Command> {
+    Err(format!(
+        "{} paths need pngme built with the cloud feature",
+        scheme.prefix()
+    ))?
+}
+
+/// Collects what the tool prints to stderr on a thread of its own, so that a
+/// tool with a lot to say cannot block on a full pipe while pngme waits on
+/// its stdout or its exit.
+fn drain_stderr(child: &mut Child) -> Option<JoinHandle<String>> {
+    let mut pipe = child.stderr.take()?;
+    Some(thread::spawn(move || {
+        let mut stderr = String::new();
+        let _ = pipe.read_to_string(&mut stderr);
+        stderr
+    }))
+}
+
+/// The error of a tool that exited unsuccessfully, with what it printed.
+fn failure(stderr: Option<JoinHandle<String>>, doing: String) -> io::Error {
+    let stderr = stderr
+        .and_then(|handle| handle.join().ok())
+        .unwrap_or_default();
+    io::Error::other(format!("{}: {}", doing, stderr.trim()))
+}
+
+/// Streams the object at `url`.
+pub fn open(url: &ObjectUrl) -> Result<ObjectReader> {
+    let location = url.to_string();
+    let args: &[&str] = match url.scheme {
+        Scheme::S3 => &["s3", "cp", "--no-progress", &location, "-"],
+        Scheme::Gcs => &["storage", "cat", &location],
+    };
+    let mut child = command(url.scheme, args)?
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("running {}: {}", url.scheme.program(), e))?;
+    Ok(ObjectReader {
+        stdout: child.stdout.take(),
+        stderr: drain_stderr(&mut child),
+        child,
+        location,
+    })
+}
+
+/// Replaces the object at `url` with what is written, once
+/// [`ObjectWriter::finish`] is called.
+pub fn create(url: &ObjectUrl) -> Result<ObjectWriter> {
+    let location = url.to_string();
+    let args: &[&str] = match url.scheme {
+        Scheme::S3 => &["s3", "cp", "--no-progress", "-", &location],
+        Scheme::Gcs => &["storage", "cp", "-", &location],
+    };
+    let mut child = command(url.scheme, args)?
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("running {}: {}", url.scheme.program(), e))?;
+    Ok(ObjectWriter {
+        stdin: child.stdin.take(),
+        stderr: drain_stderr(&mut child),
+        child,
+        location,
+    })
+}
+
+/// The PNG objects `url` names: the ones directly under a prefix, or with
+/// `recursive` every one beneath it, the ones whose name matches a pattern
+/// such as `s3://bucket/out/*.png`, or the object itself. Objects are listed
+/// in URL order.
+pub fn expand(url: &ObjectUrl, recursive: bool) -> Result<Vec<ObjectUrl>> {
+    let dir = url.key.rfind('/').map_or(0, |i| i + 1);
+    let (prefix, name) = url.key.split_at(dir);
+    if !name.is_empty() && !name.contains(['*', '?']) {
+        return Ok(vec![url.clone()]);
+    }
+
+    let listing = ObjectUrl {
+        key: prefix.to_string(),
+        ..url.clone()
+    };
+    let location = listing.to_string();
+    let pattern = format!("{}**", location);
+    let args: &[&str] = match url.scheme {
+        Scheme::S3 => &["s3", "ls", "--recursive", &location],
+        Scheme::Gcs => &["storage", "ls", &pattern],
+    };
+    let output = command(url.scheme, args)?
+        .output()
+        .map_err(|e| format!("running {}: {}", url.scheme.program(), e))?;
+    if !output.status.success() {
+        Err(format!(
+            "listing {}: {}",
+            location,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))?
+    }
+
+    let mut objects: Vec<ObjectUrl> =
+        parse_listing(&listing, &String::from_utf8_lossy(&output.stdout))
+            .into_iter()
+            .filter(|object| {
+                let rest = &object.key[prefix.len()..];
+                match name {
+                    "" => recursive || !rest.contains('/'),
+                    _ => !rest.contains('/') && glob::matches(name, rest),
+                }
+            })
+            .collect();
+    if objects.is_empty() {
+        Err(format!("no PNG objects match {}", url))?
+    }
+    objects.sort();
+    Ok(objects)
+}
+
+/// The PNG objects under `listing` in the output of `aws s3 ls --recursive`,
+/// whose lines end with the key after a date, time and size, or of
+/// `gcloud storage ls`, which prints one URL per line.
+pub fn parse_listing(listing: &ObjectUrl, output: &str) -> Vec<ObjectUrl> {
+    output
+        .lines()
+        .filter_map(|line| match listing.scheme {
+            Scheme::S3 => {
+                let mut rest = line;
+                for _ in 0..3 {
+                    rest = rest.trim_start();
+                    rest = &rest[rest.find(' ')?..];
+                }
+                Some(ObjectUrl {
+                    key: rest.trim_start().to_string(),
+                    ..listing.clone()
+                })
+            }
+            Scheme::Gcs => ObjectUrl::from_path(Path::new(line.trim())).ok()?,
+        })
+        .filter(|object| {
+            object.bucket == listing.bucket
+                && object.key.starts_with(&listing.key)
+                && Path::new(&object.key)
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("png"))
+        })
+        .collect()
+}
+
+/// An object being downloaded. Reading past its end reports whether the
+/// download succeeded.
+pub struct ObjectReader {
+    child: Child,
+    stdout: Option<ChildStdout>,
+    stderr: Option<JoinHandle<String>>,
+    location: String,
+}
+
+impl Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(stdout) = self.stdout.as_mut() else {
+            return Ok(0);
+        };
+        let read = stdout.read(buf)?;
+        if read == 0 && !buf.is_empty() {
+            self.stdout = None;
+            if !self.child.wait()?.success() {
+                return Err(failure(
+                    self.stderr.take(),
+                    format!("reading {}", self.location),
+                ));
+            }
+        }
+        Ok(read)
+    }
+}
+
+/// Parsing stops at IEND, so the download may still be running.
+impl Drop for ObjectReader {
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// An object being uploaded. Dropping it without calling
+/// [`finish`](ObjectWriter::finish) abandons the upload, so an interrupted
+/// write never replaces the object with part of a file.
+pub struct ObjectWriter {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stderr: Option<JoinHandle<String>>,
+    location: String,
+}
+
+impl ObjectWriter {
+    pub fn finish(mut self) -> Result<()> {
+        drop(self.stdin.take());
+        if !self.child.wait()?.success() {
+            Err(failure(
+                self.stderr.take(),
+                format!("writing {}", self.location),
+            ))?
+        }
+        Ok(())
+    }
+}
+
+impl Write for ObjectWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.stdin.as_mut() {
+            Some(stdin) => stdin.write(buf),
+            None => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.stdin.as_mut() {
+            Some(stdin) => stdin.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ObjectWriter {
+    fn drop(&mut self) {
+        // Kill before the pipe closes, which the tool would take as the end
+        // of the object.
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> ObjectUrl {
+        ObjectUrl::from_path(Path::new(s)).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_from_path() {
+        let object = url("s3://assets/icons/a.png");
+        assert_eq!(object.scheme, Scheme::S3);
+        assert_eq!(object.bucket, "assets");
+        assert_eq!(object.key, "icons/a.png");
+        assert_eq!(object.to_string(), "s3://assets/icons/a.png");
+
+        assert_eq!(url("gs://assets").key, "");
+        assert!(ObjectUrl::from_path(Path::new("icons/a.png"))
+            .unwrap()
+            .is_none());
+        assert!(ObjectUrl::from_path(Path::new("s3:///a.png")).is_err());
+    }
+
+    #[test]
+    fn test_parse_listing() {
+        let s3 = "2024-06-01 12:00:00       1234 out/a.png\n\
+                  2024-06-01 12:00:00         10 out/notes.txt\n\
+                  2024-06-01 12:00:00     999999 out/deep/b c.PNG\n";
+        let keys: Vec<String> = parse_listing(&url("s3://assets/out/"), s3)
+            .into_iter()
+            .map(|object| object.key)
+            .collect();
+        assert_eq!(keys, ["out/a.png", "out/deep/b c.PNG"]);
+
+        let gcs = "gs://assets/out/a.png\ngs://other/out/b.png\n\n";
+        assert_eq!(
+            parse_listing(&url("gs://assets/out/"), gcs),
+            [url("gs://assets/out/a.png")]
+        );
+    }
+
+    #[cfg(not(feature = "cloud"))]
+    #[test]
+    fn test_needs_cloud_feature() {
+        let error = open(&url("s3://assets/a.png")).err().unwrap();
+        assert!(error.to_string().contains("cloud feature"));
+    }
+}
@@ -0,0 +1,223 @@
+//! Payloads spread across several carrier PNGs, one stripe per file.
+
+use crate::hash;
+use crate::Result;
+
+/// One fixed-size piece of a payload, stored as the data of a chunk.
+///
+/// Layout: the 4 magic bytes, a version byte, the payload id (the first 8
+/// bytes of its SHA-256), then big-endian `u32` index, `u32` stripe count and
+/// `u64` payload length, followed by the stripe's bytes. The id ties stripes of
+/// one payload together and checks the reassembled result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stripe {
+    pub id: [u8; 8],
+    pub index: u32,
+    pub count: u32,
+    pub total_len: u64,
+    pub data: Vec<u8>,
+}
+
+impl Stripe {
+    pub const MAGIC: [u8; 4] = *b"\0PMS";
+    pub const VERSION: u8 = 1;
+    const HEADER_LEN: usize = 4 + 1 + 8 + 4 + 4 + 8;
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Stripe::MAGIC.to_vec();
+        bytes.push(Stripe::VERSION);
+        bytes.extend_from_slice(&self.id);
+        bytes.extend_from_slice(&self.index.to_be_bytes());
+        bytes.extend_from_slice(&self.count.to_be_bytes());
+        bytes.extend_from_slice(&self.total_len.to_be_bytes());
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    /// Reads a stripe, or returns `None` when `data` is not one.
+    pub fn parse(data: &[u8]) -> Result<Option<Stripe>> {
+        if !data.starts_with(&Stripe::MAGIC) {
+            return Ok(None);
+        }
+        if data.len() < Stripe::HEADER_LEN {
+            return Err("truncated stripe header".into());
+        }
+        if data[4] != Stripe::VERSION {
+            return Err(format!("unsupported stripe version {}", data[4]).into());
+        }
+        let field = |start: usize, len: usize| &data[start..start + len];
+        let stripe = Stripe {
+            id: field(5, 8).try_into().unwrap(),
+            index: u32::from_be_bytes(field(13, 4).try_into().unwrap()),
+            count: u32::from_be_bytes(field(17, 4).try_into().unwrap()),
+            total_len: u64::from_be_bytes(field(21, 8).try_into().unwrap()),
+            data: data[Stripe::HEADER_LEN..].to_vec(),
+        };
+        if stripe.index >= stripe.count {
+            return Err(format!("stripe {} of only {}", stripe.index, stripe.count).into());
+        }
+        Ok(Some(stripe))
+    }
+}
+
+/// The indices below `count` that are not in `present`, which must be in
+/// ascending order, as inclusive ranges. The count comes from the file, so
+/// this takes time in the stripes present rather than in `count`.
+pub fn missing_ranges(count: u32, present: impl IntoIterator<Item = u32>) -> Vec<(u32, u32)> {
+    let mut missing = vec![];
+    let mut next = 0;
+    for index in present.into_iter().take_while(|&index| index < count) {
+        if index > next {
+            missing.push((next, index - 1));
+        }
+        next = next.max(index + 1);
+    }
+    if next < count {
+        missing.push((next, count - 1));
+    }
+    missing
+}
+
+/// Ranges as `0, 2-5, 9`.
+pub fn format_ranges(ranges: &[(u32, u32)]) -> String {
+    ranges
+        .iter()
+        .map(|&(first, last)| {
+            if first == last {
+                first.to_string()
+            } else {
+                format!("{}-{}", first, last)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn payload_id(payload: &[u8]) -> [u8; 8] {
+    hash::sha256(payload)[..8].try_into().unwrap()
+}
+
+/// Cuts `payload` into stripes of at most `stripe_size` bytes. An empty
+/// payload still gets one, empty, stripe.
+pub fn split(payload: &[u8], stripe_size: usize) -> Vec<Stripe> {
+    let pieces: Vec<&[u8]> = if payload.is_empty() {
+        vec![&[]]
+    } else {
+        payload.chunks(stripe_size.max(1)).collect()
+    };
+    let id = payload_id(payload);
+    pieces
+        .iter()
+        .enumerate()
+        .map(|(index, piece)| Stripe {
+            id,
+            index: index as u32,
+            count: pieces.len() as u32,
+            total_len: payload.len() as u64,
+            data: piece.to_vec(),
+        })
+        .collect()
+}
+
+/// Reassembles a payload from its stripes, in any order. Fails unless the
+/// stripes all belong to one payload and every one of them is present.
+pub fn gather(mut stripes: Vec<Stripe>) -> Result<Vec<u8>> {
+    let first = stripes.first().ok_or("no stripes found")?.clone();
+    if stripes.iter().any(|stripe| {
+        (stripe.id, stripe.count, stripe.total_len) != (first.id, first.count, first.total_len)
+    }) {
+        return Err("stripes from more than one payload found".into());
+    }
+
+    stripes.sort_by_key(|stripe| stripe.index);
+    stripes.dedup();
+    let missing = missing_ranges(first.count, stripes.iter().map(|stripe| stripe.index));
+    if !missing.is_empty() {
+        return Err(format!(
+            "missing stripes {} of {}",
+            format_ranges(&missing),
+            first.count
+        )
+        .into());
+    }
+    if stripes.len() != first.count as usize {
+        return Err("conflicting copies of the same stripe found".into());
+    }
+
+    let payload: Vec<u8> = stripes.into_iter().flat_map(|stripe| stripe.data).collect();
+    if payload.len() as u64 != first.total_len || payload_id(&payload) != first.id {
+        return Err("reassembled payload does not match its stripe headers".into());
+    }
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let payload: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let stripes = split(&payload, 300);
+        assert_eq!(stripes.len(), 4);
+        assert_eq!(stripes[3].data.len(), 100);
+
+        let parsed: Vec<Stripe> = stripes
+            .iter()
+            .rev()
+            .map(|stripe| Stripe::parse(&stripe.to_bytes()).unwrap().unwrap())
+            .collect();
+        assert_eq!(gather(parsed).unwrap(), payload);
+
+        assert_eq!(gather(split(b"", 10)).unwrap(), b"");
+        assert_eq!(Stripe::parse(b"plain text").unwrap(), None);
+        assert!(Stripe::parse(b"\0PMS\x01").is_err());
+    }
+
+    #[test]
+    fn test_gather_errors() {
+        let mut stripes = split(b"abcdefghij", 3);
+        stripes.remove(1);
+        assert_eq!(
+            gather(stripes).unwrap_err().to_string(),
+            "missing stripes 1 of 4"
+        );
+
+        let mut stripes = split(b"abcdefghij", 3);
+        stripes.push(split(b"another", 3).remove(0));
+        assert!(gather(stripes).is_err());
+
+        let mut stripes = split(b"abcdefghij", 3);
+        stripes[2].data = b"xyz".to_vec();
+        assert!(gather(stripes).is_err());
+
+        // The same stripe found in two files is fine.
+        let mut stripes = split(b"abcdefghij", 3);
+        stripes.push(stripes[0].clone());
+        assert_eq!(gather(stripes).unwrap(), b"abcdefghij");
+
+        assert!(gather(vec![]).is_err());
+
+        // A count from a crafted header is not iterated over.
+        let mut stripes = split(b"abcdefghij", 3);
+        for stripe in &mut stripes {
+            stripe.count = u32::MAX;
+        }
+        assert_eq!(
+            gather(stripes).unwrap_err().to_string(),
+            "missing stripes 4-4294967294 of 4294967295"
+        );
+
+        let mut stripe = split(b"abc", 3).remove(0);
+        stripe.count = 0;
+        assert!(Stripe::parse(&stripe.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_missing_ranges() {
+        assert_eq!(missing_ranges(3, [0, 1, 2]), []);
+        assert_eq!(missing_ranges(10, [1, 2, 5, 5]), [(0, 0), (3, 4), (6, 9)]);
+        assert_eq!(missing_ranges(u32::MAX, []), [(0, u32::MAX - 1)]);
+        assert_eq!(format_ranges(&[(0, 0), (3, 4)]), "0, 3-4");
+    }
+}
@@ -0,0 +1,134 @@
+use crate::ihdr::Header;
+use crate::png::Png;
+use std::fmt::Display;
+
+/// File-level figures shown at the top of `print`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Summary {
+    pub total_bytes: usize,
+    /// `None` when IHDR is missing or malformed.
+    pub header: Option<Header>,
+    pub critical: usize,
+    pub ancillary: usize,
+    pub private: usize,
+    /// Bytes taken by ancillary and private chunks, framing included.
+    pub metadata_bytes: usize,
+}
+
+impl Summary {
+    pub fn of(png: &Png) -> Summary {
+        let mut summary = Summary {
+            total_bytes: Png::STANDARD_HEADER.len(),
+            header: Header::from_png(png).ok(),
+            critical: 0,
+            ancillary: 0,
+            private: 0,
+            metadata_bytes: 0,
+        };
+        for chunk in png.chunks() {
+            // Length, type and CRC fields around the data.
+            let size = chunk.data().len() + 12;
+            summary.total_bytes += size;
+            match chunk.chunk_type().category() {
+                "critical" => {
+                    summary.critical += 1;
+                    continue;
+                }
+                "private" => summary.private += 1,
+                _ => summary.ancillary += 1,
+            }
+            summary.metadata_bytes += size;
+        }
+        summary
+    }
+
+    /// Share of the file taken by metadata, in percent.
+    pub fn metadata_percent(&self) -> f64 {
+        self.metadata_bytes as f64 * 100.0 / self.total_bytes as f64
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "size:       {} bytes", self.total_bytes)?;
+        match &self.header {
+            Some(header) => writeln!(
+                f,
+                "dimensions: {}x{} {}, {}-bit",
+                header.width, header.height, header.color_type, header.bit_depth
+            )?,
+            None => writeln!(f, "dimensions: unknown (no valid IHDR)")?,
+        }
+        writeln!(
+            f,
+            "chunks:     {} ({} critical, {} ancillary, {} private)",
+            self.critical + self.ancillary + self.private,
+            self.critical,
+            self.ancillary,
+            self.private
+        )?;
+        write!(
+            f,
+            "metadata:   {} bytes ({:.1}%)",
+            self.metadata_bytes,
+            self.metadata_percent()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, length: usize) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![0; length])
+    }
+
+    #[test]
+    fn test_summary() {
+        let ihdr = Chunk::new(
+            ChunkType::from_str("IHDR").unwrap(),
+            [
+                &4u32.to_be_bytes()[..],
+                &2u32.to_be_bytes(),
+                &[8, 6, 0, 0, 0],
+            ]
+            .concat(),
+        );
+        let png = Png::from_chunks(vec![
+            ihdr,
+            chunk("tEXt", 38),
+            chunk("IDAT", 100),
+            chunk("ruSt", 18),
+            chunk("IEND", 0),
+        ]);
+        let summary = Summary::of(&png);
+
+        assert_eq!(summary.total_bytes, 8 + 25 + 50 + 112 + 30 + 12);
+        assert_eq!(
+            (summary.critical, summary.ancillary, summary.private),
+            (3, 1, 1)
+        );
+        assert_eq!(summary.metadata_bytes, 80);
+        assert_eq!(
+            summary.to_string(),
+            "size:       237 bytes\n\
+             dimensions: 4x2 truecolor+alpha, 8-bit\n\
+             chunks:     5 (3 critical, 1 ancillary, 1 private)\n\
+             metadata:   80 bytes (33.8%)"
+        );
+    }
+
+    #[test]
+    fn test_summary_without_ihdr() {
+        let summary = Summary::of(&Png::from_chunks(vec![chunk("IEND", 0)]));
+        assert_eq!(summary.header, None);
+        assert_eq!(summary.metadata_bytes, 0);
+        assert!(summary
+            .to_string()
+            .contains("dimensions: unknown (no valid IHDR)"));
+    }
+}
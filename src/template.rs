@@ -0,0 +1,111 @@
+//! Expands `{variable}` placeholders in messages. Write `{{` and `}}` for
+//! literal braces.
+//!
+//! | Variable         | Value                                      |
+//! |------------------|--------------------------------------------|
+//! | `{date}`         | current UTC date, `YYYY-MM-DD`             |
+//! | `{timestamp}`    | current UTC time, `YYYY-MM-DDTHH:MM:SSZ`   |
+//! | `{user}`         | `$USER` (or `%USERNAME%` on Windows)       |
+//! | `{git_sha}`      | commit checked out in the current directory |
+//! | `{git_branch}`   | branch checked out in the current directory |
+//! | `{env:NAME}`     | environment variable `NAME`                |
+//! | `{sha256:PATH}`  | SHA-256 of the file at `PATH`, as hex      |
+//!
+//! Templates stamp build metadata, so `{date}` and `{timestamp}` use
+//! `SOURCE_DATE_EPOCH` instead of the clock when it is set.
+
+use crate::{hash, provenance, time, Result};
+use std::{env, fs};
+
+pub fn expand(template: &str) -> Result<String> {
+    expand_with(template, resolve)
+}
+
+/// Expands `template`, asking `resolve` for the value of each variable.
+pub fn expand_with(template: &str, resolve: impl Fn(&str) -> Result<String>) -> Result<String> {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find(['{', '}']) {
+        expanded.push_str(&rest[..start]);
+        let brace = rest.as_bytes()[start];
+        rest = &rest[start + 1..];
+
+        if rest.as_bytes().first() == Some(&brace) {
+            expanded.push(brace as char);
+            rest = &rest[1..];
+        } else if brace == b'}' {
+            return Err("unmatched '}' in template (write '}}' for a literal brace)".into());
+        } else {
+            let end = rest.find('}').ok_or("unterminated '{' in template")?;
+            expanded.push_str(&resolve(&rest[..end])?);
+            rest = &rest[end + 1..];
+        }
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+fn resolve(variable: &str) -> Result<String> {
+    let value = match variable.split_once(':') {
+        Some(("env", name)) => env::var(name).ok(),
+        Some(("sha256", path)) => Some(hash::to_hex(&hash::sha256(&fs::read(path)?))),
+        Some(_) => None,
+        None => match variable {
+            "date" => Some(time::format_rfc3339(time::build_time())[..10].to_string()),
+            "timestamp" => Some(time::format_rfc3339(time::build_time())),
+            "user" => env::var("USER").or_else(|_| env::var("USERNAME")).ok(),
+            "git_sha" => provenance::git(&["rev-parse", "HEAD"]),
+            "git_branch" => provenance::git(&["rev-parse", "--abbrev-ref", "HEAD"]),
+            _ => return Err(format!("unknown template variable {{{}}}", variable))?,
+        },
+    };
+
+    value.ok_or_else(|| format!("template variable {{{}}} has no value", variable).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake(variable: &str) -> Result<String> {
+        match variable {
+            "git_sha" => Ok("abc123".to_string()),
+            "user" => Ok("von".to_string()),
+            _ => Err(format!("unknown {}", variable))?,
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(
+            expand_with("built {git_sha} by {user}", fake).unwrap(),
+            "built abc123 by von"
+        );
+        assert_eq!(
+            expand_with("{{literal}} {user}", fake).unwrap(),
+            "{literal} von"
+        );
+        assert_eq!(expand_with("no variables", fake).unwrap(), "no variables");
+    }
+
+    #[test]
+    fn test_expand_errors() {
+        assert!(expand_with("{unknown}", fake).is_err());
+        assert!(expand_with("{user", fake).is_err());
+        assert!(expand_with("user}", fake).is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(resolve("date").unwrap().len(), 10);
+        assert_eq!(
+            resolve("sha256:Cargo.toml").unwrap(),
+            hash::to_hex(&hash::sha256(&fs::read("Cargo.toml").unwrap()))
+        );
+        assert!(resolve("env:PNGME_TEMPLATE_TEST_UNSET").is_err());
+        assert!(resolve("nope").is_err());
+        assert!(resolve("nope:value").is_err());
+    }
+}
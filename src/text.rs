@@ -0,0 +1,449 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::provenance;
+use crate::warning::Warning;
+use crate::{zlib, Result};
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// What to do with characters that Latin-1, the encoding of tEXt and zTXt
+/// and of every keyword, cannot represent. The default is also the default
+/// of `tag --unmappable`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Unmappable {
+    /// Refuse to write the chunk.
+    Error,
+    /// Write the closest ASCII spelling, e.g. `Łódź` as `Lodz`.
+    #[default]
+    Transliterate,
+    /// Write `?` instead.
+    Replace,
+}
+
+impl Display for Unmappable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Unmappable::Error => "error",
+            Unmappable::Transliterate => "transliterate",
+            Unmappable::Replace => "replace",
+        })
+    }
+}
+
+impl FromStr for Unmappable {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        match s {
+            "error" => Ok(Unmappable::Error),
+            "transliterate" => Ok(Unmappable::Transliterate),
+            "replace" => Ok(Unmappable::Replace),
+            _ => Err(format!(
+                "expected error, transliterate or replace, not {:?}",
+                s
+            )),
+        }
+    }
+}
+
+/// Base letters of Latin Extended-A, U+0100 to U+017F.
+const LATIN_EXTENDED_A: [&str; 128] = [
+    "A", "a", "A", "a", "A", "a", "C", "c", "C", "c", "C", "c", "C", "c", "D", "d", "D", "d", "E",
+    "e", "E", "e", "E", "e", "E", "e", "E", "e", "G", "g", "G", "g", "G", "g", "G", "g", "H", "h",
+    "H", "h", "I", "i", "I", "i", "I", "i", "I", "i", "I", "i", "IJ", "ij", "J", "j", "K", "k",
+    "k", "L", "l", "L", "l", "L", "l", "L", "l", "L", "l", "N", "n", "N", "n", "N", "n", "'n", "N",
+    "n", "O", "o", "O", "o", "O", "o", "OE", "oe", "R", "r", "R", "r", "R", "r", "S", "s", "S",
+    "s", "S", "s", "S", "s", "T", "t", "T", "t", "T", "t", "U", "u", "U", "u", "U", "u", "U", "u",
+    "U", "u", "U", "u", "W", "w", "Y", "y", "Y", "Z", "z", "Z", "z", "Z", "z", "s",
+];
+
+fn transliterate(c: char) -> &'static str {
+    match c {
+        '\u{100}'..='\u{17f}' => LATIN_EXTENDED_A[c as usize - 0x100],
+        '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}' | '\u{2032}' => "'",
+        '\u{201c}' | '\u{201d}' | '\u{201e}' | '\u{2033}' => "\"",
+        '\u{2010}'..='\u{2015}' | '\u{2212}' => "-",
+        '\u{2026}' => "...",
+        '\u{2022}' => "*",
+        '\u{2039}' => "<",
+        '\u{203a}' => ">",
+        '\u{20ac}' => "EUR",
+        '\u{2122}' => "(TM)",
+        _ => "?",
+    }
+}
+
+/// Latin-1 maps every byte to the code point of the same value.
+pub fn decode_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
+pub fn encode_latin1(text: &str, unmappable: Unmappable) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        if (c as u32) < 0x100 {
+            bytes.push(c as u8);
+            continue;
+        }
+        match unmappable {
+            Unmappable::Error => {
+                return Err(format!("{:?} cannot be written as Latin-1", c).into());
+            }
+            Unmappable::Transliterate => bytes.extend_from_slice(transliterate(c).as_bytes()),
+            Unmappable::Replace => bytes.push(b'?'),
+        }
+    }
+    Ok(bytes)
+}
+
+/// Keywords the spec predefines. Any other keyword is allowed but only
+/// meaningful to software that knows it.
+pub const REGISTERED_KEYWORDS: &[&str] = &[
+    "Title",
+    "Author",
+    "Description",
+    "Copyright",
+    "Creation Time",
+    "Software",
+    "Disclaimer",
+    "Warning",
+    "Source",
+    "Comment",
+    "Collection",
+    "XML:com.adobe.xmp",
+];
+
+/// Checks the spec's keyword rules: 1 to 79 printable Latin-1 characters
+/// (letters, digits, punctuation and spaces, but not non-breaking space),
+/// with no leading, trailing or consecutive spaces.
+pub fn validate_keyword(keyword: &str) -> Result<()> {
+    let length = keyword.chars().count();
+    let problem = if length == 0 {
+        "is empty".to_string()
+    } else if length > 79 {
+        format!("is {} characters long, the limit is 79", length)
+    } else if let Some(c) = keyword
+        .chars()
+        .find(|&c| !matches!(c, ' '..='~' | '\u{a1}'..='\u{ff}'))
+    {
+        format!(
+            "contains {:?}, which is not a printable Latin-1 character",
+            c
+        )
+    } else if keyword.starts_with(' ') || keyword.ends_with(' ') {
+        "has a leading or trailing space".to_string()
+    } else if keyword.contains("  ") {
+        "has consecutive spaces".to_string()
+    } else {
+        return Ok(());
+    };
+    Err(format!("keyword {:?} {}", keyword, problem).into())
+}
+
+/// Which of the three text chunk types holds an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// `tEXt`: Latin-1 text.
+    Latin1,
+    /// `zTXt`: zlib-compressed Latin-1 text.
+    Compressed,
+    /// `iTXt`: UTF-8 text, optionally with a language tag, stored uncompressed.
+    International,
+}
+
+impl Encoding {
+    pub fn chunk_type(&self) -> [u8; 4] {
+        match self {
+            Encoding::Latin1 => *b"tEXt",
+            Encoding::Compressed => *b"zTXt",
+            Encoding::International => *b"iTXt",
+        }
+    }
+}
+
+/// A `tEXt`, `zTXt` or `iTXt` chunk: a keyword and its text.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TextChunk {
+    pub keyword: String,
+    pub text: String,
+    pub encoding: Encoding,
+    /// iTXt only: RFC 3066 language tag, possibly empty.
+    pub language: String,
+    /// iTXt only: the keyword in `language`, possibly empty.
+    pub translated_keyword: String,
+}
+
+impl TextChunk {
+    pub const TYPE: [u8; 4] = *b"tEXt";
+
+    pub fn new(keyword: &str, text: &str) -> TextChunk {
+        TextChunk::with_encoding(keyword, text, Encoding::Latin1)
+    }
+
+    pub fn with_encoding(keyword: &str, text: &str, encoding: Encoding) -> TextChunk {
+        TextChunk {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+            encoding,
+            language: String::new(),
+            translated_keyword: String::new(),
+        }
+    }
+
+    /// Encodes the entry, failing if its keyword breaks the spec's rules.
+    pub fn to_chunk(&self, unmappable: Unmappable) -> Result<Chunk> {
+        let mut data = encode_latin1(&self.keyword, unmappable)?;
+        validate_keyword(&decode_latin1(&data))?;
+        data.push(0);
+        match self.encoding {
+            Encoding::Latin1 => data.extend(encode_latin1(&self.text, unmappable)?),
+            Encoding::Compressed => {
+                data.push(0);
+                data.extend(zlib::compress(&encode_latin1(&self.text, unmappable)?));
+            }
+            Encoding::International => {
+                data.extend_from_slice(&[0, 0]);
+                data.extend_from_slice(self.language.as_bytes());
+                data.push(0);
+                data.extend_from_slice(self.translated_keyword.as_bytes());
+                data.push(0);
+                data.extend_from_slice(self.text.as_bytes());
+            }
+        }
+        Ok(Chunk::new(
+            ChunkType::try_from(self.encoding.chunk_type())?,
+            data,
+        ))
+    }
+
+    pub fn from_chunk(chunk: &Chunk) -> Result<TextChunk> {
+        let encoding = match &chunk.chunk_type().bytes() {
+            b"tEXt" => Encoding::Latin1,
+            b"zTXt" => Encoding::Compressed,
+            b"iTXt" => Encoding::International,
+            _ => return Err(format!("{} is not a text chunk", chunk.chunk_type()))?,
+        };
+        let (keyword, rest) = split_nul(chunk.data())
+            .ok_or_else(|| format!("{} chunk has no keyword separator", chunk.chunk_type()))?;
+        let mut entry = TextChunk::with_encoding(&decode_latin1(keyword), "", encoding);
+
+        match encoding {
+            Encoding::Latin1 => entry.text = decode_latin1(rest),
+            Encoding::Compressed => {
+                let (&method, compressed) = rest
+                    .split_first()
+                    .ok_or("zTXt chunk has no compression method")?;
+                entry.text = decode_latin1(&decompress(method, compressed)?);
+            }
+            Encoding::International => {
+                let &[flag, method, ..] = rest else {
+                    return Err("iTXt chunk has no compression fields")?;
+                };
+                let malformed = "iTXt chunk is missing a separator";
+                let (language, rest) = split_nul(&rest[2..]).ok_or(malformed)?;
+                let (translated_keyword, text) = split_nul(rest).ok_or(malformed)?;
+                let text = match flag {
+                    0 => text.to_vec(),
+                    1 => decompress(method, text)?,
+                    _ => return Err(format!("invalid iTXt compression flag {}", flag))?,
+                };
+                entry.language = String::from_utf8(language.to_vec())?;
+                entry.translated_keyword = String::from_utf8(translated_keyword.to_vec())?;
+                entry.text = String::from_utf8(text)
+                    .map_err(|_| "iTXt text is not valid UTF-8".to_string())?;
+            }
+        }
+        Ok(entry)
+    }
+}
+
+impl TextChunk {
+    /// Warns about a valid but unregistered keyword. The provenance keywords
+    /// `tag --from-git` writes are pngme's own and never warned about.
+    pub fn warnings(&self) -> Vec<Warning> {
+        let keyword = self.keyword.as_str();
+        if REGISTERED_KEYWORDS.contains(&keyword) || provenance::KEYWORDS.contains(&keyword) {
+            return vec![];
+        }
+        vec![Warning::UnregisteredKeyword {
+            chunk_type: String::from_utf8_lossy(&self.encoding.chunk_type()).into_owned(),
+            keyword: self.keyword.clone(),
+        }]
+    }
+}
+
+impl Display for TextChunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.keyword, self.text)
+    }
+}
+
+fn split_nul(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let separator = data.iter().position(|&byte| byte == 0)?;
+    Some((&data[..separator], &data[separator + 1..]))
+}
+
+/// The most a compressed text may inflate to, so that a few kilobytes of
+/// zTXt cannot make pngme allocate gigabytes.
+const MAX_TEXT_LENGTH: usize = 16 << 20;
+
+fn decompress(method: u8, data: &[u8]) -> Result<Vec<u8>> {
+    if method != 0 {
+        return Err(format!("unknown text compression method {}", method).into());
+    }
+    zlib::decompress_limited(data, MAX_TEXT_LENGTH)
+}
+
+/// Every text entry in the file, in chunk order. Chunks that fail to
+/// decode are skipped.
+pub fn entries(png: &Png) -> Vec<TextChunk> {
+    png.chunks()
+        .iter()
+        .filter_map(|chunk| TextChunk::from_chunk(chunk).ok())
+        .collect()
+}
+
+/// Sets a keyword, replacing any text chunks of any type already using it.
+pub fn set_entry(png: &mut Png, entry: &TextChunk, unmappable: Unmappable) -> Result<()> {
+    let chunk = entry.to_chunk(unmappable)?;
+    while let Some(index) = png.chunks().iter().position(|chunk| {
+        TextChunk::from_chunk(chunk).is_ok_and(|existing| existing.keyword == entry.keyword)
+    }) {
+        png.remove_chunk_at(index);
+    }
+    png.insert_before_iend(chunk);
+    Ok(())
+}
+
+/// Sets `keyword` to `text` in a `tEXt` chunk.
+pub fn set_text(png: &mut Png, keyword: &str, text: &str, unmappable: Unmappable) -> Result<()> {
+    set_entry(png, &TextChunk::new(keyword, text), unmappable)
+}
+
+pub fn get_text(png: &Png, keyword: &str) -> Option<String> {
+    entries(png)
+        .into_iter()
+        .find(|text| text.keyword == keyword)
+        .map(|text| text.text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_chunk_round_trip() {
+        let text = TextChunk::new("Author", "von");
+        let chunk = text.to_chunk(Unmappable::Error).unwrap();
+        assert_eq!(chunk.data(), b"Author\0von");
+        assert_eq!(TextChunk::from_chunk(&chunk).unwrap(), text);
+    }
+
+    #[test]
+    fn test_latin1() {
+        let text = TextChunk::new("Comment", "café ½");
+        let chunk = text.to_chunk(Unmappable::Error).unwrap();
+        assert_eq!(chunk.data(), b"Comment\0caf\xe9 \xbd");
+        assert_eq!(TextChunk::from_chunk(&chunk).unwrap(), text);
+
+        assert!(encode_latin1("Łódź", Unmappable::Error).is_err());
+        assert_eq!(
+            encode_latin1("Łódź “Œuvre” – 5€", Unmappable::Transliterate).unwrap(),
+            b"L\xf3dz \"OEuvre\" - 5EUR"
+        );
+        assert_eq!(
+            encode_latin1("Łódź ✓", Unmappable::Replace).unwrap(),
+            b"?\xf3d? ?"
+        );
+        for mode in [
+            Unmappable::Error,
+            Unmappable::default(),
+            Unmappable::Replace,
+        ] {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+        }
+    }
+
+    #[test]
+    fn test_keywords() {
+        for keyword in ["Title", "Creation Time", "pngme.git.commit", "Légende"] {
+            assert!(validate_keyword(keyword).is_ok(), "{}", keyword);
+        }
+        for keyword in ["", " Title", "Title ", "Creation  Time", "a\tb", "a\u{a0}b"] {
+            assert!(validate_keyword(keyword).is_err(), "{:?}", keyword);
+        }
+        assert!(validate_keyword(&"k".repeat(79)).is_ok());
+        assert!(validate_keyword(&"k".repeat(80)).is_err());
+        assert!(TextChunk::new("Title ", "x")
+            .to_chunk(Unmappable::Error)
+            .is_err());
+
+        assert!(TextChunk::new("Author", "x").warnings().is_empty());
+        assert!(TextChunk::new(provenance::GIT_COMMIT, "x")
+            .warnings()
+            .is_empty());
+        assert_eq!(
+            TextChunk::with_encoding("Build Id", "x", Encoding::International)
+                .warnings()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["iTXt keyword \"Build Id\" is not a predefined keyword"]
+        );
+    }
+
+    #[test]
+    fn test_compressed_and_international() {
+        let compressed =
+            TextChunk::with_encoding("Description", &"dé ".repeat(50), Encoding::Compressed);
+        let chunk = compressed.to_chunk(Unmappable::Error).unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "zTXt");
+        assert!(chunk.data().len() < 100);
+        assert_eq!(TextChunk::from_chunk(&chunk).unwrap(), compressed);
+
+        let mut international =
+            TextChunk::with_encoding("Title", "Łódź ✓", Encoding::International);
+        international.language = "pl".to_string();
+        international.translated_keyword = "Tytuł".to_string();
+        let chunk = international.to_chunk(Unmappable::Error).unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "iTXt");
+        assert_eq!(TextChunk::from_chunk(&chunk).unwrap(), international);
+    }
+
+    #[test]
+    fn test_not_a_text_chunk() {
+        let chunk = Chunk::new(ChunkType::try_from(*b"ruSt").unwrap(), b"a\0b".to_vec());
+        assert!(TextChunk::from_chunk(&chunk).is_err());
+
+        let chunk = Chunk::new(
+            ChunkType::try_from(TextChunk::TYPE).unwrap(),
+            b"ab".to_vec(),
+        );
+        assert!(TextChunk::from_chunk(&chunk).is_err());
+
+        let chunk = Chunk::new(ChunkType::try_from(*b"iTXt").unwrap(), b"a\0\0".to_vec());
+        assert!(TextChunk::from_chunk(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_set_text_replaces() {
+        let mut png = Png::from_chunks(vec![Chunk::new(
+            ChunkType::try_from(*b"IEND").unwrap(),
+            vec![],
+        )]);
+        set_text(&mut png, "pngme.git.commit", "abc", Unmappable::Error).unwrap();
+        set_text(&mut png, "Title", "dice", Unmappable::Error).unwrap();
+        set_entry(
+            &mut png,
+            &TextChunk::with_encoding("pngme.git.commit", "def", Encoding::Compressed),
+            Unmappable::Error,
+        )
+        .unwrap();
+
+        assert_eq!(png.chunks().len(), 3);
+        assert_eq!(get_text(&png, "pngme.git.commit").unwrap(), "def");
+        assert_eq!(get_text(&png, "Title").unwrap(), "dice");
+        assert!(get_text(&png, "Missing").is_none());
+    }
+}
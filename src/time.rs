@@ -0,0 +1,124 @@
+//! Just enough UTC calendar arithmetic for timestamps stored in images.
+
+use crate::Result;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds since the Unix epoch on the wall clock, for timestamps that must
+/// tell events apart, such as the versions of a payload.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// The time to record as build metadata: `SOURCE_DATE_EPOCH` when set, so
+/// that builds which embed timestamps stay reproducible, and [`now`]
+/// otherwise.
+pub fn build_time() -> u64 {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(now)
+}
+
+/// Formats `secs` since the Unix epoch as `YYYY-MM-DDTHH:MM:SSZ`.
+pub fn format_rfc3339(secs: u64) -> String {
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let seconds_of_day = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60
+    )
+}
+
+/// Parses `YYYY-MM-DDTHH:MM:SSZ`, or a bare `YYYY-MM-DD` meaning the end
+/// of that day, so that "as of June 1" includes everything from June 1.
+pub fn parse_as_of(text: &str) -> Result<u64> {
+    let invalid = || format!("{:?} is not YYYY-MM-DD or YYYY-MM-DDTHH:MM:SSZ", text);
+    let (date, time) = match text.split_once('T') {
+        Some((date, time)) => (date, Some(time.strip_suffix('Z').ok_or_else(invalid)?)),
+        None => (text, None),
+    };
+    let fields = |text: &str, separator| -> Option<Vec<u32>> {
+        text.split(separator)
+            .map(|field| field.parse().ok().filter(|_| field.len() >= 2))
+            .collect()
+    };
+    let Some(&[year, month, day]) = fields(date, '-').as_deref() else {
+        return Err(invalid().into());
+    };
+    let seconds_of_day = match time.map(|time| fields(time, ':')) {
+        None => 86399,
+        Some(Some(hms)) if matches!(hms[..], [h, m, s] if h < 24 && m < 60 && s < 60) => {
+            hms[0] * 3600 + hms[1] * 60 + hms[2]
+        }
+        Some(_) => return Err(invalid().into()),
+    };
+    let days = days_from_civil(year as i64, month, day);
+    if days < 0 || civil_from_days(days) != (year as i64, month, day) {
+        return Err(invalid().into());
+    }
+    Ok(days as u64 * 86400 + seconds_of_day as u64)
+}
+
+/// The inverse of [`civil_from_days`], from Howard Hinnant's
+/// `days_from_civil`. Out-of-range months and days are not rejected.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let day_of_year = (153 * mp + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Converts days since 1970-01-01 to a proleptic Gregorian (year, month, day),
+/// following Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_rfc3339() {
+        assert_eq!(format_rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_rfc3339(951782400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_rfc3339(1717243199), "2024-06-01T11:59:59Z");
+    }
+
+    #[test]
+    fn test_parse_as_of() {
+        assert_eq!(parse_as_of("2024-06-01T11:59:59Z").unwrap(), 1717243199);
+        assert_eq!(parse_as_of("1970-01-01T00:00:00Z").unwrap(), 0);
+        assert_eq!(parse_as_of("2000-02-29").unwrap(), 951782400 + 86399);
+        for text in [
+            "2023-02-29",
+            "2024-13-01",
+            "2024-6-1",
+            "2024-06-01T25:00:00Z",
+            "2024-06-01T12:00:00",
+            "yesterday",
+        ] {
+            assert!(parse_as_of(text).is_err(), "{}", text);
+        }
+    }
+}
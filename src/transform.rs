@@ -0,0 +1,264 @@
+use crate::i18n::{self, CodedError};
+use crate::zlib;
+use crate::Result;
+use std::str::FromStr;
+
+/// One reversible stage of the payload pipeline. Stages are applied in the
+/// order given on encode and their ids are recorded in the payload header, so
+/// decode can undo them without being told which ones were used.
+pub trait Transform {
+    /// Identifies the stage inside a payload header. Never reuse an id.
+    fn id(&self) -> u8;
+
+    fn name(&self) -> &'static str;
+
+    fn apply(&self, data: Vec<u8>, options: &Options) -> Result<Vec<u8>>;
+
+    fn reverse(&self, data: Vec<u8>, options: &Options) -> Result<Vec<u8>>;
+
+    /// Whether the stage reads `Options::dictionary`, in which case the
+    /// dictionary id is recorded in the payload header.
+    fn uses_dictionary(&self) -> bool {
+        false
+    }
+}
+
+/// Settings shared by every stage of one encode or decode.
+#[derive(Default)]
+pub struct Options {
+    /// Preset dictionary for compression, e.g. a sample of the JSON documents
+    /// a fleet of images carries.
+    pub dictionary: Option<Vec<u8>>,
+}
+
+/// The id recorded for a compression dictionary: its Adler-32, the same value
+/// zlib stores as DICTID.
+pub fn dictionary_id(dictionary: &[u8]) -> u32 {
+    zlib::adler32(dictionary)
+}
+
+/// Every available stage. Adding a transform only requires listing it here.
+pub fn all() -> Vec<Box<dyn Transform>> {
+    vec![Box::new(Compress), Box::new(Base64)]
+}
+
+pub fn by_name(name: &str) -> Result<Box<dyn Transform>> {
+    all()
+        .into_iter()
+        .find(|transform| transform.name() == name)
+        .ok_or_else(|| {
+            CodedError::new(&i18n::UNKNOWN_TRANSFORM, vec![format!("{:?}", name)]).into()
+        })
+}
+
+pub fn by_id(id: u8) -> Result<Box<dyn Transform>> {
+    all()
+        .into_iter()
+        .find(|transform| transform.id() == id)
+        .ok_or_else(|| CodedError::new(&i18n::UNKNOWN_TRANSFORM_ID, vec![id.to_string()]).into())
+}
+
+/// How `encode --compress` compresses a payload ahead of any `--transform`
+/// stages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    /// The `compress` transform.
+    Deflate,
+    /// Whichever of the others gives the smallest payload.
+    Auto,
+}
+
+impl Compression {
+    /// Names of the leading stages to try; each choice is one list, and the
+    /// one giving the smallest payload is kept, earlier ones winning ties.
+    pub fn candidates(&self) -> &'static [&'static [&'static str]] {
+        match self {
+            Compression::None => &[&[]],
+            Compression::Deflate => &[&["compress"]],
+            Compression::Auto => &[&[], &["compress"]],
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        match s {
+            "none" => Ok(Compression::None),
+            "deflate" => Ok(Compression::Deflate),
+            "auto" => Ok(Compression::Auto),
+            "zstd" => Err("zstd is not available in this build; use deflate or auto".into()),
+            _ => Err(format!("expected none, deflate or auto, not {:?}", s)),
+        }
+    }
+}
+
+/// zlib compression.
+pub struct Compress;
+
+impl Compress {
+    /// The most a compressed payload may inflate to, well past anything
+    /// pngme stores but small enough that a crafted stream cannot exhaust
+    /// memory.
+    pub const MAX_LENGTH: usize = 256 << 20;
+}
+
+impl Transform for Compress {
+    fn id(&self) -> u8 {
+        1
+    }
+
+    fn name(&self) -> &'static str {
+        "compress"
+    }
+
+    fn apply(&self, data: Vec<u8>, options: &Options) -> Result<Vec<u8>> {
+        Ok(zlib::compress_with_dictionary(
+            &data,
+            options.dictionary.as_deref(),
+        ))
+    }
+
+    fn reverse(&self, data: Vec<u8>, options: &Options) -> Result<Vec<u8>> {
+        zlib::decompress_limited_with_dictionary(
+            &data,
+            options.dictionary.as_deref(),
+            Compress::MAX_LENGTH,
+        )
+    }
+
+    fn uses_dictionary(&self) -> bool {
+        true
+    }
+}
+
+/// Standard base64 with padding, for payloads that must stay printable.
+pub struct Base64;
+
+impl Base64 {
+    const ALPHABET: &'static [u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    fn value(byte: u8) -> Result<u32> {
+        Base64::ALPHABET
+            .iter()
+            .position(|&c| c == byte)
+            .map(|i| i as u32)
+            .ok_or_else(|| format!("invalid base64 character {:?}", byte as char).into())
+    }
+}
+
+impl Transform for Base64 {
+    fn id(&self) -> u8 {
+        2
+    }
+
+    fn name(&self) -> &'static str {
+        "base64"
+    }
+
+    fn apply(&self, data: Vec<u8>, _: &Options) -> Result<Vec<u8>> {
+        let mut encoded = Vec::with_capacity(data.len().div_ceil(3) * 4);
+        for group in data.chunks(3) {
+            let bits = group.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+                bits | (byte as u32) << (16 - 8 * i)
+            });
+            for i in 0..4 {
+                if i <= group.len() {
+                    encoded.push(Base64::ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize]);
+                } else {
+                    encoded.push(b'=');
+                }
+            }
+        }
+        Ok(encoded)
+    }
+
+    fn reverse(&self, data: Vec<u8>, _: &Options) -> Result<Vec<u8>> {
+        let data: Vec<u8> = data
+            .into_iter()
+            .filter(|byte| !byte.is_ascii_whitespace())
+            .collect();
+        if !data.len().is_multiple_of(4) {
+            return Err("base64 payload length is not a multiple of 4")?;
+        }
+
+        let mut decoded = Vec::with_capacity(data.len() / 4 * 3);
+        let groups = data.len() / 4;
+        for (n, group) in data.chunks(4).enumerate() {
+            let padding = group.iter().rev().take_while(|&&c| c == b'=').count();
+            // Padding may only end the payload.
+            if padding > 2 || (padding > 0 && n + 1 < groups) {
+                return Err("invalid base64 padding")?;
+            }
+            let mut bits = 0u32;
+            for (i, &c) in group[..4 - padding].iter().enumerate() {
+                bits |= Base64::value(c)? << (18 - 6 * i);
+            }
+            decoded.extend_from_slice(&bits.to_be_bytes()[1..4 - padding]);
+        }
+        Ok(decoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        let cases: [(&[u8], &[u8]); 4] = [
+            (b"", b""),
+            (b"f", b"Zg=="),
+            (b"fo", b"Zm8="),
+            (b"foobar", b"Zm9vYmFy"),
+        ];
+        let options = Options::default();
+        for (plain, encoded) in cases {
+            assert_eq!(Base64.apply(plain.to_vec(), &options).unwrap(), encoded);
+            assert_eq!(Base64.reverse(encoded.to_vec(), &options).unwrap(), plain);
+        }
+        assert!(Base64.reverse(b"Zm9v!mFy".to_vec(), &options).is_err());
+        for invalid in [&b"Zg==Zg=="[..], b"Zm8=Zm9v", b"Zg=a", b"Z===", b"===="] {
+            assert!(Base64.reverse(invalid.to_vec(), &options).is_err());
+        }
+    }
+
+    #[test]
+    fn test_compress() {
+        let options = Options::default();
+        let data = b"abcabcabcabcabcabcabcabc".to_vec();
+        let compressed = Compress.apply(data.clone(), &options).unwrap();
+        assert_eq!(Compress.reverse(compressed, &options).unwrap(), data);
+    }
+
+    #[test]
+    fn test_compression_candidates() {
+        assert_eq!("auto".parse(), Ok(Compression::Auto));
+        assert!("zstd".parse::<Compression>().is_err());
+        for compression in [Compression::None, Compression::Deflate, Compression::Auto] {
+            for name in compression.candidates().iter().copied().flatten() {
+                assert!(by_name(name).is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(by_name("base64").unwrap().id(), 2);
+        assert_eq!(by_id(1).unwrap().name(), "compress");
+        assert!(by_name("rot13").is_err());
+        assert!(by_id(0).is_err());
+    }
+
+    #[test]
+    fn test_ids_are_unique() {
+        let mut ids: Vec<u8> = all().iter().map(|transform| transform.id()).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), all().len());
+    }
+}
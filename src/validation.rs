@@ -0,0 +1,391 @@
+use crate::chunk::Chunk;
+use crate::png::Png;
+use crate::warning::Warning;
+
+/// A PNG spec rule pngme checks, with what `verify --explain` tells the user
+/// about it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Rule {
+    /// Stable short name, e.g. for matching in scripts.
+    pub id: &'static str,
+    /// One line stating the rule; used in warning messages.
+    pub summary: &'static str,
+    /// Where the rule comes from in the PNG specification.
+    pub spec: &'static str,
+    /// Why breaking the rule matters in practice.
+    pub rationale: &'static str,
+    /// Command that fixes the problem, if pngme has one. `{file}` and
+    /// `{type}` are replaced by the offending file and chunk type.
+    pub fix: Option<&'static str>,
+}
+
+impl Rule {
+    /// The fix command with its placeholders filled in.
+    pub fn fix_for(&self, file: &str, chunk_type: Option<&str>) -> Option<String> {
+        self.fix_at(file, chunk_type, None)
+    }
+
+    /// Like [`fix_for`](Rule::fix_for), for fixes that name the chunk by its
+    /// offset.
+    pub fn fix_at(
+        &self,
+        file: &str,
+        chunk_type: Option<&str>,
+        offset: Option<usize>,
+    ) -> Option<String> {
+        self.fix.map(|fix| {
+            fix.replace("{file}", file)
+                .replace("{type}", chunk_type.unwrap_or("<type>"))
+                .replace(
+                    "{offset}",
+                    &offset.map_or_else(|| "<offset>".to_string(), |offset| offset.to_string()),
+                )
+        })
+    }
+}
+
+pub static SIGNATURE: Rule = Rule {
+    id: "signature",
+    summary: "file must start with the PNG signature",
+    spec: "PNG spec 5.2, PNG signature",
+    rationale: "decoders identify PNG files by these 8 bytes and reject anything else",
+    fix: Some("pngme repair --structure {file}"),
+};
+
+pub static TRUNCATED: Rule = Rule {
+    id: "truncated",
+    summary: "every chunk must be complete",
+    spec: "PNG spec 5.3, Chunk layout",
+    rationale: "a chunk whose length runs past the end of the file usually means an interrupted download or write",
+    fix: Some("pngme repair --structure {file}"),
+};
+
+pub static CHUNK_TYPE: Rule = Rule {
+    id: "chunk-type",
+    summary: "chunk type codes consist of ASCII letters",
+    spec: "PNG spec 5.3, Chunk layout",
+    rationale: "an invalid type code means the chunk stream is misaligned or corrupt",
+    fix: None,
+};
+
+pub static CRC: Rule = Rule {
+    id: "crc",
+    summary: "chunk CRC must match its type and data",
+    spec: "PNG spec 5.3, Chunk layout",
+    rationale:
+        "a CRC mismatch means the chunk was corrupted; strict decoders refuse the whole file",
+    fix: None,
+};
+
+pub static IHDR_FIRST: Rule = Rule {
+    id: "ihdr-first",
+    summary: "IHDR must be the first chunk",
+    spec: "PNG spec 5.6, Chunk ordering",
+    rationale: "decoders need the image dimensions and color type before any other chunk",
+    fix: None,
+};
+
+pub static IEND_LAST: Rule = Rule {
+    id: "iend-last",
+    summary: "IEND must be the last chunk",
+    spec: "PNG spec 5.6, Chunk ordering",
+    rationale: "decoders stop at IEND, so any chunk after it is silently ignored",
+    fix: Some("pngme repair --structure {file}"),
+};
+
+pub static PLTE_BEFORE_IDAT: Rule = Rule {
+    id: "plte-before-idat",
+    summary: "PLTE must come before the first IDAT",
+    spec: "PNG spec 5.6, Chunk ordering",
+    rationale: "indexed pixels cannot be decoded until the palette is known",
+    fix: None,
+};
+
+pub static IDAT_CONSECUTIVE: Rule = Rule {
+    id: "idat-consecutive",
+    summary: "IDAT chunks must be consecutive",
+    spec: "PNG spec 5.6, Chunk ordering",
+    rationale: "streaming decoders treat the first non-IDAT chunk as the end of the image data",
+    fix: None,
+};
+
+pub static SINGLE_INSTANCE: Rule = Rule {
+    id: "single-instance",
+    summary: "this chunk may appear at most once",
+    spec: "PNG spec 5.6, Chunk ordering",
+    rationale: "decoders disagree on which copy wins, so the image may render differently",
+    fix: Some("pngme remove {file} --offset {offset}"),
+};
+
+pub static UNSAFE_TO_COPY: Rule = Rule {
+    id: "unsafe-to-copy",
+    summary: "unknown unsafe-to-copy chunks must be dropped when critical data changes",
+    spec: "PNG spec 14.2, Behaviour of PNG editors",
+    rationale: "the chunk may describe image data that no longer exists",
+    fix: Some("pngme remove {file} {type}"),
+};
+
+pub static BEFORE_PLTE: Rule = Rule {
+    id: "before-plte",
+    summary: "cHRM, cICP, gAMA, iCCP, sBIT and sRGB must come before PLTE and IDAT",
+    spec: "PNG spec 5.6, Chunk ordering",
+    rationale:
+        "color space information has to be known before the palette and pixels are interpreted",
+    fix: Some("pngme lint {file} --fix"),
+};
+
+pub static AFTER_PLTE: Rule = Rule {
+    id: "after-plte",
+    summary: "bKGD, hIST and tRNS must come after PLTE and before IDAT",
+    spec: "PNG spec 5.6, Chunk ordering",
+    rationale: "these chunks index into the palette, so it must already be known",
+    fix: Some("pngme lint {file} --fix"),
+};
+
+pub static BEFORE_IDAT: Rule = Rule {
+    id: "before-idat",
+    summary: "pHYs, sPLT, eXIf and acTL must come before the first IDAT",
+    spec: "PNG spec 5.6, Chunk ordering",
+    rationale: "streaming decoders apply them while decoding pixels and may ignore them afterwards",
+    fix: Some("pngme lint {file} --fix"),
+};
+
+pub static ICCP_SRGB: Rule = Rule {
+    id: "iccp-srgb",
+    summary: "iCCP and sRGB must not both be present",
+    spec: "PNG spec 11.3.3.5, sRGB Standard RGB colour space",
+    rationale: "the two describe conflicting color spaces and decoders pick one arbitrarily",
+    fix: Some("pngme lint {file} --fix"),
+};
+
+pub static HIST_NEEDS_PLTE: Rule = Rule {
+    id: "hist-needs-plte",
+    summary: "hIST is only allowed when PLTE is present",
+    spec: "PNG spec 11.3.5.2, hIST Image histogram",
+    rationale: "a histogram without a palette has nothing to describe",
+    fix: Some("pngme lint {file} --fix"),
+};
+
+pub static KEYWORD: Rule = Rule {
+    id: "keyword",
+    summary: "text keywords are 1-79 printable Latin-1 characters with no leading, trailing or consecutive spaces",
+    spec: "PNG spec 11.3.4.2, Keywords and text strings",
+    rationale: "decoders may reject or misread text chunks with malformed keywords",
+    fix: None,
+};
+
+pub static REGISTERED_KEYWORD: Rule = Rule {
+    id: "registered-keyword",
+    summary: "text keywords should be one of the predefined keywords",
+    spec: "PNG spec 11.3.4.2, Keywords and text strings",
+    rationale: "viewers only know what the predefined keywords mean; other keywords are legal but may be ignored",
+    fix: None,
+};
+
+pub static METADATA_RATIO: Rule = Rule {
+    id: "metadata-ratio",
+    summary: "ancillary chunks should not make up most of the file",
+    spec: "pngme policy set with --max-metadata-percent, not a PNG spec rule",
+    rationale: "a blob embedded by mistake bloats every download of the image without changing how it looks",
+    fix: Some("pngme remove {file} --offset {offset}"),
+};
+
+/// Every rule, for listing.
+pub static RULES: &[&Rule] = &[
+    &SIGNATURE,
+    &TRUNCATED,
+    &CHUNK_TYPE,
+    &CRC,
+    &IHDR_FIRST,
+    &IEND_LAST,
+    &PLTE_BEFORE_IDAT,
+    &IDAT_CONSECUTIVE,
+    &SINGLE_INSTANCE,
+    &UNSAFE_TO_COPY,
+    &BEFORE_PLTE,
+    &AFTER_PLTE,
+    &BEFORE_IDAT,
+    &ICCP_SRGB,
+    &HIST_NEEDS_PLTE,
+    &KEYWORD,
+    &REGISTERED_KEYWORD,
+    &METADATA_RATIO,
+];
+
+pub fn rule_by_id(id: &str) -> Option<&'static Rule> {
+    RULES.iter().copied().find(|rule| rule.id == id)
+}
+
+/// Chunks the spec allows at most once per file.
+pub const SINGLE_INSTANCE_TYPES: &[&[u8; 4]] = &[
+    b"IHDR", b"PLTE", b"IEND", b"cHRM", b"cICP", b"gAMA", b"iCCP", b"sBIT", b"sRGB", b"bKGD",
+    b"hIST", b"tRNS", b"eXIf", b"pHYs", b"tIME", b"acTL",
+];
+
+/// Structural problems that do not stop the file from being parsed:
+/// misplaced critical chunks and repeated single-instance chunks.
+pub fn warnings(png: &Png) -> Vec<Warning> {
+    let chunks: Vec<(usize, [u8; 4])> = png
+        .chunks_with_offsets()
+        .map(|(offset, chunk)| (offset, chunk.chunk_type().bytes()))
+        .collect();
+    warnings_for_chunks(&chunks)
+}
+
+/// `warnings` for a file with chunks of these types at these offsets, in
+/// file order.
+pub fn warnings_for_chunks(chunks: &[(usize, [u8; 4])]) -> Vec<Warning> {
+    let types: Vec<[u8; 4]> = chunks.iter().map(|&(_, chunk_type)| chunk_type).collect();
+    let mut warnings = vec![];
+    let out_of_order = |index: usize, rule| Warning::OutOfOrder {
+        chunk_type: String::from_utf8_lossy(&types[index]).into_owned(),
+        index,
+        rule,
+    };
+
+    if let Some(index) = types.iter().position(|t| t == b"IHDR") {
+        if index != 0 {
+            warnings.push(out_of_order(index, &IHDR_FIRST));
+        }
+    }
+    if let Some(index) = types.iter().position(|t| t == b"IEND") {
+        if index != types.len() - 1 {
+            warnings.push(out_of_order(index, &IEND_LAST));
+        }
+    }
+
+    let first_idat = types.iter().position(|t| t == b"IDAT");
+    if let Some(first_idat) = first_idat {
+        if let Some(index) = types.iter().position(|t| t == b"PLTE") {
+            if index > first_idat {
+                warnings.push(out_of_order(index, &PLTE_BEFORE_IDAT));
+            }
+        }
+
+        let last_idat = types.iter().rposition(|t| t == b"IDAT").unwrap();
+        if let Some(offset) = types[first_idat..last_idat]
+            .iter()
+            .position(|t| t != b"IDAT")
+        {
+            warnings.push(out_of_order(first_idat + offset, &IDAT_CONSECUTIVE));
+        }
+    }
+
+    // The first copy is the one decoders are most likely to use, so the
+    // later ones are reported.
+    for (index, (offset, chunk_type)) in chunks.iter().enumerate() {
+        if SINGLE_INSTANCE_TYPES.contains(&chunk_type) && types[..index].contains(chunk_type) {
+            warnings.push(Warning::Duplicate {
+                chunk_type: String::from_utf8_lossy(chunk_type).into_owned(),
+                index,
+                offset: *offset,
+            });
+        }
+    }
+
+    warnings
+}
+
+/// A warning when ancillary chunks, including their length, type and CRC
+/// fields, make up more than `max_percent` of the file.
+pub fn metadata_ratio_warning(png: &Png, max_percent: u8) -> Option<Warning> {
+    let size = |chunk: &Chunk| 12 + chunk.length() as u64;
+    let total = Png::STANDARD_HEADER.len() as u64 + png.chunks().iter().map(size).sum::<u64>();
+    let ancillary = || {
+        png.chunks()
+            .iter()
+            .filter(|chunk| !chunk.chunk_type().is_critical())
+    };
+    let metadata: u64 = ancillary().map(size).sum();
+    if metadata * 100 <= total * max_percent as u64 {
+        return None;
+    }
+    // Removing the largest chunk does the most to bring the ratio down.
+    let (offset, largest) = png
+        .chunks_with_offsets()
+        .filter(|(_, chunk)| !chunk.chunk_type().is_critical())
+        .max_by_key(|(_, chunk)| chunk.length())?;
+    Some(Warning::MetadataRatio {
+        metadata,
+        total,
+        max_percent,
+        largest: largest.chunk_type().to_string(),
+        offset,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_rule_ids_are_unique() {
+        for rule in RULES {
+            assert_eq!(rule_by_id(rule.id), Some(*rule));
+        }
+        assert_eq!(rule_by_id("no-such-rule"), None);
+    }
+
+    #[test]
+    fn test_fix_for() {
+        assert_eq!(
+            UNSAFE_TO_COPY.fix_for("a.png", Some("prVt")).as_deref(),
+            Some("pngme remove a.png prVt")
+        );
+        assert_eq!(
+            SINGLE_INSTANCE.fix_for("a.png", Some("tIME")).as_deref(),
+            Some("pngme remove a.png --offset <offset>")
+        );
+        assert_eq!(CRC.fix_for("a.png", None), None);
+    }
+
+    #[test]
+    fn test_warning_rules() {
+        let warning = Warning::Duplicate {
+            chunk_type: "tIME".to_string(),
+            index: 3,
+            offset: 90,
+        };
+        assert_eq!(warning.rule(), &SINGLE_INSTANCE);
+        assert_eq!(warning.chunk_type(), "tIME");
+        assert_eq!(
+            warning
+                .rule()
+                .fix_at("a.png", Some(warning.chunk_type()), warning.offset()),
+            Some("pngme remove a.png --offset 90".to_string())
+        );
+    }
+
+    #[test]
+    fn test_metadata_ratio() {
+        let chunk = |chunk_type: &str, length: usize| {
+            Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![0; length])
+        };
+        // 8 + 25 + 22 + 112 + 15 + 12 = 194 bytes, 37 of them in tEXt and ruSt.
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", 13),
+            chunk("tEXt", 10),
+            chunk("IDAT", 100),
+            chunk("ruSt", 3),
+            chunk("IEND", 0),
+        ]);
+        assert_eq!(metadata_ratio_warning(&png, 20), None);
+
+        let warning = metadata_ratio_warning(&png, 19).unwrap();
+        assert_eq!(
+            warning.to_string(),
+            "ancillary chunks are 37 of 194 bytes (19.1%), over the 19% limit"
+        );
+        assert_eq!(warning.chunk_type(), "tEXt");
+        assert_eq!(warning.offset(), Some(33));
+        assert_eq!(warning.rule(), &METADATA_RATIO);
+        assert_eq!(
+            METADATA_RATIO
+                .fix_at("a.png", Some("tEXt"), warning.offset())
+                .as_deref(),
+            Some("pngme remove a.png --offset 33")
+        );
+    }
+}
@@ -0,0 +1,220 @@
+//! Borrowed, read-only views of a PNG held in memory.
+//!
+//! [`Png`] and [`Chunk`] own their bytes so that they can be edited. Code
+//! that only reads a file, such as `decode` or a service inspecting uploads,
+//! can instead parse the buffer it already has into a [`PngRef`], whose
+//! [`ChunkRef`]s point into that buffer: parsing copies no chunk data, and
+//! payloads are handed out as slices of the original input. Call
+//! [`PngRef::to_png`] to get an owned copy to modify.
+
+use crate::chunk::{Chunk, CrcProfile};
+use crate::chunk_type::ChunkType;
+use crate::limits::Limits;
+use crate::metrics::{self, Phase};
+use crate::png::{ChunkWalker, ParseError, Png};
+use crate::validation;
+use crate::warning::Warning;
+use crate::Result;
+use std::io::Read;
+use std::str::FromStr;
+
+/// One chunk inside a borrowed buffer: its length, type, data and CRC
+/// fields. The type is always valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkRef<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ChunkRef<'a> {
+    /// `bytes` must be one whole chunk with a valid type.
+    pub(crate) fn from_raw(bytes: &'a [u8]) -> ChunkRef<'a> {
+        ChunkRef { bytes }
+    }
+
+    pub fn length(&self) -> u32 {
+        u32::from_be_bytes(self.bytes[0..4].try_into().unwrap())
+    }
+
+    pub fn chunk_type(&self) -> ChunkType {
+        let bytes: [u8; 4] = self.bytes[4..8].try_into().unwrap();
+        ChunkType::try_from(bytes).expect("Invalid chunk type")
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        &self.bytes[8..self.bytes.len() - 4]
+    }
+
+    pub fn data_reader(&self) -> impl Read + 'a {
+        self.data()
+    }
+
+    /// The standard CRC of the chunk, which is what pngme would write for it.
+    pub fn crc(&self) -> u32 {
+        Chunk::crc_of(&self.bytes[4..self.bytes.len() - 4])
+    }
+
+    /// The whole chunk as stored.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// An owned copy, with a standard CRC.
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(self.chunk_type(), self.data().to_vec())
+    }
+}
+
+/// The chunks of a PNG in a borrowed buffer, in file order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PngRef<'a> {
+    chunks: Vec<ChunkRef<'a>>,
+}
+
+impl<'a> PngRef<'a> {
+    /// Parses `bytes` with the same checks as `Png::read_with_crc_profile`.
+    pub fn parse(bytes: &'a [u8], profile: &CrcProfile, limits: &Limits) -> Result<PngRef<'a>> {
+        match PngRef::parse_partial(bytes, profile, limits)? {
+            (_, Some(offset)) => Err(ParseError::Truncated { offset })?,
+            (png, None) => Ok(png),
+        }
+    }
+
+    /// Like `parse`, but a file cut off mid-chunk yields the complete chunks
+    /// before the cut and its offset, as with `Png::read_partial`.
+    pub fn parse_partial(
+        bytes: &'a [u8],
+        profile: &CrcProfile,
+        limits: &Limits,
+    ) -> Result<(PngRef<'a>, Option<usize>)> {
+        metrics::time(Phase::Parse, || {
+            if !bytes.starts_with(&Png::STANDARD_HEADER) {
+                return Err(ParseError::InvalidSignature)?;
+            }
+            let mut walker = ChunkWalker::new(profile, limits);
+            let mut chunks = vec![];
+            loop {
+                match walker.next_in(bytes) {
+                    Ok(Some(chunk)) => {
+                        walker.accept(chunk)?;
+                        chunks.push(ChunkRef { bytes: chunk });
+                    }
+                    Ok(None) => return Ok((PngRef { chunks }, None)),
+                    Err(e) => match e.downcast_ref::<ParseError>() {
+                        Some(&ParseError::Truncated { offset }) => {
+                            return Ok((PngRef { chunks }, Some(offset)))
+                        }
+                        _ => return Err(e),
+                    },
+                }
+            }
+        })
+    }
+
+    pub fn from_chunks(chunks: Vec<ChunkRef<'a>>) -> PngRef<'a> {
+        PngRef { chunks }
+    }
+
+    pub fn chunks(&self) -> &[ChunkRef<'a>] {
+        &self.chunks
+    }
+
+    /// Each chunk with the file offset of its length field.
+    pub fn chunks_with_offsets(&self) -> impl Iterator<Item = (usize, ChunkRef<'a>)> + '_ {
+        self.chunks
+            .iter()
+            .scan(Png::STANDARD_HEADER.len(), |offset, chunk| {
+                let start = *offset;
+                *offset += chunk.bytes.len();
+                Some((start, *chunk))
+            })
+    }
+
+    pub fn chunks_of_type(&self, chunk_type: ChunkType) -> impl Iterator<Item = ChunkRef<'a>> + '_ {
+        self.chunks
+            .iter()
+            .copied()
+            .filter(move |chunk| chunk.chunk_type() == chunk_type)
+    }
+
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<ChunkRef<'a>> {
+        let chunk_type = ChunkType::from_str(chunk_type).ok()?;
+        self.chunks_of_type(chunk_type).next()
+    }
+
+    /// The same warnings `Png::warnings` reports for the file.
+    pub fn warnings(&self) -> Vec<Warning> {
+        let chunks: Vec<(usize, [u8; 4])> = self
+            .chunks_with_offsets()
+            .map(|(offset, chunk)| (offset, chunk.chunk_type().bytes()))
+            .collect();
+        validation::warnings_for_chunks(&chunks)
+    }
+
+    /// An owned copy to edit.
+    pub fn to_png(&self) -> Png {
+        Png::from_chunks(self.chunks.iter().map(ChunkRef::to_chunk).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_png() -> Png {
+        let chunk = |chunk_type: &str, data: &str| {
+            Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.into())
+        };
+        Png::from_chunks(vec![
+            chunk("IHDR", "not really a header"),
+            chunk("ruSt", "a payload"),
+            chunk("IEND", ""),
+        ])
+    }
+
+    #[test]
+    fn test_parse_borrows_from_input() {
+        let png = testing_png();
+        let bytes = png.as_bytes();
+        let view = PngRef::parse(&bytes, &CrcProfile::Standard, &Limits::default()).unwrap();
+        assert_eq!(view, png.view());
+
+        let payload = view.chunk_by_type("ruSt").unwrap().data();
+        assert_eq!(payload, b"a payload");
+        assert!(bytes.as_ptr_range().contains(&payload.as_ptr()));
+
+        let offsets: Vec<usize> = view.chunks_with_offsets().map(|(o, _)| o).collect();
+        let expected: Vec<usize> = png.chunks_with_offsets().map(|(o, _)| o).collect();
+        assert_eq!(offsets, expected);
+        assert_eq!(view.to_png().as_bytes(), bytes);
+        assert_eq!(view.warnings(), png.warnings());
+    }
+
+    #[test]
+    fn test_parse_rejects_what_the_reader_rejects() {
+        let bytes = testing_png().as_bytes();
+        let parse = |bytes: &[u8]| {
+            PngRef::parse(bytes, &CrcProfile::Standard, &Limits::default()).map(|_| ())
+        };
+        let read = |bytes: &[u8]| {
+            Png::read_with_crc_profile(bytes, &CrcProfile::Standard, &Limits::default()).map(|_| ())
+        };
+
+        let mut corrupt = bytes.clone();
+        corrupt[8 + 12 + 19 + 8] ^= 1;
+        for input in [&bytes[1..], &bytes[..bytes.len() - 2], &corrupt[..]] {
+            assert_eq!(
+                parse(input).unwrap_err().to_string(),
+                read(input).unwrap_err().to_string()
+            );
+        }
+
+        let (view, truncated_at) = PngRef::parse_partial(
+            &bytes[..bytes.len() - 2],
+            &CrcProfile::Standard,
+            &Limits::default(),
+        )
+        .unwrap();
+        assert_eq!(view.chunks().len(), 2);
+        assert_eq!(truncated_at, Some(bytes.len() - 12));
+    }
+}
@@ -0,0 +1,133 @@
+use crate::validation::{self, Rule};
+use std::fmt::Display;
+
+/// A non-fatal problem found while reading or editing a PNG. Operations that
+/// can produce warnings return them next to their result instead of printing
+/// them, so callers decide whether to ignore, report or reject them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// A chunk sits somewhere the spec does not allow.
+    OutOfOrder {
+        chunk_type: String,
+        index: usize,
+        rule: &'static Rule,
+    },
+    /// A chunk is not allowed given the other chunks in the file.
+    Conflict {
+        chunk_type: String,
+        index: usize,
+        rule: &'static Rule,
+    },
+    /// A chunk that may appear at most once appears again.
+    Duplicate {
+        chunk_type: String,
+        index: usize,
+        offset: usize,
+    },
+    /// An unknown unsafe-to-copy chunk was kept after critical data changed.
+    UnsafeToCopy { chunk_type: String },
+    /// A text chunk uses a keyword the spec does not predefine.
+    UnregisteredKeyword { chunk_type: String, keyword: String },
+    /// Ancillary chunks take up more of the file than allowed; `largest` is
+    /// the type of the biggest one and `offset` where it starts.
+    MetadataRatio {
+        metadata: u64,
+        total: u64,
+        max_percent: u8,
+        largest: String,
+        offset: usize,
+    },
+}
+
+impl Warning {
+    /// The validation rule the warning reports.
+    pub fn rule(&self) -> &'static Rule {
+        match self {
+            Warning::OutOfOrder { rule, .. } | Warning::Conflict { rule, .. } => rule,
+            Warning::Duplicate { .. } => &validation::SINGLE_INSTANCE,
+            Warning::UnsafeToCopy { .. } => &validation::UNSAFE_TO_COPY,
+            Warning::UnregisteredKeyword { .. } => &validation::REGISTERED_KEYWORD,
+            Warning::MetadataRatio { .. } => &validation::METADATA_RATIO,
+        }
+    }
+
+    /// The file offset of the chunk to act on, for fixes that name one.
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            Warning::Duplicate { offset, .. } | Warning::MetadataRatio { offset, .. } => {
+                Some(*offset)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn chunk_type(&self) -> &str {
+        match self {
+            Warning::OutOfOrder { chunk_type, .. }
+            | Warning::Conflict { chunk_type, .. }
+            | Warning::Duplicate { chunk_type, .. }
+            | Warning::UnsafeToCopy { chunk_type }
+            | Warning::UnregisteredKeyword { chunk_type, .. }
+            | Warning::MetadataRatio {
+                largest: chunk_type,
+                ..
+            } => chunk_type,
+        }
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Warning::OutOfOrder {
+                chunk_type,
+                index,
+                rule,
+            } => write!(
+                f,
+                "chunk {} ({}) is out of order: {}",
+                index, chunk_type, rule.summary
+            ),
+            Warning::Conflict {
+                chunk_type,
+                index,
+                rule,
+            } => write!(
+                f,
+                "chunk {} ({}) is not allowed: {}",
+                index, chunk_type, rule.summary
+            ),
+            Warning::Duplicate {
+                chunk_type, index, ..
+            } => {
+                write!(f, "chunk {} is a duplicate {} chunk", index, chunk_type)
+            }
+            Warning::UnsafeToCopy { chunk_type } => write!(
+                f,
+                "unsafe-to-copy chunk {} kept after modifying critical data",
+                chunk_type
+            ),
+            Warning::UnregisteredKeyword {
+                chunk_type,
+                keyword,
+            } => write!(
+                f,
+                "{} keyword {:?} is not a predefined keyword",
+                chunk_type, keyword
+            ),
+            Warning::MetadataRatio {
+                metadata,
+                total,
+                max_percent,
+                ..
+            } => write!(
+                f,
+                "ancillary chunks are {} of {} bytes ({:.1}%), over the {}% limit",
+                metadata,
+                total,
+                *metadata as f64 * 100.0 / *total as f64,
+                max_percent
+            ),
+        }
+    }
+}
@@ -0,0 +1,636 @@
+//! A small zlib (RFC 1950) codec on top of DEFLATE (RFC 1951).
+//!
+//! Decompression understands every block type. Compression finds matches with
+//! hash chains and emits a single block with the fixed Huffman codes, which
+//! keeps the encoder short while still shrinking repetitive payloads well.
+
+use crate::i18n::{self, Lang};
+use crate::metrics::{self, Phase};
+use crate::Result;
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 128;
+const HASH_BITS: u32 = 15;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    compress_with_dictionary(data, None)
+}
+
+/// Compresses `data`, letting matches refer back into `dictionary` as if it
+/// had been sent first. The dictionary's Adler-32 is recorded as the stream's
+/// DICTID, and the same dictionary must be handed to the decompressor.
+pub fn compress_with_dictionary(data: &[u8], dictionary: Option<&[u8]>) -> Vec<u8> {
+    metrics::time(Phase::Compression, || compress_stream(data, dictionary))
+}
+
+fn compress_stream(data: &[u8], dictionary: Option<&[u8]>) -> Vec<u8> {
+    let mut writer = BitWriter::default();
+
+    let cmf = 0x78u8;
+    let mut flg = 0x80u8 | if dictionary.is_some() { 0x20 } else { 0 };
+    flg += 31 - ((cmf as u16 * 256 + flg as u16) % 31) as u8;
+    writer.bytes.extend_from_slice(&[cmf, flg]);
+    if let Some(dictionary) = dictionary {
+        writer
+            .bytes
+            .extend_from_slice(&adler32(dictionary).to_be_bytes());
+    }
+
+    let dictionary = dictionary.unwrap_or_default();
+    let dictionary = &dictionary[dictionary.len().saturating_sub(WINDOW_SIZE)..];
+    let input: Vec<u8> = dictionary.iter().chain(data).copied().collect();
+
+    // BFINAL = 1, BTYPE = 01 (fixed Huffman codes)
+    writer.put_bits(1, 1);
+    writer.put_bits(1, 2);
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+    let insert = |pos: usize, head: &mut Vec<usize>, prev: &mut Vec<usize>| {
+        if pos + MIN_MATCH <= input.len() {
+            let hash = hash3(&input[pos..]);
+            prev[pos % WINDOW_SIZE] = head[hash];
+            head[hash] = pos;
+        }
+    };
+
+    for pos in 0..dictionary.len() {
+        insert(pos, &mut head, &mut prev);
+    }
+
+    let mut pos = dictionary.len();
+    while pos < input.len() {
+        let (length, distance) = longest_match(&input, pos, &head, &prev);
+        if length >= MIN_MATCH {
+            writer.put_length(length);
+            writer.put_distance(distance);
+            for p in pos..pos + length {
+                insert(p, &mut head, &mut prev);
+            }
+            pos += length;
+        } else {
+            writer.put_literal(input[pos] as u16);
+            insert(pos, &mut head, &mut prev);
+            pos += 1;
+        }
+    }
+    writer.put_literal(256);
+
+    let mut bytes = writer.finish();
+    bytes.extend_from_slice(&adler32(data).to_be_bytes());
+    bytes
+}
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    decompress_with_dictionary(data, None)
+}
+
+pub fn decompress_with_dictionary(data: &[u8], dictionary: Option<&[u8]>) -> Result<Vec<u8>> {
+    decompress_limited_with_dictionary(data, dictionary, usize::MAX)
+}
+
+/// Like [`decompress`], but fails with [`OutputLimit`] as soon as the output
+/// grows past `max_out` bytes, so a small crafted stream cannot make pngme
+/// allocate gigabytes.
+pub fn decompress_limited(data: &[u8], max_out: usize) -> Result<Vec<u8>> {
+    decompress_limited_with_dictionary(data, None, max_out)
+}
+
+pub fn decompress_limited_with_dictionary(
+    data: &[u8],
+    dictionary: Option<&[u8]>,
+    max_out: usize,
+) -> Result<Vec<u8>> {
+    metrics::time(Phase::Compression, || {
+        decompress_stream(data, dictionary, max_out)
+    })
+}
+
+/// The error of a stream that decompresses to more than the allowed size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputLimit {
+    pub limit: usize,
+}
+
+impl std::fmt::Display for OutputLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let args = [self.limit.to_string()];
+        write!(f, "{}", i18n::OUTPUT_LIMIT.format(Lang::En, &args))
+    }
+}
+
+impl std::error::Error for OutputLimit {}
+
+fn decompress_stream(data: &[u8], dictionary: Option<&[u8]>, max_out: usize) -> Result<Vec<u8>> {
+    if data.len() < 6 {
+        return Err("zlib stream is too short".into());
+    }
+    let (cmf, flg) = (data[0], data[1]);
+    if cmf & 0x0f != 8 || cmf >> 4 > 7 || !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) {
+        return Err("invalid zlib header".into());
+    }
+
+    let mut start = 2;
+    let mut output = vec![];
+    if flg & 0x20 != 0 {
+        let dictionary = dictionary.ok_or("zlib stream needs a preset dictionary")?;
+        let mut id = [0u8; 4];
+        id.copy_from_slice(data.get(2..6).ok_or("zlib stream is too short")?);
+        if u32::from_be_bytes(id) != adler32(dictionary) {
+            return Err("zlib stream was compressed with a different dictionary".into());
+        }
+        output.extend_from_slice(dictionary);
+        start = 6;
+    }
+    let prefix = output.len();
+
+    let mut reader = BitReader::new(&data[start..]);
+    let limit = Limit {
+        max_len: prefix.saturating_add(max_out),
+        max_out,
+    };
+    inflate(&mut reader, &mut output, limit)?;
+    let output = output.split_off(prefix);
+
+    let trailer = reader
+        .remaining_bytes()
+        .get(..4)
+        .ok_or("zlib stream is missing its checksum")?;
+    let mut checksum = [0u8; 4];
+    checksum.copy_from_slice(trailer);
+    if u32::from_be_bytes(checksum) != adler32(&output) {
+        return Err("zlib checksum mismatch".into());
+    }
+
+    Ok(output)
+}
+
+fn hash3(bytes: &[u8]) -> usize {
+    let value = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+    (value.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+fn longest_match(input: &[u8], pos: usize, head: &[usize], prev: &[usize]) -> (usize, usize) {
+    if pos + MIN_MATCH > input.len() {
+        return (0, 0);
+    }
+
+    let max_length = MAX_MATCH.min(input.len() - pos);
+    let (mut best_length, mut best_distance) = (0, 0);
+    let mut candidate = head[hash3(&input[pos..])];
+    for _ in 0..MAX_CHAIN {
+        if candidate == usize::MAX || candidate >= pos || pos - candidate > WINDOW_SIZE {
+            break;
+        }
+        let length = input[candidate..]
+            .iter()
+            .zip(&input[pos..pos + max_length])
+            .take_while(|(a, b)| a == b)
+            .count();
+        if length > best_length {
+            best_length = length;
+            best_distance = pos - candidate;
+            if length == max_length {
+                break;
+            }
+        }
+        let next = prev[candidate % WINDOW_SIZE];
+        if next == usize::MAX || next >= candidate {
+            break;
+        }
+        candidate = next;
+    }
+
+    (best_length, best_distance)
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn put_bits(&mut self, value: u32, count: u32) {
+        self.bit_buffer |= value << self.bit_count;
+        self.bit_count += count;
+        while self.bit_count >= 8 {
+            self.bytes.push(self.bit_buffer as u8);
+            self.bit_buffer >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    /// Huffman codes are packed starting from their most significant bit.
+    fn put_code(&mut self, code: u32, length: u32) {
+        let reversed = code.reverse_bits() >> (32 - length);
+        self.put_bits(reversed, length);
+    }
+
+    fn put_literal(&mut self, symbol: u16) {
+        let symbol = symbol as u32;
+        match symbol {
+            0..=143 => self.put_code(0x30 + symbol, 8),
+            144..=255 => self.put_code(0x190 + symbol - 144, 9),
+            256..=279 => self.put_code(symbol - 256, 7),
+            _ => self.put_code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    fn put_length(&mut self, length: usize) {
+        let index = LENGTH_BASE
+            .iter()
+            .rposition(|&base| base as usize <= length)
+            .expect("match length is at least 3");
+        self.put_literal(257 + index as u16);
+        self.put_bits(
+            (length - LENGTH_BASE[index] as usize) as u32,
+            LENGTH_EXTRA[index] as u32,
+        );
+    }
+
+    fn put_distance(&mut self, distance: usize) {
+        let index = DIST_BASE
+            .iter()
+            .rposition(|&base| base as usize <= distance)
+            .expect("match distance is at least 1");
+        self.put_code(index as u32, 5);
+        self.put_bits(
+            (distance - DIST_BASE[index] as usize) as u32,
+            DIST_EXTRA[index] as u32,
+        );
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.bytes.push(self.bit_buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data,
+            position: 0,
+            bit_buffer: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32> {
+        while self.bit_count < count {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or("unexpected end of deflate stream")?;
+            self.position += 1;
+            self.bit_buffer |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buffer & ((1u64 << count) - 1) as u32;
+        self.bit_buffer >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+    }
+
+    fn remaining_bytes(&self) -> &'a [u8] {
+        &self.data[self.position..]
+    }
+
+    fn decode(&mut self, huffman: &Huffman) -> Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..=15 {
+            code |= self.bits(1)? as i32;
+            let count = huffman.counts[length] as i32;
+            if code - first < count {
+                return Ok(huffman.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code in deflate stream")?
+    }
+}
+
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Huffman { counts, symbols }
+    }
+
+    fn fixed() -> (Huffman, Huffman) {
+        let mut lengths = [0u8; 288];
+        lengths[..144].fill(8);
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        lengths[280..].fill(8);
+        (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+    }
+}
+
+/// How long the output buffer may grow, counting a preset dictionary in
+/// front of it, and the limit as the caller gave it.
+#[derive(Clone, Copy)]
+struct Limit {
+    max_len: usize,
+    max_out: usize,
+}
+
+impl Limit {
+    fn check(&self, len: usize) -> Result<()> {
+        if len > self.max_len {
+            Err(OutputLimit {
+                limit: self.max_out,
+            })?
+        }
+        Ok(())
+    }
+}
+
+fn inflate(reader: &mut BitReader, output: &mut Vec<u8>, limit: Limit) -> Result<()> {
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => inflate_stored(reader, output, limit)?,
+            1 => {
+                let (literals, distances) = Huffman::fixed();
+                inflate_block(reader, output, &literals, &distances, limit)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_tables(reader)?;
+                inflate_block(reader, output, &literals, &distances, limit)?;
+            }
+            _ => return Err("invalid deflate block type")?,
+        }
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+fn inflate_stored(reader: &mut BitReader, output: &mut Vec<u8>, limit: Limit) -> Result<()> {
+    reader.align_to_byte();
+    let header = reader
+        .remaining_bytes()
+        .get(..4)
+        .ok_or("unexpected end of deflate stream")?;
+    let length = u16::from_le_bytes([header[0], header[1]]);
+    let complement = u16::from_le_bytes([header[2], header[3]]);
+    if length != !complement {
+        return Err("corrupt stored block length in deflate stream".into());
+    }
+    reader.position += 4;
+
+    let bytes = reader
+        .remaining_bytes()
+        .get(..length as usize)
+        .ok_or("unexpected end of deflate stream")?;
+    limit.check(output.len() + bytes.len())?;
+    output.extend_from_slice(bytes);
+    reader.position += length as usize;
+    Ok(())
+}
+
+fn read_dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_length_huffman = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![];
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match reader.decode(&code_length_huffman)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or("repeat code with no previous length in deflate stream")?;
+                (previous, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() != literal_count + distance_count {
+        return Err("code lengths overflow in deflate stream".into());
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+    limit: Limit,
+) -> Result<()> {
+    loop {
+        let symbol = reader.decode(literals)? as usize;
+        match symbol {
+            0..=255 => {
+                limit.check(output.len() + 1)?;
+                output.push(symbol as u8);
+            }
+            256 => return Ok(()),
+            257..=285 => {
+                let index = symbol - 257;
+                let length =
+                    LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+
+                let index = reader.decode(distances)? as usize;
+                if index >= DIST_BASE.len() {
+                    return Err("invalid distance code in deflate stream".into());
+                }
+                let distance =
+                    DIST_BASE[index] as usize + reader.bits(DIST_EXTRA[index] as u32)? as usize;
+                if distance > output.len() {
+                    return Err("distance too far back in deflate stream".into());
+                }
+
+                limit.check(output.len() + length)?;
+                let start = output.len() - distance;
+                for i in 0..length {
+                    output.push(output[start + i]);
+                }
+            }
+            _ => return Err("invalid literal/length code in deflate stream")?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_text() -> Vec<u8> {
+        "The quick brown fox jumps over the lazy dog. "
+            .repeat(50)
+            .into_bytes()
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+
+    #[test]
+    fn test_decompress_reference_stream() {
+        // zlib.compress(b"hello") from the reference implementation
+        let stream = [
+            0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00, 0x06, 0x2c, 0x02, 0x15,
+        ];
+        assert_eq!(decompress(&stream).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_decompress_stored_block() {
+        let stream = [
+            0x78, 0x01, 0x01, 0x05, 0x00, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o', 0x06, 0x2c,
+            0x02, 0x15,
+        ];
+        assert_eq!(decompress(&stream).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for data in [vec![], b"a".to_vec(), sample_text(), (0..=255).collect()] {
+            assert_eq!(decompress(&compress(&data)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_compress_shrinks_repetitive_data() {
+        let data = sample_text();
+        assert!(compress(&data).len() < data.len() / 10);
+    }
+
+    #[test]
+    fn test_dictionary_round_trip() {
+        let dictionary = b"{\"version\": \"\", \"name\": \"\"}".to_vec();
+        let data = b"{\"version\": \"1.2.3\", \"name\": \"pngme\"}";
+
+        let with = compress_with_dictionary(data, Some(&dictionary));
+        assert!(with.len() < compress(data).len());
+        assert_eq!(
+            decompress_with_dictionary(&with, Some(&dictionary)).unwrap(),
+            data
+        );
+        assert!(decompress(&with).is_err());
+        assert!(decompress_with_dictionary(&with, Some(b"other")).is_err());
+    }
+
+    #[test]
+    fn test_decompress_limited() {
+        let data = sample_text();
+        let compressed = compress(&data);
+        assert_eq!(decompress_limited(&compressed, data.len()).unwrap(), data);
+
+        let error = decompress_limited(&compressed, data.len() - 1).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<OutputLimit>(),
+            Some(&OutputLimit {
+                limit: data.len() - 1
+            })
+        );
+
+        // A dictionary in front of the output does not count towards it.
+        let dictionary = &data[..50];
+        let compressed = compress_with_dictionary(&data, Some(dictionary));
+        assert_eq!(
+            decompress_limited_with_dictionary(&compressed, Some(dictionary), data.len()).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn test_corrupt_stream() {
+        let mut stream = compress(&sample_text());
+        let last = stream.len() - 1;
+        stream[last] ^= 0xff;
+        assert!(decompress(&stream).is_err());
+        assert!(decompress(&stream[..stream.len() / 2]).is_err());
+    }
+}
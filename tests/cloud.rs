@@ -0,0 +1,139 @@
+//! Commands on s3:// objects, run against a fake `aws` tool put first on
+//! PATH, which keeps objects as files in a temporary directory.
+#![cfg(all(feature = "cloud", unix))]
+
+use pngme::chunk::Chunk;
+use pngme::chunk_type::ChunkType;
+use pngme::png::Png;
+use pngme::zlib;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::str::FromStr;
+
+/// Handles `s3 cp --no-progress SRC DST` and `s3 ls --recursive URL` on the
+/// files under $FAKE_S3. Downloading `noisy.png` first prints far more to
+/// stderr than a pipe buffer holds.
+const FAKE_AWS: &str = r#"#!/bin/sh
+[ "$1" = s3 ] || exit 2
+case "$2" in
+cp)
+    if [ "$4" = - ]; then
+        file="$FAKE_S3/${5#s3://}"
+        mkdir -p "$(dirname "$file")" && cat > "$file"
+    else
+        file="$FAKE_S3/${4#s3://}"
+        if [ ! -f "$file" ]; then
+            echo "An error occurred (404) when calling the HeadObject operation: Not Found" >&2
+            exit 1
+        fi
+        case "$file" in
+        */noisy.png) i=0; while [ $i -lt 2000 ]; do
+            echo "progress line $i padded to make the output long enough" >&2
+            i=$((i + 1))
+        done ;;
+        esac
+        cat "$file"
+    fi ;;
+ls)
+    prefix="${4#s3://}"
+    bucket="${prefix%%/*}"
+    (cd "$FAKE_S3/$bucket" && find . -type f | sed 's|^\./||') | while read -r key; do
+        case "$key" in
+        "${prefix#*/}"*) echo "2024-06-01 12:00:00 $(wc -c < "$FAKE_S3/$bucket/$key") $key" ;;
+        esac
+    done ;;
+*) exit 2 ;;
+esac
+"#;
+
+struct FakeS3 {
+    root: PathBuf,
+}
+
+impl FakeS3 {
+    fn new(name: &str) -> FakeS3 {
+        let root = std::env::temp_dir().join(format!("pngme-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("bin")).unwrap();
+        fs::create_dir_all(root.join("store/assets")).unwrap();
+        let aws = root.join("bin/aws");
+        fs::write(&aws, FAKE_AWS).unwrap();
+        fs::set_permissions(&aws, fs::Permissions::from_mode(0o755)).unwrap();
+        FakeS3 { root }
+    }
+
+    fn object(&self, key: &str) -> PathBuf {
+        self.root.join("store/assets").join(key)
+    }
+
+    fn pngme(&self, args: &[&str]) -> Output {
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        let mut paths = vec![self.root.join("bin")];
+        paths.extend(std::env::split_paths(&path));
+        Command::new(env!("CARGO_BIN_EXE_pngme"))
+            .args(args)
+            .env("PATH", std::env::join_paths(paths).unwrap())
+            .env("FAKE_S3", self.root.join("store"))
+            .output()
+            .unwrap()
+    }
+}
+
+impl Drop for FakeS3 {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+fn write_png(path: &Path) {
+    let chunk = |chunk_type: &str, data: Vec<u8>| {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    };
+    let mut ihdr = 1u32.to_be_bytes().repeat(2);
+    ihdr.extend_from_slice(&[8, 0, 0, 0, 0]);
+    let png = Png::from_chunks(vec![
+        chunk("IHDR", ihdr),
+        chunk("IDAT", zlib::compress(&[0, 0])),
+        chunk("IEND", vec![]),
+    ]);
+    fs::write(path, png.as_bytes()).unwrap();
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn test_commands_on_objects() {
+    let s3 = FakeS3::new("cloud-commands");
+    write_png(&s3.object("a.png"));
+
+    stdout(&s3.pngme(&["encode", "s3://assets/a.png", "ruSt", "hello"]));
+    assert!(stdout(&s3.pngme(&["decode", "s3://assets/a.png", "ruSt"])).contains("hello"));
+    assert!(stdout(&s3.pngme(&["scan", "s3://assets/a.png"])).contains("ruSt"));
+
+    fs::copy(s3.object("a.png"), s3.object("noisy.png")).unwrap();
+    stdout(&s3.pngme(&["verify", "s3://assets/"]));
+    assert!(stdout(&s3.pngme(&["decode", "s3://assets/noisy.png", "ruSt"])).contains("hello"));
+}
+
+#[test]
+fn test_missing_object() {
+    let s3 = FakeS3::new("cloud-missing");
+    let output = s3.pngme(&["decode", "s3://assets/gone.png", "ruSt"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("reading s3://assets/gone.png"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("Not Found"), "{}", stderr);
+}
@@ -0,0 +1,32 @@
+//! Exit codes of `decode --exists`, which shell conditionals rely on.
+
+use pngme::chunk::Chunk;
+use pngme::chunk_type::ChunkType;
+use pngme::png::Png;
+use std::fs;
+use std::process::Command;
+use std::str::FromStr;
+
+fn exit_code(args: &[&str]) -> Option<i32> {
+    let output = Command::new(env!("CARGO_BIN_EXE_pngme"))
+        .args(args)
+        .output()
+        .unwrap();
+    output.status.code()
+}
+
+#[test]
+fn test_exit_codes() {
+    let path = std::env::temp_dir().join(format!("pngme-exists-{}.png", std::process::id()));
+    let png = Png::from_chunks(vec![
+        Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hello".to_vec()),
+        Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+    ]);
+    fs::write(&path, png.as_bytes()).unwrap();
+    let file = path.to_str().unwrap();
+
+    assert_eq!(exit_code(&["decode", file, "ruSt", "--exists"]), Some(0));
+    assert_eq!(exit_code(&["decode", file, "teXt", "--exists"]), Some(1));
+    fs::remove_file(&path).unwrap();
+    assert_eq!(exit_code(&["decode", file, "ruSt", "--exists"]), Some(2));
+}